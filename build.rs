@@ -0,0 +1,22 @@
+/*!
+Records some information about how `cargo-eval` was built, for `cargo eval version --verbose`.
+*/
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_owned())
+        .unwrap_or_else(|| "unknown".into());
+
+    println!("cargo:rustc-env=CARGO_EVAL_RUSTC_VERSION={}", rustc_version);
+    println!(
+        "cargo:rustc-env=CARGO_EVAL_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+}
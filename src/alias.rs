@@ -0,0 +1,177 @@
+/*!
+This module deals with aliases: short names for scripts that have already been built, so they can be run with `cargo eval @name`.
+
+`--alias NAME` copies the script's executable into the `aliases` folder in the data directory, and records where it came from in `aliases.json` next to it.  Running `@NAME` then runs that copy directly: the script isn't looked at again, so it doesn't matter if it's moved, changed or cleaned out of the cache in the meantime.  To pick up changes to the script, run it with `--alias` again.
+
+The copy is made, rather than pointing at the executable in the cache, because executables in the shared binary cache get overwritten by any other script with the same name.
+*/
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app;
+use crate::error::{Blame, MainError, Result, ResultExt};
+
+/**
+Name of the file aliases are recorded in, in the aliases folder.
+*/
+const REGISTRY_FILE: &str = "aliases.json";
+
+#[derive(Debug)]
+pub enum Args {
+    List,
+    Remove { name: String },
+}
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{AppSettings, Arg, SubCommand};
+
+        SubCommand::with_name("alias")
+            .about("Manage the aliases made with `--alias`.")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about("List the aliases, and the scripts they were made from."),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("Remove an alias.")
+                    .arg(
+                        Arg::with_name("name")
+                            .help("Name of the alias to remove.")
+                            .index(1)
+                            .required(true),
+                    ),
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        match m.subcommand() {
+            ("list", _) => Args::List,
+            ("remove", Some(m)) => Args::Remove {
+                name: m.value_of("name").unwrap().into(),
+            },
+            (name, _) => panic!("bad subcommand: {:?}", name),
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    match args {
+        Args::List => {
+            for (name, alias) in load_registry()? {
+                println!("@{}: {}", name, alias.script);
+            }
+        }
+        Args::Remove { name } => {
+            let mut registry = load_registry()?;
+            if registry.remove(&name).is_none() {
+                return Err(unknown_alias(&name));
+            }
+            let exe_path = exe_path(&name);
+            if exe_path.exists() {
+                fs::remove_file(&exe_path)?;
+            }
+            save_registry(&registry)?;
+        }
+    }
+
+    Ok(0)
+}
+
+/**
+What's recorded about each alias.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Alias {
+    /// Where the alias came from, for `alias list`.
+    pub script: String,
+
+    /// The environment variables the script was run with, which it might depend on.
+    pub env: Vec<(String, String)>,
+}
+
+/**
+Makes (or replaces) the alias `name`, for the executable at `exe`.
+*/
+pub fn save(name: &str, exe: &Path, alias: Alias) -> Result<()> {
+    let dest = exe_path(name);
+    info!("copying {:?} to {:?}", exe, dest);
+    fs::create_dir_all(dest.parent().unwrap())?;
+    fs::copy(exe, &dest)
+        .map_err(MainError::from)
+        .err_tag(format!("could not save alias `{}`", name))?;
+
+    let mut registry = load_registry()?;
+    registry.insert(name.into(), alias);
+    save_registry(&registry)
+}
+
+/**
+Runs the alias `name` with the given arguments, and extra environment variables.  Returns the exit code to use.
+*/
+pub fn run(name: &str, args: &[String], env: &[(String, String)]) -> Result<i32> {
+    let registry = load_registry()?;
+    let alias = registry.get(name).ok_or_else(|| unknown_alias(name))?;
+    let exe_path = exe_path(name);
+    if !exe_path.is_file() {
+        return Err((
+            Blame::Human,
+            format!(
+                "the executable for alias `{}` is missing; run `{}` with `--alias {}` again",
+                name, alias.script, name
+            ),
+        )
+            .into());
+    }
+
+    info!("executing {:?}", exe_path);
+    let mut cmd = Command::new(&exe_path);
+    cmd.args(args);
+    for (key, value) in alias.env.iter().chain(env) {
+        cmd.env(key, value);
+    }
+    Ok(cmd.status()?.code().unwrap_or(1))
+}
+
+fn unknown_alias(name: &str) -> MainError {
+    (
+        Blame::Human,
+        format!(
+            "there's no alias called `{}`; see `cargo eval alias list`",
+            name
+        ),
+    )
+        .into()
+}
+
+fn aliases_dir() -> PathBuf {
+    app::data_dir().join("aliases")
+}
+
+fn exe_path(name: &str) -> PathBuf {
+    aliases_dir().join(format!("{}{}", name, std::env::consts::EXE_SUFFIX))
+}
+
+fn load_registry() -> Result<BTreeMap<String, Alias>> {
+    let path = aliases_dir().join(REGISTRY_FILE);
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|err| {
+            format!("could not read aliases from `{}`: {}", path.display(), err).into()
+        }),
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn save_registry(registry: &BTreeMap<String, Alias>) -> Result<()> {
+    let path = aliases_dir().join(REGISTRY_FILE);
+    fs::create_dir_all(path.parent().unwrap())?;
+    let text = serde_json::to_string_pretty(registry).map_err(|err| err.to_string())?;
+    fs::write(&path, text)?;
+    Ok(())
+}
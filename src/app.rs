@@ -1,14 +1,24 @@
 use std::env;
 use std::path::PathBuf;
+use std::sync::Once;
+use std::time::Duration;
 
 use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
 
+use crate::alias;
+use crate::batch;
+use crate::cache_key::HashAlgorithm;
+use crate::completions;
+use crate::new;
+use crate::repl;
 use crate::templates;
+use crate::timings;
+use crate::version;
 
 const NAME: &str = "cargo-eval";
 
 #[inline(always)]
-const fn name() -> &'static str {
+pub const fn name() -> &'static str {
     NAME
 }
 
@@ -17,19 +27,68 @@ fn subcommand_name() -> &'static str {
     &name()[6..]
 }
 
-pub fn data_dir() -> Option<PathBuf> {
-    Some(dirs::data_local_dir()?.join(name()))
+pub fn data_dir() -> PathBuf {
+    static WARNED: Once = Once::new();
+    if let Ok(path) = env::var("CARGO_EVAL_DATA_DIR") {
+        return path.into();
+    }
+
+    dir_or_fallback(
+        dirs::data_local_dir(),
+        "data",
+        "CARGO_EVAL_DATA_DIR",
+        &WARNED,
+    )
 }
 
-pub fn cache_dir() -> Option<PathBuf> {
-    Some(dirs::cache_dir()?.join(name()))
+pub fn cache_dir() -> PathBuf {
+    static WARNED: Once = Once::new();
+    if let Ok(path) = env::var("CARGO_EVAL_CACHE_DIR") {
+        return path.into();
+    }
+
+    dir_or_fallback(dirs::cache_dir(), "cache", "CARGO_EVAL_CACHE_DIR", &WARNED)
 }
 
-fn app() -> App<'static, 'static> {
+/**
+Works out where to put our `kind` directory, given where the platform says that kind of thing goes.
+
+Some systems (*e.g.* a service account without a home directory) don't have anywhere; then we make do with the temporary directory, and say so (once) on stderr.
+*/
+fn dir_or_fallback(platform_dir: Option<PathBuf>, kind: &str, var: &str, warned: &Once) -> PathBuf {
+    // So the tests can pretend to be on such a system.
+    let platform_dir =
+        if cfg!(debug_assertions) && env::var_os("CARGO_EVAL_TEST_NO_PLATFORM_DIRS").is_some() {
+            None
+        } else {
+            platform_dir
+        };
+
+    match platform_dir {
+        Some(dir) => dir.join(name()),
+        None => {
+            let dir = env::temp_dir().join(format!("{}-{}", name(), kind));
+            warned.call_once(|| {
+                eprintln!(
+                    "warning: could not find a {} directory, so using `{}`; set `{}` to use somewhere else",
+                    kind,
+                    dir.display(),
+                    var
+                )
+            });
+            dir
+        }
+    }
+}
+
+pub fn app() -> App<'static, 'static> {
     let mut app = SubCommand::with_name(subcommand_name())
     .version(env!("CARGO_PKG_VERSION"))
     .about("Compiles and runs “Cargoified Rust scripts”.")
     .usage("cargo eval [FLAGS OPTIONS] [--] <script> <args>...")
+    .setting(AppSettings::SubcommandsNegateReqs)
+    // Otherwise, `cargo eval script.rs batch` would run `batch`, instead of passing it to the script.
+    .setting(AppSettings::ArgsNegateSubcommands)
 
     /*
     Major script modes.
@@ -37,10 +96,10 @@ fn app() -> App<'static, 'static> {
     .arg(Arg::with_name("script")
         .help("Script file (with or without extension) to execute.")
         .index(1)
-        .required_unless("clear_cache")
+        .required_unless_one(&["clear_cache", "print_cache_dir", "print_data_dir", "print_rustc_wrapper"])
     )
     .arg(Arg::with_name("args")
-        .help("Additional arguments passed to the script.")
+        .help("Additional arguments passed to the script.  For expressions, these are available as `args: Vec<String>`.")
         .index(2)
         .multiple(true)
     )
@@ -56,6 +115,17 @@ fn app() -> App<'static, 'static> {
         .short("l")
         .requires("script")
     )
+    .arg(Arg::with_name("remote")
+        .help("Fetch <script> from an `http://` or `https://` URL, and run it.  The host must be trusted, with --allow-remote or in a project configuration file.")
+        .long("remote")
+        .requires("script")
+        .conflicts_with_all(&["expr", "loop"])
+    )
+    .arg(Arg::with_name("allow_remote")
+        .help("Trust the host a --remote script is fetched from.")
+        .long("allow-remote")
+        .requires("remote")
+    )
     .group(ArgGroup::with_name("expr_or_loop")
         .args(&["expr", "loop"])
     )
@@ -68,13 +138,240 @@ fn app() -> App<'static, 'static> {
         .long("count")
         .requires("loop")
     )
+    .arg(Arg::with_name("input_encoding")
+        .help("Decode each line of input from the given encoding, like `latin1` or `shift_jis`, rather than expecting UTF-8.  Only encodings which are compatible with ASCII will work properly.")
+        .long("input-encoding")
+        .takes_value(true)
+        .value_name("ENCODING")
+        .requires("loop")
+    )
+    .arg(Arg::with_name("parallel")
+        .help("Run the loop closure on N threads at once.  The output still comes out in the same order as the input.")
+        .long("parallel")
+        .takes_value(true)
+        .value_name("N")
+        .requires("loop")
+        .conflicts_with_all(&["input_encoding", "template_variant"])
+        .validator(|s| match s.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(()),
+            _ => Err(format!("`{}` is not a valid number of threads", s)),
+        })
+    )
+    .arg(Arg::with_name("assert")
+        .help("Treat the expression as an assertion: it must evaluate to a `bool`, and if it's `false`, the expression is printed and cargo eval exits with a non-zero status.")
+        .long("assert")
+        .requires("expr")
+        .conflicts_with_all(&["color_output", "radix", "template"])
+    )
+    .arg(Arg::with_name("unwrap")
+        .help("Unwrap an `Option` or `Result` expression, displaying what's inside.  If it's `None` or `Err`, say so and exit with a non-zero status.")
+        .long("unwrap")
+        .requires("expr")
+        .conflicts_with_all(&["assert", "color_output", "template", "template_variant"])
+    )
+    .arg(Arg::with_name("lines")
+        .help("Iterate over the result of the expression, and display each item on its own line.")
+        .long("lines")
+        .requires("expr")
+        .conflicts_with_all(&["assert", "unwrap", "color_output", "template", "template_variant"])
+    )
+    .arg(Arg::with_name("json")
+        .help("Display the result of the expression as pretty-printed JSON.  It has to implement `serde::Serialize`.")
+        .long("json")
+        .requires("expr")
+        .conflicts_with_all(&["assert", "unwrap", "lines", "color_output", "radix", "template", "template_variant"])
+    )
+    .arg(Arg::with_name("join")
+        .help("Iterate over the result of the expression, and display the items on one line, separated by SEP.  The items are displayed with `{}`, so they have to implement `Display`.")
+        .long("join")
+        .takes_value(true)
+        .value_name("SEP")
+        .requires("expr")
+        .conflicts_with_all(&["assert", "unwrap", "lines", "json", "color_output", "radix", "template", "template_variant"])
+    )
+    .arg(Arg::with_name("sep")
+        .help("With --lines, separate the items with the given string instead of a newline.")
+        .long("sep")
+        .takes_value(true)
+        .value_name("SEPARATOR")
+        .requires("lines")
+    )
+    .arg(Arg::with_name("async_runtime")
+        .help("Treat the expression as a future, and await it with the given runtime, which is added as a dependency.")
+        .long("async-runtime")
+        .takes_value(true)
+        .value_name("RUNTIME")
+        .possible_values(&["tokio", "async-std", "smol"])
+        .requires("expr")
+    )
+    .arg(Arg::with_name("time")
+        .help("Measure how long the expression takes to evaluate, and print it to stderr.")
+        .long("time")
+        .requires("expr")
+    )
+    .arg(Arg::with_name("fetch")
+        .help("Fetch the given URL, and make the response available to the expression as `body: String`.")
+        .long("fetch")
+        .takes_value(true)
+        .value_name("URL")
+        .requires("expr")
+    )
+    .arg(Arg::with_name("with_time")
+        .help("Make `now()` available to the expression, returning the current local date and time as a `chrono::DateTime`.")
+        .long("with-time")
+        .requires("expr")
+    )
+    .arg(Arg::with_name("with_uom")
+        .help("Make the `uom` crate's SI quantities (`Length`, `Mass`, ...) and unit modules (`length`, `mass`, ...) available to the expression, for converting between units.")
+        .long("with-uom")
+        .requires("expr")
+    )
+    .arg(Arg::with_name("file")
+        .help("Read the given file, and make its contents available to the expression as `contents: String`.  If given more than once, `contents` is a `Vec<String>`, in the same order.")
+        .long("file")
+        .takes_value(true)
+        .value_name("PATH")
+        .multiple(true)
+        .number_of_values(1)
+        .requires("expr")
+    )
+    .arg(Arg::with_name("fields")
+        .help("Split standard input on SEP, and make the pieces available to the expression as `fields: Vec<String>`.  A trailing newline is ignored.")
+        .long("fields")
+        .takes_value(true)
+        .value_name("SEP")
+        .requires("expr")
+        .conflicts_with_all(&["template", "no_stdin"])
+        .validator(|s| if s.is_empty() {
+            Err("the separator can't be empty".into())
+        } else {
+            Ok(())
+        })
+    )
+    .arg(Arg::with_name("stdin_bytes")
+        .help("Read all of standard input, and make it available to the expression as `input: Vec<u8>`.  It doesn't have to be UTF-8.")
+        .long("stdin-bytes")
+        .requires("expr")
+        .conflicts_with_all(&["fields", "no_stdin"])
+    )
+    .arg(Arg::with_name("state")
+        .help("Make the contents of the file at PATH available to the expression as `prev: Option<String>` (`None` if there's no such file), and write the result back to it afterwards.  The result is written with `{}`, so it has to implement `Display`.")
+        .long("state")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("expr")
+        .conflicts_with_all(&["unwrap", "lines", "join"])
+    )
+    .arg(Arg::with_name("color_output")
+        .help("Pretty-print the result of an expression, in colour if standard output is a terminal.")
+        .long("color-output")
+        .requires("expr")
+        .conflicts_with_all(&["radix", "template"])
+    )
+    .arg(Arg::with_name("smart_display")
+        .help("Display a string result as it is, without quotes or escapes.  Anything else is still displayed with `{:?}`.")
+        .long("smart-display")
+        .requires("expr")
+        .conflicts_with_all(&["assert", "unwrap", "lines", "join", "json", "color_output", "radix", "thousands", "template"])
+    )
+    .arg(Arg::with_name("thousands")
+        .help("Display the result of an integer expression with commas between the thousands, e.g. `1,000,000`.")
+        .long("thousands")
+        .requires("expr")
+        .conflicts_with_all(&["assert", "unwrap", "lines", "join", "json", "color_output", "radix", "template"])
+    )
+    .arg(Arg::with_name("radix")
+        .help("Display the result of an integer expression in hexadecimal, octal or binary.")
+        .long("radix")
+        .takes_value(true)
+        .possible_values(&["hex", "oct", "bin"])
+        .requires("expr")
+    )
+    .arg(Arg::with_name("to_stderr")
+        .help("Display the result of the expression, or the loop closure, on standard error instead of standard output.")
+        .long("to-stderr")
+        .requires("expr_or_loop")
+    )
+    .arg(Arg::with_name("verbose")
+        .help("Show more of what's going on, both from cargo-eval and from Cargo.  Give it twice for even more.")
+        .long("verbose")
+        .short("v")
+        .multiple(true)
+    )
     .arg(Arg::with_name("debug")
         .help("Build a debug executable, not an optimised one.")
         .long("debug")
         .requires("script")
     )
+    .arg(Arg::with_name("profile")
+        .help("Build with the named Cargo profile, rather than `release`.  Custom profiles can be defined in the script's manifest, or with --manifest-override.")
+        .long("profile")
+        .takes_value(true)
+        .value_name("NAME")
+        .requires("script")
+        .conflicts_with_all(&["debug", "release", "test", "bench"])
+    )
+    .arg(Arg::with_name("release")
+        .help("Build an optimised executable.  This is the default anyway; it's accepted so Cargo habits don't trip you up.")
+        .long("release")
+        .short("r")
+        .requires("script")
+        .conflicts_with("debug")
+    )
+    .arg(Arg::with_name("panic")
+        .help("Set the panic strategy.  `abort` gives smaller executables, but panics won't unwind, so destructors won't run.")
+        .long("panic")
+        .takes_value(true)
+        .possible_values(&["unwind", "abort"])
+        .requires("script")
+        .conflicts_with_all(&["test", "bench"])
+    )
+    .arg(Arg::with_name("strip")
+        .help("Strip debug info, or all symbols, from the release executable, to make it smaller.")
+        .long("strip")
+        .takes_value(true)
+        .possible_values(&["none", "debuginfo", "symbols"])
+        .requires("script")
+        .conflicts_with("debug")
+    )
+    .arg(Arg::with_name("codegen_units")
+        .help("Split each crate into at most N codegen units.  1 gives the most consistent (and usually fastest) code, at the cost of slower builds.")
+        .long("codegen-units")
+        .takes_value(true)
+        .value_name("N")
+        .requires("script")
+        .validator(|s| match s.parse::<u32>() {
+            Ok(n) if n > 0 => Ok(()),
+            _ => Err(format!("`{}` is not a valid number of codegen units", s)),
+        })
+    )
+    .arg(Arg::with_name("max_compile_time")
+        .help("Give up on building the script if it takes longer than SECS seconds.")
+        .long("max-compile-time")
+        .takes_value(true)
+        .value_name("SECS")
+        .requires("script")
+        .validator(|s| match s.parse::<f64>() {
+            // Anything too big for a `Duration` isn't a limit worth having.
+            Ok(n) if n > 0.0 && Duration::try_from_secs_f64(n).is_ok() => Ok(()),
+            _ => Err(format!("`{}` is not a valid number of seconds", s)),
+        })
+    )
+    .arg(Arg::with_name("retry")
+        .help("If fetching the script's dependencies fails with what looks like a network problem, try again, up to N more times, waiting longer each time.")
+        .long("retry")
+        .takes_value(true)
+        .value_name("N")
+        .requires("script")
+        .validator(|s| s.parse::<u32>().map(|_| ()).map_err(|_| format!("`{}` is not a valid number of retries", s)))
+    )
+    .arg(Arg::with_name("no_color_errors")
+        .help("Remove colours (ANSI escape sequences) from Cargo's output, when it's been held back and then shown because the build failed or took a while.  This is the default when stderr isn't a terminal.")
+        .long("no-color-errors")
+        .requires("script")
+    )
     .arg(Arg::with_name("dep")
-        .help("Add an additional Cargo dependency.  Each SPEC can be either just the package name (which will assume the latest version) or a full `name=version` spec.")
+        .help("Add an additional Cargo dependency.  Each SPEC can be either just the package name (which will assume the latest version) or a full `name=version` spec, optionally followed by `+feature,...` to turn on features.")
         .long("dep")
         .short("d")
         .takes_value(true)
@@ -82,11 +379,124 @@ fn app() -> App<'static, 'static> {
         .number_of_values(1)
         .requires("script")
     )
+    .arg(Arg::with_name("deps_file")
+        .help("Add the dependencies listed in a file, one SPEC per line, as if each were given with --dep.  Blank lines, and anything after a `#`, are ignored.")
+        .long("deps-file")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("script")
+    )
+    .arg(Arg::with_name("dep_target")
+        .help("Add a Cargo dependency which is only used on some platforms.  TARGET is a `cfg(...)` expression or a target triple, and SPEC is the same as for --dep, e.g. `cfg(windows)=winapi=0.3`.")
+        .long("dep-target")
+        .takes_value(true)
+        .value_name("TARGET=SPEC")
+        .multiple(true)
+        .number_of_values(1)
+        .requires("script")
+    )
+    .arg(Arg::with_name("lockfile")
+        .help("Pin dependencies given without a version to the versions recorded in an existing `Cargo.lock`.  Dependencies it doesn't list are resolved as usual.")
+        .long("lockfile")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("script")
+    )
+    .arg(Arg::with_name("preprocessor")
+        .help("Pipe the script through the given command before compiling it, and use its output as the source.")
+        .long("preprocessor")
+        .takes_value(true)
+        .value_name("COMMAND")
+        .requires("script")
+    )
+    .arg(Arg::with_name("build_script")
+        .help("Copy the given build script into the generated package, and use it.")
+        .long("build-script")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("script")
+    )
+    .arg(Arg::with_name("include")
+        .help("Copy an extra source file into the generated package, alongside the script, so the script can use it with `mod NAME;`, where NAME is the file's stem.  May be given more than once.")
+        .long("include")
+        .takes_value(true)
+        .value_name("PATH")
+        .multiple(true)
+        .number_of_values(1)
+        .requires("script")
+    )
+    .arg(Arg::with_name("raw")
+        .help("Compile the script file exactly as written, without putting it through the `file` template.")
+        .long("raw")
+        .requires("script")
+        .conflicts_with_all(&["expr", "loop"])
+    )
+    .arg(Arg::with_name("panic_location")
+        .help("Report panics at the script's own path and line, as well as at the copy of it that was compiled.  The script has to have exactly one `main`, which isn't `async`.")
+        .long("panic-location")
+        .requires("script")
+        .conflicts_with_all(&["expr", "loop", "raw"])
+    )
+    .arg(Arg::with_name("verify_hash")
+        .help("Refuse to run the script unless its SHA-256 hash matches the one given.")
+        .long("verify-hash")
+        .takes_value(true)
+        .value_name("SHA256")
+        .requires("script")
+        .conflicts_with_all(&["expr", "loop"])
+        .validator(|s| {
+            if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+                Ok(())
+            } else {
+                Err(format!("`{}` is not a SHA-256 hash", s))
+            }
+        })
+    )
+    .arg(Arg::with_name("manifest_override")
+        .help("Merge the given TOML into the generated Cargo manifest, over everything else.  Use `@PATH` to read it from a file instead.")
+        .long("manifest-override")
+        .takes_value(true)
+        .value_name("TOML")
+        .requires("script")
+    )
+    .arg(Arg::with_name("smart_deps")
+        .help("Turn on the features most people want for certain common dependencies, like `full` for `tokio` and `blocking` for `reqwest`.  Giving a dependency as a full table overrides this.")
+        .long("smart-deps")
+        .requires("script")
+    )
     .arg(Arg::with_name("features")
          .help("Cargo features to pass when building and running.")
          .long("features")
          .takes_value(true)
     )
+    .arg(Arg::with_name("rustflags")
+        .help("Additional flags to pass to `rustc` when building the script.  Split into words like a shell would.  Overrides the `RUSTFLAGS` environment variable.")
+        .long("rustflags")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .allow_hyphen_values(true)
+        .requires("script")
+    )
+    .arg(Arg::with_name("rustc_wrapper")
+        .help("Build the script with `RUSTC_WRAPPER` set to the given program, such as `sccache`, to cache compiled dependencies between builds.  Doesn't change what's built, so it doesn't cause a rebuild.")
+        .long("rustc-wrapper")
+        .takes_value(true)
+        .value_name("PATH")
+    )
+    .arg(Arg::with_name("reproducible")
+        .help("Build the script so the executable doesn't depend on where it was built: paths in the cache and Cargo's home directory are remapped, and `SOURCE_DATE_EPOCH` is set.  Overrides the `RUSTFLAGS` environment variable.")
+        .long("reproducible")
+        .requires("script")
+    )
+    .arg(Arg::with_name("sanitizer")
+        .help("Build the script with the given sanitizer, to catch memory errors or data races.  Needs a nightly toolchain.  Overrides the `RUSTFLAGS` environment variable.")
+        .long("sanitizer")
+        .takes_value(true)
+        .value_name("SANITIZER")
+        .possible_values(&["address", "thread", "leak", "memory"])
+        .requires("script")
+    )
     .arg(Arg::with_name("unstable_features")
         .help("Add a #![feature] declaration to the crate.")
         .long("unstable-feature")
@@ -102,6 +512,7 @@ fn app() -> App<'static, 'static> {
     .arg(Arg::with_name("build_only")
         .help("Build the script, but don't run it.")
         .long("build-only")
+        .visible_alias("no-run")
         .requires("script")
         .conflicts_with_all(&["args"])
     )
@@ -109,6 +520,64 @@ fn app() -> App<'static, 'static> {
         .help("Clears out the script cache.")
         .long("clear-cache")
     )
+    .arg(Arg::with_name("clean")
+        .help("Remove the cached package and executable for <script>, then exit.")
+        .long("clean")
+        .requires("script")
+        .conflicts_with_all(&["args", "build_only", "clear_cache", "force", "gen_pkg_only", "pkg_path", "test", "bench"])
+    )
+    .arg(Arg::with_name("touch")
+        .help("Mark the cached package for <script> as just used, without building or running it, so it isn't the next thing cleaned out of the cache, then exit.")
+        .long("touch")
+        .requires("script")
+        .conflicts_with_all(&["args", "build_only", "clean", "clear_cache", "force", "gen_pkg_only", "pkg_path", "test", "bench"])
+    )
+    .arg(Arg::with_name("print_cache_dir")
+        .help("Print the path to the cache directory and exit.")
+        .long("print-cache-dir")
+        .conflicts_with_all(&["script", "clear_cache", "print_data_dir"])
+    )
+    .arg(Arg::with_name("print_data_dir")
+        .help("Print the path to the data directory and exit.")
+        .long("print-data-dir")
+        .conflicts_with_all(&["script", "clear_cache", "print_cache_dir"])
+    )
+    .arg(Arg::with_name("print_rustc_wrapper")
+        .help("Print the `RUSTC_WRAPPER` scripts would be built with, if any, and exit.")
+        .long("print-rustc-wrapper")
+        .conflicts_with_all(&["script", "clear_cache", "print_cache_dir", "print_data_dir"])
+    )
+    .arg(Arg::with_name("print_deps")
+        .help("Print the script's dependency tree, as reported by `cargo tree`, to standard error.")
+        .long("print-deps")
+        .requires("script")
+    )
+    .arg(Arg::with_name("print_features")
+        .help("Print the features enabled for each crate the script depends on, as worked out by `cargo metadata`.")
+        .long("print-features")
+        .requires("script")
+    )
+    .arg(Arg::with_name("print_binary_size")
+        .help("Print the size of the compiled executable to standard error.")
+        .long("print-binary-size")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("warn_unused_deps")
+        .help("Warn about dependencies which the script doesn't appear to use.  This is a best guess, based on looking for the crate names in the source.")
+        .long("warn-unused-deps")
+    )
+    .arg(Arg::with_name("max_cache_entries")
+        .help("After building a script into the cache, remove the least recently used cache entries so that at most N remain.")
+        .long("max-cache-entries")
+        .takes_value(true)
+        .value_name("N")
+        // Zero would leave no room for the script that's about to run.
+        .validator(|s| match s.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(()),
+            _ => Err(format!("`{}` is not a valid number of entries", s)),
+        })
+    )
     .arg(Arg::with_name("force")
         .help("Force the script to be rebuilt.")
         .long("force")
@@ -120,6 +589,61 @@ fn app() -> App<'static, 'static> {
         .requires("script")
         .conflicts_with_all(&["args", "build_only", "debug", "force", "test", "bench"])
     )
+    .arg(Arg::with_name("emit_runner")
+        .help("With --gen-pkg-only, also write a `run.sh` (or `run.bat` on Windows) into the package, which builds and runs it the same way cargo eval would.")
+        .long("emit-runner")
+        .requires("gen_pkg_only")
+    )
+    .arg(Arg::with_name("emit_binary")
+        .help("Copy the compiled executable to the given path, so it can be used without cargo eval.")
+        .long("emit-binary")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("after_build")
+        .help("Run the given shell command once the script has been built, with the path to the executable in `CARGO_EVAL_BIN`, e.g. to copy or sign it.  The script only runs if the command succeeds.")
+        .long("after-build")
+        .takes_value(true)
+        .value_name("CMD")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("alias")
+        .help("Save the compiled executable under NAME, so it can be run again later with `cargo eval @NAME`.  See `cargo eval alias`.")
+        .long("alias")
+        .takes_value(true)
+        .value_name("NAME")
+        .validator(is_alias_name)
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "gen_pkg_only", "crate_type"])
+    )
+    .arg(Arg::with_name("crate_type")
+        .help("Build the script as a library of the given crate type, and print the path to it, instead of running it.")
+        .long("crate-type")
+        .takes_value(true)
+        .value_name("TYPE")
+        .possible_values(&["bin", "lib", "cdylib", "staticlib"])
+        .requires("script")
+        .conflicts_with_all(&["expr", "loop", "args", "test", "bench"])
+    )
+    .arg(Arg::with_name("emit")
+        .help("Have rustc write the script's assembly, LLVM IR or MIR to a file in the package, and print its path.  Implies --build-only.")
+        .long("emit")
+        .takes_value(true)
+        .value_name("KIND")
+        .possible_values(&["asm", "llvm-ir", "mir"])
+        .requires("script")
+        .conflicts_with_all(&["args", "test", "bench", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("emit_tarball")
+        .help("Write the generated package to the given path as a `.tar.gz`.  Combine with --gen-pkg-only to skip building it.")
+        .long("emit-tarball")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("script")
+    )
     .arg(Arg::with_name("pkg_path")
         .help("Specify where to place the generated Cargo package.")
         .long("pkg-path")
@@ -127,17 +651,131 @@ fn app() -> App<'static, 'static> {
         .requires("script")
         .conflicts_with_all(&["clear_cache", "force"])
     )
+    .arg(Arg::with_name("pkg_name")
+        .help("Set the name of the generated Cargo package, instead of deriving it from the script.  The executable keeps its usual name.")
+        .long("pkg-name")
+        .takes_value(true)
+        .value_name("NAME")
+        .requires("script")
+        .validator(is_package_name)
+    )
+    .arg(Arg::with_name("rust_version")
+        .help("Set the minimum Rust version needed to build the script, as `rust-version` in the generated manifest.  The script won't be built with an older `rustc`.")
+        .long("rust-version")
+        .takes_value(true)
+        .value_name("VERSION")
+        .requires("script")
+        .validator(|s| match crate::util::parse_rust_version(&s) {
+            Some(_) => Ok(()),
+            None => Err(format!("`{}` is not a valid Rust version", s)),
+        })
+    )
     .arg(Arg::with_name("use_bincache")
         .help("Override whether or not the shared binary cache will be used for compilation.")
         .long("use-shared-binary-cache")
         .takes_value(true)
         .possible_values(&["no", "yes"])
     )
+    .arg(Arg::with_name("isolated")
+        .help("Build the script in a target directory of its own, rather than the shared binary cache, so it can't get mixed up with other scripts.  Takes precedence over --use-shared-binary-cache.")
+        .long("isolated")
+        .requires("script")
+    )
+    .arg(Arg::with_name("hash")
+        .help("Hash algorithm to make the script's cache key with.  Defaults to `sha1`.  Switching to another one means scripts get rebuilt under new keys.")
+        .long("hash")
+        .takes_value(true)
+        .value_name("ALGORITHM")
+        .possible_values(HashAlgorithm::NAMES)
+        .requires("script")
+    )
+    .arg(Arg::with_name("rebuild_on")
+        .help("How to decide whether a cached script needs rebuilding: `hash` compares the content of the script against the cache, `mtime` only compares the script file's modification time.  `mtime` is faster, but won't notice changes that leave the modification time alone.")
+        .long("rebuild-on")
+        .takes_value(true)
+        .possible_values(&["hash", "mtime"])
+        .requires("script")
+    )
+    .arg(Arg::with_name("log_timings")
+        .help("Append a line to `timings.log` in the data directory, saying how long the script took to build.  See `cargo eval timings`.")
+        .long("log-timings")
+        .requires("script")
+    )
+    .arg(Arg::with_name("why_rebuild")
+        .help("If the script has to be rebuilt, say why, such as which of its inputs changed since it was last built.")
+        .long("why-rebuild")
+        .requires("script")
+    )
+    .arg(Arg::with_name("sandbox")
+        .help("Run the script with an empty environment, and (on Linux) prevent it from writing outside a private temporary directory or using the network.")
+        .long("sandbox")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "build_only", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("keep_temps")
+        .help("Don't clean up throwaway directories: the generated package when a build fails, and the --sandbox temporary directory.  Their locations are printed instead.")
+        .long("keep-temps")
+        .requires("script")
+    )
+    .arg(Arg::with_name("clean_env")
+        .help("Run the script with an empty environment, other than `PATH` and anything given with `--env`.")
+        .long("clean-env")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "build_only", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("env")
+        .help("Set an environment variable for the script.  May be given more than once.")
+        .long("env")
+        .takes_value(true)
+        .value_name("KEY=VALUE")
+        .multiple(true)
+        .number_of_values(1)
+        .requires("script")
+        .validator(is_key_value)
+    )
+    .arg(Arg::with_name("dotenv")
+        .help("Load environment variables for the script from a `.env` file.  Defaults to the one next to the script (or in the current directory, for expressions).")
+        .long("dotenv")
+        .takes_value(true)
+        .value_name("PATH")
+        .min_values(0)
+        .max_values(1)
+        .require_equals(true)
+        .requires("script")
+    )
+    .arg(Arg::with_name("no_stdin")
+        .help("Don't connect the script to standard input; it'll see an empty stream instead.")
+        .long("no-stdin")
+        .requires("script")
+        .conflicts_with_all(&["loop", "test", "bench", "build_only", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("output")
+        .help("Write the script's standard output to the given file, creating or truncating it.  Diagnostics still go to standard error.")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .value_name("PATH")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "build_only", "gen_pkg_only"])
+    )
+    .arg(Arg::with_name("working_dir")
+        .help("Run the script in the given working directory.  This does not affect where the script is built.")
+        .long("working-dir")
+        .takes_value(true)
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "build_only", "gen_pkg_only"])
+    )
     .arg(Arg::with_name("test")
         .help("Compile and run tests.")
         .long("test")
         .conflicts_with_all(&["bench", "debug", "args", "force"])
     )
+    .arg(Arg::with_name("check")
+        .help("Compile and run the script's tests, then run the script itself, if they pass.")
+        .long("check")
+        .requires("script")
+        .conflicts_with_all(&["test", "bench", "build_only", "gen_pkg_only", "panic", "expr", "loop"])
+    )
     .arg(Arg::with_name("bench")
         .help("Compile and run benchmarks.  Requires a nightly toolchain.")
         .long("bench")
@@ -149,6 +787,31 @@ fn app() -> App<'static, 'static> {
         .short("t")
         .takes_value(true)
         .requires("expr")
+    )
+    .arg(Arg::with_name("bind")
+        .help("Name of the variable the expression's result is bound to in the template, as `#{bind}`.  Defaults to `v`.")
+        .long("bind")
+        .takes_value(true)
+        .value_name("NAME")
+        .requires("expr")
+        .validator(is_identifier)
+    )
+    .arg(Arg::with_name("define")
+        .help("Define an additional substitution for the template, so that `#{KEY}` is replaced with VALUE.  May be given more than once.")
+        .long("define")
+        .takes_value(true)
+        .value_name("KEY=VALUE")
+        .multiple(true)
+        .number_of_values(1)
+        .requires("expr_or_loop")
+        .validator(is_key_value)
+    )
+    .arg(Arg::with_name("template_variant")
+        .help("Use the named variant of the expression template, for templates which have several.")
+        .long("template-variant")
+        .takes_value(true)
+        .value_name("NAME")
+        .requires("expr")
     );
 
     #[cfg(windows)]
@@ -156,11 +819,88 @@ fn app() -> App<'static, 'static> {
         app = app.subcommand(crate::file_assoc::Args::subcommand())
     }
 
+    app = app.subcommand(alias::Args::subcommand());
+    app = app.subcommand(batch::Args::subcommand());
+    app = app.subcommand(completions::Args::subcommand());
+    app = app.subcommand(new::Args::subcommand());
+    app = app.subcommand(repl::Args::subcommand());
     app = app.subcommand(templates::Args::subcommand());
+    app = app.subcommand(timings::Args::subcommand());
+    app = app.subcommand(version::Args::subcommand());
 
     app
 }
 
+/**
+Strict keywords, which can't be used as names.
+*/
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+/**
+Checks that an argument is a Rust identifier, which can be used as a variable name.
+*/
+fn is_identifier(s: String) -> Result<(), String> {
+    let mut chars = s.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+    if valid && s != "_" && !KEYWORDS.contains(&&*s) {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a valid identifier", s))
+    }
+}
+
+/**
+Checks that an argument is a name Cargo will accept for a package.
+*/
+fn is_package_name(s: String) -> Result<(), String> {
+    let mut chars = s.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        }
+        _ => false,
+    };
+    if valid && s.len() <= 64 && !KEYWORDS.contains(&&*s) {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a valid package name", s))
+    }
+}
+
+/**
+Checks that an argument can be used as the name of an alias.  It becomes a file name, so nothing fancy.
+*/
+fn is_alias_name(s: String) -> Result<(), String> {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a valid alias name", s))
+    }
+}
+
+/**
+Checks that an argument looks like `KEY=VALUE`.
+*/
+fn is_key_value(s: String) -> Result<(), String> {
+    match s.split_once('=') {
+        Some((key, _)) if !key.is_empty() => Ok(()),
+        _ => Err(format!("`{}` is not of the form KEY=VALUE", s)),
+    }
+}
+
 pub fn get_matches() -> ArgMatches<'static> {
     let mut args = env::args().collect::<Vec<_>>();
 
@@ -0,0 +1,129 @@
+/*!
+This module contains the `batch` subcommand, which builds several scripts without running them.
+
+Each script is built by running `cargo eval --build-only` on it in a child process, so every script ends up with its own cache entry, exactly as if it had been built by hand.  Up to `--jobs-scripts` of these children run at once.  They can safely share the binary cache's target directory, since Cargo locks it; builds which collide just wait their turn.
+
+Output from each build is buffered, and printed in the order the scripts were given, no matter what order they finish in.
+*/
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::error::Result;
+
+#[derive(Debug)]
+pub struct Args {
+    scripts: Vec<String>,
+    jobs: usize,
+}
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name("batch")
+            .about("Builds several scripts into the cache, without running them.")
+            .arg(
+                Arg::with_name("scripts")
+                    .help("Script files to build.")
+                    .index(1)
+                    .multiple(true)
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("jobs_scripts")
+                    .help("Build up to N scripts at the same time.")
+                    .long("jobs-scripts")
+                    .takes_value(true)
+                    .value_name("N")
+                    .default_value("1")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err(format!("`{}` is not a valid number of jobs", s)),
+                    }),
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            scripts: m.values_of("scripts").unwrap().map(Into::into).collect(),
+            jobs: m.value_of("jobs_scripts").unwrap().parse().unwrap(),
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    let exe = env::current_exe()?;
+    let count = args.scripts.len();
+    let scripts = Arc::new(args.scripts);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+
+    for _ in 0..args.jobs.min(count) {
+        let exe = exe.clone();
+        let scripts = scripts.clone();
+        let next = next.clone();
+        let tx = tx.clone();
+        thread::spawn(move || loop {
+            let i = next.fetch_add(1, Ordering::SeqCst);
+            if i >= scripts.len() {
+                break;
+            }
+            info!("building {:?}", scripts[i]);
+            let out = Command::new(&exe)
+                .arg("eval")
+                .arg("--build-only")
+                .arg(&scripts[i])
+                .output();
+            if tx.send((i, out)).is_err() {
+                break;
+            }
+        });
+    }
+    drop(tx);
+
+    // Hold on to results until everything before them has been reported.
+    let mut results: Vec<Option<io::Result<Output>>> = (0..count).map(|_| None).collect();
+    let mut reported = 0;
+    let mut built = 0;
+    for (i, out) in rx {
+        results[i] = Some(out);
+        while let Some(out) = results.get_mut(reported).and_then(Option::take) {
+            if report(&scripts[reported], out)? {
+                built += 1;
+            }
+            reported += 1;
+        }
+    }
+
+    println!("built {} of {} scripts", built, count);
+    Ok(if built == count { 0 } else { 1 })
+}
+
+/**
+Prints the outcome of building a single script, returning whether it succeeded.
+*/
+fn report(script: &str, out: io::Result<Output>) -> Result<bool> {
+    let out = match out {
+        Ok(out) => out,
+        Err(err) => {
+            println!("{}: failed: {}", script, err);
+            return Ok(false);
+        }
+    };
+
+    let stderr = &mut io::stderr();
+    stderr.write_all(&out.stdout)?;
+    stderr.write_all(&out.stderr)?;
+    stderr.flush()?;
+
+    if out.status.success() {
+        println!("{}: ok", script);
+    } else {
+        println!("{}: failed: {}", script, out.status);
+    }
+    Ok(out.status.success())
+}
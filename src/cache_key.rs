@@ -0,0 +1,133 @@
+/*!
+This module deals with hashing inputs into the keys their packages are cached under.
+
+Which hash gets used can be picked with `--hash` (or `hash` in a project configuration file), for anyone who needs a particular algorithm, or a faster one.  Changing it changes every key, so scripts cached under one algorithm won't be found under another; they'll just be rebuilt, and the old packages eventually cleaned out of the cache.
+*/
+use std::hash::Hasher;
+
+use serde::Deserialize;
+use shaman::digest::Digest;
+
+/**
+Something an input can be hashed with, to get its cache key.
+*/
+pub trait KeyHasher {
+    fn input_str(&mut self, s: &str);
+
+    /**
+    Returns the hash of everything input so far, in hex.
+    */
+    fn result_str(&mut self) -> String;
+}
+
+impl KeyHasher for shaman::sha1::Sha1 {
+    fn input_str(&mut self, s: &str) {
+        Digest::input_str(self, s)
+    }
+
+    fn result_str(&mut self) -> String {
+        Digest::result_str(self)
+    }
+}
+
+impl KeyHasher for shaman::sha2::Sha256 {
+    fn input_str(&mut self, s: &str) {
+        Digest::input_str(self, s)
+    }
+
+    fn result_str(&mut self) -> String {
+        Digest::result_str(self)
+    }
+}
+
+impl KeyHasher for siphasher::sip::SipHasher13 {
+    fn input_str(&mut self, s: &str) {
+        // Not `Hash::hash`, which would tack a terminator onto each string.
+        self.write(s.as_bytes())
+    }
+
+    fn result_str(&mut self) -> String {
+        format!("{:016x}", self.finish())
+    }
+}
+
+impl KeyHasher for blake3::Hasher {
+    fn input_str(&mut self, s: &str) {
+        self.update(s.as_bytes());
+    }
+
+    fn result_str(&mut self) -> String {
+        self.finalize().to_hex().to_string()
+    }
+}
+
+/**
+The hash algorithms cache keys can be made with.
+*/
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// What's always been used, and still the default.
+    #[default]
+    Sha1,
+    SipHash,
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub const NAMES: &'static [&'static str] = &["sha1", "siphash", "blake3", "sha256"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "siphash" => Some(HashAlgorithm::SipHash),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn hasher(self) -> Box<dyn KeyHasher> {
+        match self {
+            HashAlgorithm::Sha1 => Box::new(shaman::sha1::Sha1::new()),
+            // Fixed keys, so the hash is the same from one run to the next.
+            HashAlgorithm::SipHash => Box::new(siphasher::sip::SipHasher13::new_with_keys(0, 0)),
+            HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            HashAlgorithm::Sha256 => Box::new(shaman::sha2::Sha256::new()),
+        }
+    }
+}
+
+#[test]
+fn test_hash_algorithms() {
+    let hash = |algorithm: HashAlgorithm| {
+        let mut hasher = algorithm.hasher();
+        hasher.input_str("a");
+        hasher.input_str("bc");
+        hasher.result_str()
+    };
+
+    assert_eq!(
+        hash(HashAlgorithm::Sha1),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    assert_eq!(
+        hash(HashAlgorithm::Sha256),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hash(HashAlgorithm::Blake3),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+    assert_eq!(hash(HashAlgorithm::SipHash), hash(HashAlgorithm::SipHash));
+    assert_eq!(hash(HashAlgorithm::SipHash).len(), 16);
+
+    let mut hashes = HashAlgorithm::NAMES
+        .iter()
+        .map(|name| hash(HashAlgorithm::from_name(name).unwrap()))
+        .collect::<Vec<_>>();
+    hashes.sort();
+    hashes.dedup();
+    assert_eq!(hashes.len(), HashAlgorithm::NAMES.len());
+}
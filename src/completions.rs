@@ -0,0 +1,42 @@
+/*!
+This module contains the `completions` subcommand, which generates shell completion scripts.
+
+The completions are for running `cargo-eval` directly (*i.e.* `cargo-eval [OPTIONS] <script>`), since completions for `cargo` itself belong to Cargo.
+*/
+use std::io;
+
+use clap::Shell;
+
+use crate::app;
+use crate::error::Result;
+
+#[derive(Debug)]
+pub struct Args {
+    shell: Shell,
+}
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name("completions")
+            .about("Generates a completion script for the given shell, and writes it to standard output.")
+            .arg(Arg::with_name("shell")
+                .help("Shell to generate completions for.")
+                .index(1)
+                .required(true)
+                .possible_values(&Shell::variants())
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            shell: m.value_of("shell").unwrap().parse().unwrap(),
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    app::app().gen_completions_to(app::name(), args.shell, &mut io::stdout());
+    Ok(0)
+}
@@ -0,0 +1,128 @@
+/*!
+This module deals with project-local configuration, stored in `.cargo-eval.toml` files.
+
+Such a file applies to every script in the directory containing it, and in all directories below that.  For example:
+
+```toml
+# Dependencies, in the same format as `--dep`.
+deps = ["regex", "time=0.1.25"]
+# Rust edition to compile scripts with.
+edition = "2018"
+# Cargo features to pass when building and running.
+features = "foo bar"
+# Most packages to keep in the cache, as with `--max-cache-entries`.
+max_cache_entries = 20
+# Hash algorithm for cache keys, as with `--hash`.
+hash = "blake3"
+# Hosts `--remote` scripts may be fetched from without `--allow-remote`.
+trusted_hosts = ["gist.githubusercontent.com"]
+```
+*/
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::cache_key::HashAlgorithm;
+use crate::error::{Blame, Result};
+
+/**
+Name of the project configuration file.
+*/
+pub const PROJECT_CONFIG_NAME: &str = ".cargo-eval.toml";
+
+/**
+The contents of a project configuration file.
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Dependencies, in `--dep` format.
+    #[serde(default)]
+    pub deps: Vec<String>,
+
+    /// Rust edition.
+    pub edition: Option<String>,
+
+    /// Cargo features.
+    pub features: Option<String>,
+
+    /// Limit on the number of cached packages.
+    pub max_cache_entries: Option<usize>,
+
+    /// Hash algorithm for cache keys.
+    pub hash: Option<HashAlgorithm>,
+
+    /// Hosts trusted to serve `--remote` scripts.
+    #[serde(default)]
+    pub trusted_hosts: Vec<String>,
+}
+
+impl ProjectConfig {
+    /**
+    Returns the parts of the configuration which belong in the package manifest, as a partial manifest.
+    */
+    pub fn manifest(&self) -> toml::value::Table {
+        let mut mani = toml::value::Table::new();
+        if let Some(ref edition) = self.edition {
+            let mut package = toml::value::Table::new();
+            package.insert("edition".into(), toml::Value::String(edition.clone()));
+            mani.insert("package".into(), toml::Value::Table(package));
+        }
+        mani
+    }
+}
+
+/**
+Searches `start` and its ancestors for a project configuration file, and loads it.
+
+The search stops at the filesystem root, or at the first directory which contains a `.git` directory (after checking that directory itself).
+*/
+pub fn find_project_config(start: &Path) -> Result<Option<(PathBuf, ProjectConfig)>> {
+    for dir in start.ancestors() {
+        let path = dir.join(PROJECT_CONFIG_NAME);
+        if path.is_file() {
+            info!("found project config: {:?}", path);
+            let config = load_project_config(&path)?;
+            return Ok(Some((path, config)));
+        }
+
+        if dir.join(".git").exists() {
+            info!("stopping project config search at {:?}", dir);
+            break;
+        }
+    }
+
+    Ok(None)
+}
+
+fn load_project_config(path: &Path) -> Result<ProjectConfig> {
+    let text = fs::read_to_string(path)?;
+    toml::from_str(&text).map_err(|err| {
+        (
+            Blame::Human,
+            format!("could not parse {}: {}", path.display(), err),
+        )
+            .into()
+    })
+}
+
+#[test]
+fn test_project_config_manifest() {
+    let config: ProjectConfig = toml::from_str(
+        r#"
+deps = ["regex"]
+edition = "2015"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.deps, vec!["regex".to_string()]);
+    assert_eq!(config.features, None);
+    assert_eq!(
+        toml::Value::Table(config.manifest()).to_string(),
+        "[package]\nedition = \"2015\"\n"
+    );
+
+    assert!(toml::from_str::<ProjectConfig>("dependencies = []").is_err());
+}
@@ -17,3 +17,350 @@ Measured in milliseconds.
 // It's been *one week* since you looked at me,
 // cocked your head to the side and said "I'm angry."
 pub const MAX_CACHE_AGE_MS: u128 = 7 * 24 * 60 * 60 * 1000;
+
+/**
+Features turned on for dependencies by `--smart-deps`, for crates which aren't much use without them.
+*/
+pub const SMART_DEP_FEATURES: &[(&str, &[&str])] = &[
+    ("reqwest", &["blocking"]),
+    ("serde", &["derive"]),
+    ("tokio", &["full"]),
+];
+
+/**
+The dependency injected for `--color-output`.
+*/
+pub const COLOR_OUTPUT_DEP: (&str, &str) = ("colored", "2");
+
+/**
+The dependency injected for `--json`.
+*/
+pub const JSON_DEP: (&str, &str) = ("serde_json", "1");
+
+/**
+How long to wait before the first `--retry`.  It doubles after every attempt, up to `RETRY_MAX_DELAY_MS`.
+*/
+pub const RETRY_INITIAL_DELAY_MS: u64 = 1000;
+
+/**
+The longest `--retry` will wait between attempts, however many there have been.
+*/
+pub const RETRY_MAX_DELAY_MS: u64 = 60_000;
+
+/**
+Things Cargo says when fetching dependencies failed because of the network, rather than anything to do with the script.  Used to decide whether `--retry` should try again.
+*/
+pub const NETWORK_ERROR_HINTS: &[&str] = &[
+    "failed to download",
+    "failed to fetch",
+    "spurious network error",
+    "couldn't resolve host",
+    "couldn't connect to server",
+    "timeout was reached",
+    "connection reset",
+    "connection refused",
+    "ssl connect error",
+];
+
+/**
+The dependency injected for `--input-encoding`.
+*/
+pub const INPUT_ENCODING_DEP: (&str, &str) = ("encoding_rs", "0.8");
+
+/**
+The dependency injected for `--fetch`.
+*/
+pub const FETCH_DEP: (&str, &str) = ("ureq", "2");
+
+/**
+The dependency injected for `--with-time`.
+*/
+pub const WITH_TIME_DEP: (&str, &str) = ("chrono", "0.4");
+
+/**
+The dependency injected for `--with-uom`.
+*/
+pub const WITH_UOM_DEP: (&str, &str) = ("uom", "0.36");
+
+/**
+The runtimes `--async-runtime` can use to await an expression: the name, the dependency injected for it (as a `--dep` spec), and what to call with the future to block on it.
+*/
+pub const ASYNC_RUNTIMES: &[(&str, &str, &str)] = &[
+    (
+        "tokio",
+        "tokio=1+full",
+        "tokio::runtime::Runtime::new().expect(\"could not start the tokio runtime\").block_on",
+    ),
+    ("async-std", "async-std=1", "async_std::task::block_on"),
+    ("smol", "smol=2", "smol::block_on"),
+];
+
+/**
+The prelude items injected for `--with-time`.  The traits are brought in so things like `now().year()` work without any more ceremony.
+*/
+pub const WITH_TIME_PRELUDE: &str = r#"
+#[allow(unused_imports)]
+use chrono::{Datelike, Timelike};
+
+/// The current date and time, in the local time zone.
+#[allow(dead_code)]
+fn now() -> chrono::DateTime<chrono::Local> {
+    chrono::Local::now()
+}
+"#;
+
+/**
+The prelude items injected for `--with-uom`.  The quantities are the `f64` ones, and the unit modules are imported by name, so a conversion reads like `Length::new::<length::mile>(1.0).get::<length::kilometer>()`.
+*/
+pub const WITH_UOM_PRELUDE: &str = r#"
+#[allow(unused_imports)]
+use uom::si::f64::*;
+#[allow(unused_imports)]
+use uom::si::{
+    acceleration, angle, area, electric_current, electric_potential, energy, force, frequency,
+    information, length, mass, power, pressure, thermodynamic_temperature, time, velocity, volume,
+};
+"#;
+
+/**
+The prelude item injected for `--fetch`.  The expression gets wrapped in a block which binds `body` to what this returns.
+
+The URL is passed in the environment, rather than baked into the source, so fetching a different URL doesn't need a rebuild.
+*/
+pub const FETCH_PRELUDE: &str = r#"
+fn __cargo_eval_fetch() -> String {
+    let url = std::env::var("CARGO_EVAL_FETCH_URL").expect("CARGO_EVAL_FETCH_URL is not set");
+    let body = ureq::get(&url)
+        .call()
+        .map_err(|err| err.to_string())
+        .and_then(|resp| resp.into_string().map_err(|err| err.to_string()));
+    match body {
+        Ok(body) => body,
+        Err(err) => {
+            eprintln!("error: could not fetch `{}`: {}", url, err);
+            std::process::exit(1);
+        }
+    }
+}
+"#;
+
+/**
+The prelude item injected for `--file`.  The expression gets wrapped in a block which binds `contents` to what this returns, or just its first element if there was only one file.
+
+Like `--fetch`, the paths are passed in the environment, so the files are read when the expression runs, and changing them doesn't need a rebuild.
+*/
+pub const FILE_PRELUDE: &str = r#"
+fn __cargo_eval_read_files() -> Vec<String> {
+    let paths = std::env::var_os("CARGO_EVAL_FILES").expect("CARGO_EVAL_FILES is not set");
+    std::env::split_paths(&paths)
+        .map(|path| match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("error: could not read `{}`: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        })
+        .collect()
+}
+"#;
+
+/**
+The prelude item injected for `--fields`.  The expression gets wrapped in a block which binds `fields` to what this returns.
+
+This uses the expression template's `stdin()`, so the expression can still get at the whole of standard input too.
+*/
+pub const FIELDS_PRELUDE: &str = r#"
+fn __cargo_eval_fields(sep: &str) -> Vec<String> {
+    let text = stdin();
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    if text.is_empty() {
+        return vec![];
+    }
+    text.split(sep).map(String::from).collect()
+}
+"#;
+
+/**
+The prelude item injected for `--stdin-bytes`.  The expression gets wrapped in a block which binds `input` to what this returns.
+
+This reads standard input itself, rather than going through `stdin()`, so it doesn't care whether the input is UTF-8, and works with any template.
+*/
+pub const STDIN_BYTES_PRELUDE: &str = r#"
+fn __cargo_eval_stdin_bytes() -> Vec<u8> {
+    let mut bytes = vec![];
+    if let Err(err) = std::io::Read::read_to_end(&mut std::io::stdin(), &mut bytes) {
+        eprintln!("error: could not read stdin: {}", err);
+        std::process::exit(1);
+    }
+    bytes
+}
+"#;
+
+/**
+The prelude items injected for `--state`.  The expression gets wrapped in a block which binds `prev` to what was last saved, if anything, and saves the expression's result afterwards.
+
+The path is passed in the environment, like `--file`, so using a different state file doesn't need a rebuild.
+*/
+pub const STATE_PRELUDE: &str = r#"
+fn __cargo_eval_read_state() -> Option<String> {
+    let path = std::env::var_os("CARGO_EVAL_STATE").expect("CARGO_EVAL_STATE is not set");
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            eprintln!("error: could not read `{}`: {}", std::path::Path::new(&path).display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn __cargo_eval_write_state<T: std::fmt::Display>(value: &T) {
+    let path = std::env::var_os("CARGO_EVAL_STATE").expect("CARGO_EVAL_STATE is not set");
+    if let Err(err) = std::fs::write(&path, value.to_string()) {
+        eprintln!("error: could not write `{}`: {}", std::path::Path::new(&path).display(), err);
+        std::process::exit(1);
+    }
+}
+"#;
+
+/**
+The prelude items injected for `--smart-display`.  The expression's result gets passed to `__cargo_eval_smart_display!`, and what that gives back is displayed with `{}`.
+
+Picking between the two traits is done by the compiler, with method resolution preferring the impl which doesn't need an extra `&`.  Checking with `Any` at runtime, like the loop templates do, would only work for `'static` types, which rules out things like `stdin().trim()`.
+*/
+pub const SMART_DISPLAY_PRELUDE: &str = r#"
+struct __CargoEvalSmartDisplay<'a, T: ?Sized>(&'a T);
+
+trait __CargoEvalDisplayStr {
+    fn __cargo_eval_display(&self) -> String;
+}
+
+impl<T: AsRef<str> + ?Sized> __CargoEvalDisplayStr for __CargoEvalSmartDisplay<'_, T> {
+    fn __cargo_eval_display(&self) -> String {
+        self.0.as_ref().to_owned()
+    }
+}
+
+trait __CargoEvalDisplayDebug {
+    fn __cargo_eval_display(&self) -> String;
+}
+
+impl<T: std::fmt::Debug + ?Sized> __CargoEvalDisplayDebug for &__CargoEvalSmartDisplay<'_, T> {
+    fn __cargo_eval_display(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+macro_rules! __cargo_eval_smart_display {
+    ($e:expr) => {
+        (&__CargoEvalSmartDisplay(&$e)).__cargo_eval_display()
+    };
+}
+"#;
+
+/**
+The prelude items injected for `--thousands`.  The expression's result gets wrapped in `__CargoEvalThousands`, and displayed with `{}`.
+
+Only integers implement `__CargoEvalInteger`, so anything else is a compile error.  The separator is always `,`, whatever the locale.
+*/
+pub const THOUSANDS_PRELUDE: &str = r#"
+trait __CargoEvalInteger: std::fmt::Display {}
+
+macro_rules! __cargo_eval_integers {
+    ($($ty:ty),*) => { $(impl __CargoEvalInteger for $ty {})* };
+}
+
+__cargo_eval_integers!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+struct __CargoEvalThousands<T: __CargoEvalInteger>(T);
+
+impl<T: __CargoEvalInteger> std::fmt::Display for __CargoEvalThousands<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = self.0.to_string();
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", &text[..]),
+        };
+        fmt.write_str(sign)?;
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                fmt.write_str(",")?;
+            }
+            write!(fmt, "{}", digit)?;
+        }
+        Ok(())
+    }
+}
+"#;
+
+/**
+The prelude item injected for `--color-output`.  The expression's result gets wrapped in this, and displayed with `{}`.
+
+This is a *very* rough tokeniser for `{:#?}` output: it colours strings and chars, numbers, `true`/`false`/`None`, type names, and field names.
+*/
+pub const COLOR_OUTPUT_PRELUDE: &str = r#"
+struct __CargoEvalColorized<T>(T);
+
+impl<T: std::fmt::Debug> std::fmt::Display for __CargoEvalColorized<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use colored::Colorize;
+
+        let text = format!("{:#?}", self.0);
+        let mut rest = &text[..];
+        while let Some(first) = rest.chars().next() {
+            let len = if first == '"' || first == '\'' {
+                let mut escaped = false;
+                rest.char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| {
+                        let end = !escaped && c == first;
+                        escaped = !escaped && c == '\\';
+                        end
+                    })
+                    .map(|(i, c)| i + c.len_utf8())
+                    .unwrap_or(rest.len())
+            } else if first.is_alphanumeric() || first == '_' {
+                rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len())
+            } else {
+                first.len_utf8()
+            };
+
+            let (token, tail) = rest.split_at(len);
+            rest = tail;
+            if first == '"' || first == '\'' {
+                write!(fmt, "{}", token.green())?;
+            } else if first.is_numeric() {
+                write!(fmt, "{}", token.cyan())?;
+            } else if token == "true" || token == "false" || token == "None" {
+                write!(fmt, "{}", token.magenta())?;
+            } else if first.is_uppercase() {
+                write!(fmt, "{}", token.yellow())?;
+            } else if first.is_alphabetic() && tail.starts_with(':') {
+                write!(fmt, "{}", token.blue())?;
+            } else {
+                write!(fmt, "{}", token)?;
+            }
+        }
+        Ok(())
+    }
+}
+"#;
+
+/**
+The panic hook `--panic-location` installs, through the `file` template's `#{panic_hook}`, so panics point at the script, rather than the copy of it in the cache (whose line numbers don't match, once the hashbang's been taken off).
+
+It only says where the panic was in the script; everything else is left to whichever hook was there before.
+*/
+pub const PANIC_HOOK_PRELUDE: &str = r#"
+fn __cargo_eval_panic_hook(script: &'static str, generated: &'static str, line_offset: i64) {
+    let prev_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(loc) = info.location().filter(|loc| loc.file() == generated) {
+            eprintln!("script panicked at {}:{}:{}", script, loc.line() as i64 + line_offset, loc.column());
+        }
+        prev_hook(info);
+    }));
+}
+"#;
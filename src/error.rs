@@ -3,10 +3,14 @@ This module contains the definition of the program's main error type.
 */
 
 use std::borrow::Cow;
+use std::env;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
 use std::result::Result as StdResult;
+use std::time::Duration;
 
 /**
 Shorthand for the program's common result type.
@@ -52,6 +56,20 @@ impl MainError {
         self.blame() == Blame::Human
     }
 
+    /**
+    The status `cargo-eval` should exit with, having run into this error.
+    */
+    pub fn exit_code(&self) -> i32 {
+        use self::MainError::*;
+        match *self {
+            Tag(_, _, ref err) => err.exit_code(),
+            Other(_, ref err) if err.is::<CargoNotFound>() => CargoNotFound::EXIT_CODE,
+            Other(_, ref err) if err.is::<EmptyScript>() => EmptyScript::EXIT_CODE,
+            Other(_, ref err) if err.is::<CompileTimeout>() => CompileTimeout::EXIT_CODE,
+            _ => 1,
+        }
+    }
+
     pub fn shift_blame(&mut self, blame: Blame) {
         use self::MainError::*;
         match *self {
@@ -80,6 +98,105 @@ impl fmt::Display for MainError {
 
 impl Error for MainError {}
 
+/**
+Starting `cargo` failed because it couldn't be found; most likely, Rust isn't installed.
+*/
+#[derive(Debug)]
+pub struct CargoNotFound {
+    path: Option<OsString>,
+}
+
+impl CargoNotFound {
+    /**
+    Same as a shell uses when it can't find a command.
+    */
+    pub const EXIT_CODE: i32 = 127;
+
+    pub fn new() -> Self {
+        CargoNotFound {
+            path: env::var_os("PATH"),
+        }
+    }
+}
+
+impl fmt::Display for CargoNotFound {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> StdResult<(), fmt::Error> {
+        write!(
+            fmt,
+            "could not find `cargo`; install Rust (see <https://rustup.rs/>), \
+            or make sure `cargo` is on your `PATH`"
+        )?;
+        match self.path {
+            Some(ref path) => write!(fmt, " (searched `{}`)", path.to_string_lossy()),
+            None => write!(fmt, " (`PATH` isn't set)"),
+        }
+    }
+}
+
+impl Error for CargoNotFound {}
+
+/**
+The script file has nothing in it but whitespace.  Left alone, this turns into a baffling complaint from `rustc` about `main` being missing.
+*/
+#[derive(Debug)]
+pub struct EmptyScript {
+    path: PathBuf,
+}
+
+impl EmptyScript {
+    /**
+    `EX_DATAERR`, from `sysexits.h`.
+    */
+    pub const EXIT_CODE: i32 = 65;
+
+    pub fn new(path: PathBuf) -> Self {
+        EmptyScript { path }
+    }
+}
+
+impl fmt::Display for EmptyScript {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> StdResult<(), fmt::Error> {
+        write!(
+            fmt,
+            "script `{}` is empty; there's nothing to run",
+            self.path.display()
+        )
+    }
+}
+
+impl Error for EmptyScript {}
+
+/**
+Building the script took longer than `--max-compile-time` allowed, so it was stopped.
+*/
+#[derive(Debug)]
+pub struct CompileTimeout {
+    limit: Duration,
+}
+
+impl CompileTimeout {
+    /**
+    Same as `timeout` uses when the command runs out of time.
+    */
+    pub const EXIT_CODE: i32 = 124;
+
+    pub fn new(limit: Duration) -> Self {
+        CompileTimeout { limit }
+    }
+}
+
+impl fmt::Display for CompileTimeout {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> StdResult<(), fmt::Error> {
+        write!(
+            fmt,
+            "gave up on building the script after {}s (see `--max-compile-time`)",
+            self.limit.as_secs_f64()
+        )
+    }
+}
+
+impl Error for CompileTimeout {}
+
 macro_rules! from_impl {
     ($src_ty:ty => $dst_ty:ty, $src:ident -> $e:expr) => {
         impl From<$src_ty> for $dst_ty {
@@ -33,30 +33,53 @@ Length of time to suppress Cargo output.
 #[cfg(feature = "suppress-cargo-output")]
 const CARGO_OUTPUT_TIMEOUT: u64 = 2_000/*ms*/;
 
+mod alias;
 mod app;
+mod batch;
+mod cache_key;
+mod completions;
+mod config;
 mod consts;
 mod error;
 mod manifest;
+mod new;
 mod platform;
+mod remote;
+mod repl;
+mod runner;
+mod sandbox;
+mod tarball;
 mod templates;
+mod timings;
 mod util;
+mod version;
 
 #[cfg(windows)]
 mod file_assoc;
 
-use std::ffi::OsString;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{self, Command};
+use std::time::{Duration, Instant};
 
-use crate::error::{Blame, MainError, Result};
+use crate::cache_key::HashAlgorithm;
+use crate::error::{Blame, MainError, Result, ResultExt};
 use crate::util::Defer;
 
 #[derive(Debug)]
 enum SubCommand {
-    Script(Args),
+    Script(Box<Args>),
+    Alias(alias::Args),
+    Batch(batch::Args),
+    Completions(completions::Args),
+    New(new::Args),
+    Repl(repl::Args),
     Templates(templates::Args),
+    Timings(timings::Args),
+    Version(version::Args),
     #[cfg(windows)]
     FileAssoc(file_assoc::Args),
 }
@@ -70,18 +93,162 @@ struct Args {
     expr: bool,
     loop_: bool,
     count: bool,
+    input_encoding: Option<String>,
+    parallel: Option<usize>,
+
+    radix: Option<String>,
 
     pkg_path: Option<String>,
     gen_pkg_only: bool,
+    pkg_name: Option<String>,
+    rust_version: Option<String>,
+    emit_runner: bool,
     build_only: bool,
     clear_cache: bool,
+    clean: bool,
+    touch: bool,
+    print_cache_dir: bool,
+    print_data_dir: bool,
+    print_rustc_wrapper: bool,
+    max_cache_entries: Option<usize>,
+    hash: Option<HashAlgorithm>,
+    color_output: bool,
+    assert: bool,
+    unwrap: bool,
+    lines: bool,
+    join: Option<String>,
+    thousands: bool,
+    smart_display: bool,
+    to_stderr: bool,
+    json: bool,
+    time: bool,
+    async_runtime: Option<String>,
+    fetch: Option<String>,
+    file: Vec<String>,
+    fields: Option<String>,
+    stdin_bytes: bool,
+    with_time: bool,
+    with_uom: bool,
+    state: Option<String>,
+    sep: Option<String>,
+    warn_unused_deps: bool,
+    print_deps: bool,
+    print_features: bool,
+    print_binary_size: bool,
+    smart_deps: bool,
     debug: bool,
+    profile: Option<String>,
+    strip: Option<String>,
+    panic: Option<String>,
+    codegen_units: Option<u32>,
+    max_compile_time: Option<Duration>,
+    retry: u32,
+    no_color_errors: bool,
     dep: Vec<String>,
+    dep_target: Vec<String>,
+    lockfile: Option<String>,
+    deps_file: Option<String>,
     force: bool,
+    rustflags: Vec<String>,
+    reproducible: bool,
+    sanitizer: Option<String>,
+    rustc_wrapper: Option<String>,
+    sandbox: bool,
+    keep_temps: bool,
+    clean_env: bool,
+    no_stdin: bool,
+    env: Vec<(String, String)>,
+    dotenv: Option<Option<String>>,
+    working_dir: Option<String>,
+    output: Option<String>,
+    emit_binary: Option<String>,
+    after_build: Option<String>,
+    alias: Option<String>,
+    emit: Option<String>,
+    crate_type: Option<String>,
+    emit_tarball: Option<String>,
     unstable_features: Vec<String>,
     use_bincache: Option<bool>,
+    isolated: bool,
+    rebuild_on: RebuildOn,
+    why_rebuild: bool,
+    log_timings: bool,
     build_kind: BuildKind,
+    check: bool,
+    verbosity: Verbosity,
     template: Option<String>,
+    template_variant: Option<String>,
+    bind: Option<String>,
+    define: Vec<(String, String)>,
+    manifest_override: Option<String>,
+    build_script: Option<String>,
+    include: Vec<String>,
+    raw: bool,
+    panic_location: bool,
+    verify_hash: Option<String>,
+    remote: bool,
+    allow_remote: bool,
+    preprocessor: Option<String>,
+}
+
+/**
+How to tell whether a cached script is still fresh.
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RebuildOn {
+    /// Compare the content of the generated package against the cache.
+    Hash,
+
+    /// Only compare the script file's modification time against the cache.
+    Mtime,
+}
+
+impl RebuildOn {
+    fn from_name(name: Option<&str>) -> Self {
+        match name {
+            None | Some("hash") => RebuildOn::Hash,
+            Some("mtime") => RebuildOn::Mtime,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/**
+How much to say about what's going on, both from `cargo eval` itself and from Cargo.
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Verbosity {
+    Normal,
+    Verbose,
+    VeryVerbose,
+}
+
+impl Verbosity {
+    fn from_count(count: u64) -> Self {
+        match count {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::VeryVerbose,
+        }
+    }
+
+    /// Flags to pass along to `cargo build` and friends.
+    fn cargo_args(self) -> &'static [&'static str] {
+        match self {
+            Verbosity::Normal => &[],
+            Verbosity::Verbose => &["-v"],
+            Verbosity::VeryVerbose => &["-vv"],
+        }
+    }
+
+    /// How much of our own logging to show, unless `RUST_LOG` says otherwise.
+    fn log_level(self) -> Option<log::LevelFilter> {
+        match self {
+            Verbosity::Normal => None,
+            Verbosity::Verbose => Some(log::LevelFilter::Info),
+            Verbosity::VeryVerbose => Some(log::LevelFilter::Trace),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -122,10 +289,38 @@ fn parse_args() -> SubCommand {
 
     let m = app::get_matches();
 
+    if let Some(m) = m.subcommand_matches("batch") {
+        return self::SubCommand::Batch(batch::Args::parse(m));
+    }
+
+    if let Some(m) = m.subcommand_matches("alias") {
+        return self::SubCommand::Alias(alias::Args::parse(m));
+    }
+
+    if let Some(m) = m.subcommand_matches("completions") {
+        return self::SubCommand::Completions(completions::Args::parse(m));
+    }
+
+    if let Some(m) = m.subcommand_matches("new") {
+        return self::SubCommand::New(new::Args::parse(m));
+    }
+
+    if let Some(m) = m.subcommand_matches("repl") {
+        return self::SubCommand::Repl(repl::Args::parse(m));
+    }
+
     if let Some(m) = m.subcommand_matches("templates") {
         return self::SubCommand::Templates(templates::Args::parse(m));
     }
 
+    if let Some(m) = m.subcommand_matches("timings") {
+        return self::SubCommand::Timings(timings::Args::parse(m));
+    }
+
+    if let Some(m) = m.subcommand_matches("version") {
+        return self::SubCommand::Version(version::Args::parse(m));
+    }
+
     #[cfg(windows)]
     {
         if let Some(m) = m.subcommand_matches("file-association") {
@@ -133,6 +328,16 @@ fn parse_args() -> SubCommand {
         }
     }
 
+    fn key_values(m: &clap::ArgMatches, name: &str) -> Vec<(String, String)> {
+        m.values_of(name)
+            .map(|vs| {
+                vs.filter_map(|v| v.split_once('='))
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     fn yes_or_no(v: Option<&str>) -> Option<bool> {
         v.map(|v| match v {
             "yes" => true,
@@ -141,7 +346,7 @@ fn parse_args() -> SubCommand {
         })
     }
 
-    self::SubCommand::Script(Args {
+    self::SubCommand::Script(Box::new(Args {
         script: value_t!(m, "script", String).ok(),
         args: values_t!(m, "args", String).unwrap_or_default(),
         features: value_t!(m, "features", String).ok(),
@@ -149,25 +354,121 @@ fn parse_args() -> SubCommand {
         expr: m.is_present("expr"),
         loop_: m.is_present("loop"),
         count: m.is_present("count"),
+        input_encoding: value_t!(m, "input_encoding", String).ok(),
+        parallel: value_t!(m, "parallel", usize).ok(),
+
+        radix: value_t!(m, "radix", String).ok(),
 
         pkg_path: value_t!(m, "pkg_path", String).ok(),
         gen_pkg_only: m.is_present("gen_pkg_only"),
+        pkg_name: value_t!(m, "pkg_name", String).ok(),
+        rust_version: value_t!(m, "rust_version", String).ok(),
+        emit_runner: m.is_present("emit_runner"),
         build_only: m.is_present("build_only"),
         clear_cache: m.is_present("clear_cache"),
+        clean: m.is_present("clean"),
+        touch: m.is_present("touch"),
+        print_cache_dir: m.is_present("print_cache_dir"),
+        print_data_dir: m.is_present("print_data_dir"),
+        print_rustc_wrapper: m.is_present("print_rustc_wrapper"),
+        max_cache_entries: value_t!(m, "max_cache_entries", usize).ok(),
+        hash: m
+            .value_of("hash")
+            .map(|name| HashAlgorithm::from_name(name).unwrap()),
+        color_output: m.is_present("color_output"),
+        assert: m.is_present("assert"),
+        unwrap: m.is_present("unwrap"),
+        lines: m.is_present("lines"),
+        join: value_t!(m, "join", String).ok(),
+        thousands: m.is_present("thousands"),
+        smart_display: m.is_present("smart_display"),
+        to_stderr: m.is_present("to_stderr"),
+        json: m.is_present("json"),
+        time: m.is_present("time"),
+        async_runtime: value_t!(m, "async_runtime", String).ok(),
+        fetch: value_t!(m, "fetch", String).ok(),
+        file: values_t!(m, "file", String).unwrap_or_default(),
+        fields: value_t!(m, "fields", String).ok(),
+        stdin_bytes: m.is_present("stdin_bytes"),
+        with_time: m.is_present("with_time"),
+        with_uom: m.is_present("with_uom"),
+        state: value_t!(m, "state", String).ok(),
+        sep: value_t!(m, "sep", String).ok(),
+        warn_unused_deps: m.is_present("warn_unused_deps"),
+        print_deps: m.is_present("print_deps"),
+        print_features: m.is_present("print_features"),
+        print_binary_size: m.is_present("print_binary_size"),
+        smart_deps: m.is_present("smart_deps"),
         debug: m.is_present("debug"),
+        profile: value_t!(m, "profile", String).ok(),
+        strip: value_t!(m, "strip", String).ok(),
+        panic: value_t!(m, "panic", String).ok(),
+        codegen_units: value_t!(m, "codegen_units", u32).ok(),
+        retry: value_t!(m, "retry", u32).unwrap_or(0),
+        max_compile_time: value_t!(m, "max_compile_time", f64)
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+        no_color_errors: m.is_present("no_color_errors"),
         dep: values_t!(m, "dep", String).unwrap_or_default(),
+        dep_target: values_t!(m, "dep_target", String).unwrap_or_default(),
+        lockfile: value_t!(m, "lockfile", String).ok(),
+        deps_file: value_t!(m, "deps_file", String).ok(),
         force: m.is_present("force"),
+        rustflags: values_t!(m, "rustflags", String)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|flags| util::split_shell_words(flags))
+            .collect(),
+        reproducible: m.is_present("reproducible"),
+        sanitizer: value_t!(m, "sanitizer", String).ok(),
+        rustc_wrapper: value_t!(m, "rustc_wrapper", String).ok(),
+        sandbox: m.is_present("sandbox"),
+        keep_temps: m.is_present("keep_temps"),
+        clean_env: m.is_present("clean_env"),
+        no_stdin: m.is_present("no_stdin"),
+        env: key_values(&m, "env"),
+        dotenv: if m.is_present("dotenv") {
+            Some(m.value_of("dotenv").map(String::from))
+        } else {
+            None
+        },
+        working_dir: value_t!(m, "working_dir", String).ok(),
+        output: value_t!(m, "output", String).ok(),
+        emit_binary: value_t!(m, "emit_binary", String).ok(),
+        after_build: value_t!(m, "after_build", String).ok(),
+        alias: value_t!(m, "alias", String).ok(),
+        emit: value_t!(m, "emit", String).ok(),
+        // A `bin` is what we'd build anyway, so it shouldn't count as different.
+        crate_type: value_t!(m, "crate_type", String)
+            .ok()
+            .filter(|crate_type| crate_type != "bin"),
+        emit_tarball: value_t!(m, "emit_tarball", String).ok(),
         unstable_features: values_t!(m, "unstable_features", String).unwrap_or_default(),
         use_bincache: yes_or_no(m.value_of("use_bincache")),
+        isolated: m.is_present("isolated"),
+        rebuild_on: RebuildOn::from_name(m.value_of("rebuild_on")),
+        why_rebuild: m.is_present("why_rebuild"),
+        log_timings: m.is_present("log_timings"),
         build_kind: BuildKind::from_flags(m.is_present("test"), m.is_present("bench")),
+        check: m.is_present("check"),
+        verbosity: Verbosity::from_count(m.occurrences_of("verbose")),
         template: value_t!(m, "template", String).ok(),
-    })
+        template_variant: value_t!(m, "template_variant", String).ok(),
+        bind: value_t!(m, "bind", String).ok(),
+        define: key_values(&m, "define"),
+        manifest_override: value_t!(m, "manifest_override", String).ok(),
+        build_script: value_t!(m, "build_script", String).ok(),
+        include: values_t!(m, "include", String).unwrap_or_default(),
+        raw: m.is_present("raw"),
+        panic_location: m.is_present("panic_location"),
+        verify_hash: value_t!(m, "verify_hash", String).ok(),
+        remote: m.is_present("remote"),
+        allow_remote: m.is_present("allow_remote"),
+        preprocessor: value_t!(m, "preprocessor", String).ok(),
+    }))
 }
 
 fn main() {
-    env_logger::init();
-    info!("starting");
-    info!("args: {:?}", std::env::args().collect::<Vec<_>>());
     let stderr = &mut std::io::stderr();
     match try_main() {
         Ok(0) => (),
@@ -176,7 +477,7 @@ fn main() {
         }
         Err(ref err) if err.is_human() => {
             writeln!(stderr, "error: {}", err).unwrap();
-            std::process::exit(1);
+            std::process::exit(err.exit_code());
         }
         Err(ref err) => {
             writeln!(stderr, "internal error: {}", err).unwrap();
@@ -187,15 +488,46 @@ fn main() {
 
 fn try_main() -> Result<i32> {
     let args = parse_args();
+    init_logging(match args {
+        SubCommand::Script(ref args) => args.verbosity,
+        _ => Verbosity::Normal,
+    });
+    info!("starting");
+    info!("args: {:?}", std::env::args().collect::<Vec<_>>());
     info!("Arguments: {:?}", args);
 
-    let args = match args {
-        SubCommand::Script(args) => args,
+    let mut args = match args {
+        SubCommand::Script(args) => *args,
+        SubCommand::Alias(args) => return alias::try_main(args),
+        SubCommand::Batch(args) => return batch::try_main(args),
+        SubCommand::Completions(args) => return completions::try_main(args),
+        SubCommand::New(args) => return new::try_main(args),
+        SubCommand::Repl(args) => return repl::try_main(args),
         SubCommand::Templates(args) => return templates::try_main(args),
+        SubCommand::Timings(args) => return timings::try_main(args),
+        SubCommand::Version(args) => return version::try_main(args),
         #[cfg(windows)]
         SubCommand::FileAssoc(args) => return file_assoc::try_main(args),
     };
 
+    if args.print_cache_dir || args.print_data_dir {
+        let dir = if args.print_cache_dir {
+            app::cache_dir()
+        } else {
+            app::data_dir()
+        };
+        println!("{}", dir.display());
+        return Ok(0);
+    }
+
+    if args.print_rustc_wrapper {
+        match rustc_wrapper(&args)? {
+            Some(wrapper) => println!("{}", wrapper.to_string_lossy()),
+            None => eprintln!("no rustc wrapper; use `--rustc-wrapper` or set `RUSTC_WRAPPER`"),
+        }
+        return Ok(0);
+    }
+
     if log_enabled!(log::Level::Debug) {
         let scp = script_cache_path();
         let bcp = binary_cache_path();
@@ -220,11 +552,54 @@ fn try_main() -> Result<i32> {
         }
     }
 
+    // Check this *before* we go to the trouble of building anything.
+    if let Some(ref working_dir) = args.working_dir {
+        if !Path::new(working_dir).is_dir() {
+            return Err((
+                Blame::Human,
+                format!(
+                    "working directory `{}` does not exist or is not a directory",
+                    working_dir
+                ),
+            )
+                .into());
+        }
+    }
+
+    // An alias runs the executable saved for it, without looking at any script.
+    if let (Some(script), false, false) = (&args.script, args.expr, args.loop_) {
+        if let Some(name) = script.strip_prefix('@') {
+            return alias::run(name, &args.args, &args.env);
+        }
+    }
+
+    // Colour only makes sense if the output is going to end up in front of someone.
+    let colorize = args.color_output
+        && if args.to_stderr {
+            platform::stderr_is_tty()
+        } else {
+            args.output.is_none() && platform::stdout_is_tty()
+        };
+    info!("colorize: {:?}", colorize);
+
+    // A remote script is fetched into the cache, then run from there like any other script.
+    if args.remote {
+        let url = args.script.take().unwrap();
+        let trusted_hosts = config::find_project_config(&std::env::current_dir()?)?
+            .map(|(_, config)| config.trusted_hosts)
+            .unwrap_or_default();
+        remote::check_trusted(&url, args.allow_remote, &trusted_hosts)?;
+        let path = remote::fetch(&url, &remote_cache_path())?;
+        args.script = Some(path.to_string_lossy().into_owned());
+    }
+
     // Take the arguments and work out what our input is going to be.  Primarily, this gives us the content, a user-friendly name, and a cache-friendly ID.
     // These three are just storage for the borrows we'll actually use.
     let script_name: String;
     let script_path: PathBuf;
     let content: String;
+    // The expression as the user wrote it, before we wrap anything around it.
+    let mut script_literal = None;
 
     let input = match (&args.script, args.expr, args.loop_) {
         (Some(script), false, false) => {
@@ -239,108 +614,637 @@ fn try_main() -> Result<i32> {
             let mut body = String::new();
             file.read_to_string(&mut body)?;
 
+            // This is checked against the file exactly as it is on disk, so it can be compared with what `sha256sum` says.
+            if let Some(ref expected) = args.verify_hash {
+                let actual = sha256_str(&body);
+                if !actual.eq_ignore_ascii_case(expected) {
+                    return Err((
+                        Blame::Human,
+                        format!(
+                            "script `{}` has SHA-256 hash {}, but {} was expected; refusing to run it",
+                            path.display(),
+                            actual,
+                            expected
+                        ),
+                    )
+                        .into());
+                }
+            }
+
+            // Some Windows editors start files with a byte order mark.  It'd trip up shebang and manifest detection, and isn't really part of the script, so it doesn't get to affect the content hash either.
+            if body.starts_with(UTF8_BOM) {
+                body.drain(..UTF8_BOM.len_utf8());
+            }
+
+            if body.trim().is_empty() {
+                return Err(MainError::Other(
+                    Blame::Human,
+                    Box::new(error::EmptyScript::new(path)),
+                ));
+            }
+
             let mtime = platform::file_last_modified(&file);
 
             script_path = std::env::current_dir()?.join(path);
-            content = body;
+            content = preprocess(args.preprocessor.as_deref(), body)?;
 
             Input::File(&script_name, &script_path, &content, mtime)
         }
         (Some(expr), true, false) => {
-            content = expr.clone();
-            Input::Expr(&content, args.template.as_ref().map(|s| &**s))
+            let mut expr = preprocess(args.preprocessor.as_deref(), expr.clone())?;
+            // Debug formatting a `str` gives a valid Rust string literal.
+            script_literal = Some(format!("{:?}", expr));
+            // A closure gets applied to each of the arguments, rather than printed.  This has to be worked out before anything gets wrapped around it.
+            let apply = args.template.is_none()
+                && args.template_variant.is_none()
+                && !(args.assert || args.unwrap || args.lines || args.join.is_some() || args.json)
+                && match closure_arity(&expr) {
+                    Some(1) => true,
+                    Some(n) => {
+                        return Err((
+                            Blame::Human,
+                            format!(
+                                "only closures taking a single argument can be applied to the arguments; this one takes {}",
+                                n
+                            ),
+                        )
+                            .into())
+                    }
+                    None => false,
+                };
+            if apply && args.state.is_some() {
+                return Err((
+                    Blame::Human,
+                    "`--state` can't be used with a closure that's applied to the arguments",
+                )
+                    .into());
+            }
+            let wrapper = if colorize {
+                Some("__CargoEvalColorized")
+            } else if args.thousands {
+                Some("__CargoEvalThousands")
+            } else if args.smart_display {
+                Some("__cargo_eval_smart_display!")
+            } else {
+                None
+            };
+
+            // For a closure, these are about what it gives back for each argument, not the closure itself.
+            let (closure, mut value) = if apply {
+                (Some(expr), "__cargo_eval_fn(__cargo_eval_arg)".to_owned())
+            } else {
+                (None, expr)
+            };
+            // This goes innermost, so everything else sees what the future resolved to.
+            if let Some(ref runtime) = args.async_runtime {
+                let (_, _, block_on) = async_runtime(runtime);
+                value = format!("{}({{\n{}\n}})", block_on, value);
+            }
+            if args.time {
+                value = format!(
+                    "{{\nlet __cargo_eval_start = std::time::Instant::now();\n\
+                    let __cargo_eval_value = {{\n{}\n}};\n\
+                    eprintln!(\"evaluated in {{:?}}\", __cargo_eval_start.elapsed());\n\
+                    __cargo_eval_value\n}}",
+                    value
+                );
+            }
+            expr = match (wrapper, closure) {
+                (Some(wrapper), Some(closure)) => format!(
+                    "{{\nlet __cargo_eval_fn = {{\n{}\n}};\n\
+                    move |__cargo_eval_arg| {}({{\n{}\n}})\n}}",
+                    closure, wrapper, value
+                ),
+                (None, Some(closure)) => format!(
+                    "{{\nlet __cargo_eval_fn = {{\n{}\n}};\n\
+                    move |__cargo_eval_arg| {{\n{}\n}}\n}}",
+                    closure, value
+                ),
+                (_, None) => value,
+            };
+            if args.fetch.is_some() {
+                if !cfg!(feature = "fetch") {
+                    return Err((
+                        Blame::Human,
+                        "`--fetch` isn't available, since `cargo-eval` was built without the `fetch` feature",
+                    )
+                        .into());
+                }
+                expr = format!(
+                    "{{\n#[allow(unused_variables)]\n\
+                    let body: String = __cargo_eval_fetch();\n{}\n}}",
+                    expr
+                );
+            }
+            if !args.file.is_empty() {
+                let binding = if args.file.len() == 1 {
+                    "let contents: String = __cargo_eval_read_files().remove(0);"
+                } else {
+                    "let contents: Vec<String> = __cargo_eval_read_files();"
+                };
+                expr = format!("{{\n#[allow(unused_variables)]\n{}\n{}\n}}", binding, expr);
+            }
+            if let Some(ref sep) = args.fields {
+                expr = format!(
+                    "{{\n#[allow(unused_variables)]\n\
+                    let fields: Vec<String> = __cargo_eval_fields({:?});\n{}\n}}",
+                    sep, expr
+                );
+            }
+            if args.stdin_bytes {
+                expr = format!(
+                    "{{\n#[allow(unused_variables)]\n\
+                    let input: Vec<u8> = __cargo_eval_stdin_bytes();\n{}\n}}",
+                    expr
+                );
+            }
+            if args.state.is_some() {
+                expr = format!(
+                    "{{\n#[allow(unused_variables)]\n\
+                    let prev: Option<String> = __cargo_eval_read_state();\n\
+                    let __cargo_eval_value = {{\n{}\n}};\n\
+                    __cargo_eval_write_state(&__cargo_eval_value);\n\
+                    __cargo_eval_value\n}}",
+                    expr
+                );
+            }
+            content = match (wrapper, apply) {
+                (Some(wrapper), false) => format!("{}({{\n{}\n}})", wrapper, expr),
+                _ => expr,
+            };
+            let template = if args.assert {
+                Some("assert")
+            } else {
+                args.template.as_deref()
+            };
+            if args.unwrap {
+                args.template_variant = Some("unwrap".into());
+            }
+            if args.lines {
+                args.template_variant = Some("lines".into());
+            }
+            if args.join.is_some() {
+                args.template_variant = Some("join".into());
+            }
+            if args.json {
+                args.template_variant = Some("json".into());
+            }
+            if apply {
+                args.template_variant = Some("apply".into());
+            }
+            Input::Expr(&content, template)
         }
         (Some(loop_), false, true) => {
-            content = loop_.clone();
+            content = preprocess(args.preprocessor.as_deref(), loop_.clone())?;
+            if args.input_encoding.is_some() {
+                args.template_variant = Some("decode".into());
+            }
+            if args.parallel.is_some() {
+                args.template_variant = Some("parallel".into());
+            }
             Input::Loop(&content, args.count)
         }
         _ => unreachable!(),
     };
     info!("input: {:?}", input);
 
-    /*
-    Sort out the dependencies.  We want to do a few things:
-
-    - Sort them so that they hash consistently.
-    - Check for duplicates.
-    - Expand `pkg` into `pkg=*`.
-    */
-    let deps = {
-        use std::collections::hash_map::Entry::{Occupied, Vacant};
-        use std::collections::HashMap;
-
-        let mut deps: HashMap<String, String> = HashMap::new();
-        for dep in args.dep.iter().cloned() {
-            // Append '=*' if it needs it.
-            let dep = match dep.find('=') {
-                Some(_) => dep,
-                None => dep + "=*",
+    // Pick up any project-local configuration.  Anything given on the command line takes precedence.
+    let project_config = config::find_project_config(&input.base_path())?
+        .map(|(_, config)| config)
+        .unwrap_or_default();
+    info!("project_config: {:?}", project_config);
+
+    // This only ever affects the script's environment, so it has no business going anywhere near the cache.
+    let dotenv_vars = match args.dotenv {
+        Some(ref path) => {
+            let path = match *path {
+                Some(ref path) => PathBuf::from(path),
+                None => input.base_path().join(".env"),
             };
+            load_dotenv(&path)?
+        }
+        None => vec![],
+    };
 
-            let mut parts = dep.splitn(2, '=');
-            let name = parts.next().expect("dependency is missing name");
-            let version = parts.next().expect("dependency is missing version");
-            assert!(
-                parts.next().is_none(),
-                "dependency somehow has three parts?!"
-            );
+    // Better to find out about a missing `--file` now, than after building the expression.  These also need to be absolute, in case of `--working-dir`.
+    let files_env = if args.file.is_empty() {
+        None
+    } else {
+        let mut paths = vec![];
+        for path in &args.file {
+            fs::File::open(path)
+                .map_err(MainError::from)
+                .err_tag(format!("could not read file `{}`", path))
+                .shift_blame(Blame::Human)?;
+            paths.push(std::env::current_dir()?.join(path));
+        }
+        Some(std::env::join_paths(paths).map_err(|err| (Blame::Human, err.to_string()))?)
+    };
+    let state_env = match args.state {
+        Some(ref path) => Some(std::env::current_dir()?.join(path)),
+        None => None,
+    };
 
-            if name == "" {
-                return Err((Blame::Human, "cannot have empty dependency package name").into());
-            }
+    if args.features.is_none() {
+        args.features = project_config.features.clone();
+    }
+    if args.max_cache_entries.is_none() {
+        args.max_cache_entries = project_config.max_cache_entries;
+    }
+    if args.hash.is_none() {
+        args.hash = project_config.hash;
+    }
 
-            if version == "" {
-                return Err((Blame::Human, "cannot have empty dependency version").into());
-            }
+    if let Some(ref path) = args.deps_file {
+        let text = fs::read_to_string(path)
+            .map_err(MainError::from)
+            .err_tag(format!("could not read deps file `{}`", path))
+            .shift_blame(Blame::Human)?;
+        args.dep.extend(parse_deps_file(&text));
+    }
 
-            match deps.entry(name.into()) {
-                Vacant(ve) => {
-                    ve.insert(version.into());
-                }
-                Occupied(oe) => {
-                    // This is *only* a problem if the versions don't match.  We won't try to do anything clever in terms of upgrading or resolving or anything... exact match or go home.
-                    let existing = oe.get();
-                    if version != existing {
-                        return Err((
-                            Blame::Human,
-                            format!(
-                                "conflicting versions for dependency '{}': '{}', '{}'",
-                                name, existing, version
-                            ),
-                        )
-                            .into());
-                    }
-                }
-            }
+    {
+        let cli_names = args
+            .dep
+            .iter()
+            .map(|dep| dep_spec_name(dep).to_owned())
+            .collect::<Vec<_>>();
+        let project_deps = project_config
+            .deps
+            .iter()
+            .filter(|dep| !cli_names.iter().any(|name| name == dep_spec_name(dep)))
+            .cloned();
+        args.dep.extend(project_deps);
+    }
+
+    if colorize {
+        let (name, version) = consts::COLOR_OUTPUT_DEP;
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(format!("{}={}", name, version));
+        }
+    }
+
+    if args.with_time {
+        let (name, version) = consts::WITH_TIME_DEP;
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(format!("{}={}", name, version));
         }
+    }
 
-        // Sort and turn into a regular vec.
-        let mut deps: Vec<(String, String)> = deps.into_iter().collect();
-        deps.sort();
-        deps
-    };
+    if args.with_uom {
+        let (name, version) = consts::WITH_UOM_DEP;
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(format!("{}={}", name, version));
+        }
+    }
+
+    if args.fetch.is_some() {
+        let (name, version) = consts::FETCH_DEP;
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(format!("{}={}", name, version));
+        }
+    }
+
+    if args.json {
+        let (name, version) = consts::JSON_DEP;
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(format!("{}={}", name, version));
+        }
+    }
+
+    if let Some(ref runtime) = args.async_runtime {
+        let (name, spec, _) = async_runtime(runtime);
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(spec.into());
+        }
+    }
+
+    if args.input_encoding.is_some() {
+        let (name, version) = consts::INPUT_ENCODING_DEP;
+        if !args.dep.iter().any(|dep| dep_spec_name(dep) == name) {
+            args.dep.push(format!("{}={}", name, version));
+        }
+    }
+
+    if let Some(ref path) = args.lockfile {
+        let lock_str = fs::read_to_string(path)
+            .map_err(MainError::from)
+            .err_tag(format!("could not read lockfile `{}`", path))
+            .shift_blame(Blame::Human)?;
+        args.dep = pin_locked_deps(&lock_str, &args.dep)
+            .err_tag(format!("could not parse lockfile `{}`", path))
+            .shift_blame(Blame::Human)?;
+    }
+
+    let deps = parse_deps(&args.dep, args.smart_deps)?;
     info!("deps: {:?}", deps);
+    let target_deps = parse_target_deps(&args.dep_target, args.smart_deps)?;
+    info!("target_deps: {:?}", target_deps);
+
+    if args.clean {
+        let removed = clean_cache_entry(
+            &input,
+            &deps,
+            &target_deps,
+            &args.rustflags,
+            args.hash.unwrap_or_default(),
+        )?;
+        if removed.is_empty() {
+            println!("nothing to clean.");
+        }
+        for path in removed {
+            println!("removed {}", path.display());
+        }
+        return Ok(0);
+    }
+
+    if args.touch {
+        let pkg_path = cache_pkg_path(
+            &input,
+            &deps,
+            &target_deps,
+            &args.rustflags,
+            args.hash.unwrap_or_default(),
+        );
+        if touch_cache_pkg(&pkg_path)? {
+            println!("touched {}", pkg_path.display());
+        } else {
+            println!("nothing to touch.");
+        }
+        return Ok(0);
+    }
 
     /*
-    Generate the prelude items, if we need any.  Again, ensure consistent and *valid* sorting.
+    Generate the prelude items, if we need any.  Again, ensure consistent and *valid* sorting: inner attributes have to come before any items.
     */
     let prelude_items = {
-        let unstable_features = args
+        let mut unstable_features: Vec<_> = args
             .unstable_features
             .iter()
-            .map(|uf| format!("#![feature({})]", uf));
+            .map(|uf| format!("#![feature({})]", uf))
+            .collect();
+        unstable_features.sort();
 
-        let mut items: Vec<_> = unstable_features.collect();
+        let mut items = vec![];
+        if colorize {
+            items.push(consts::COLOR_OUTPUT_PRELUDE.into());
+        }
+        if args.fetch.is_some() {
+            items.push(consts::FETCH_PRELUDE.into());
+        }
+        if !args.file.is_empty() {
+            items.push(consts::FILE_PRELUDE.into());
+        }
+        if args.fields.is_some() {
+            items.push(consts::FIELDS_PRELUDE.into());
+        }
+        if args.stdin_bytes {
+            items.push(consts::STDIN_BYTES_PRELUDE.into());
+        }
+        if args.with_time {
+            items.push(consts::WITH_TIME_PRELUDE.into());
+        }
+        if args.with_uom {
+            items.push(consts::WITH_UOM_PRELUDE.into());
+        }
+        if args.state.is_some() {
+            items.push(consts::STATE_PRELUDE.into());
+        }
+        if args.thousands {
+            items.push(consts::THOUSANDS_PRELUDE.into());
+        }
+        if args.smart_display {
+            items.push(consts::SMART_DISPLAY_PRELUDE.into());
+        }
         items.sort();
-        items
+        unstable_features.extend(items);
+        unstable_features
     };
     info!("prelude_items: {:?}", prelude_items);
 
+    /*
+    Work out the substitutions for the templates, beyond the script and prelude.
+    */
+    let template_subs = {
+        let mut subs = HashMap::new();
+        let format = match args.radix.as_deref() {
+            Some("hex") => "{:#x}",
+            Some("oct") => "{:#o}",
+            Some("bin") => "{:#b}",
+            _ if colorize || args.thousands || args.smart_display => "{}",
+            _ if args.color_output => "{:#?}",
+            _ => "{:?}",
+        };
+        subs.insert("format", format.to_owned());
+        subs.insert("bind", args.bind.clone().unwrap_or_else(|| "v".into()));
+        let (print, println) = if args.to_stderr {
+            ("eprint", "eprintln")
+        } else {
+            ("print", "println")
+        };
+        subs.insert("print", print.to_owned());
+        subs.insert("println", println.to_owned());
+        if let Some(ref literal) = script_literal {
+            subs.insert("script_literal", literal.clone());
+        }
+        if let Some(ref encoding) = args.input_encoding {
+            subs.insert("input_encoding", format!("{:?}", encoding));
+        }
+        if let Some(parallel) = args.parallel {
+            subs.insert("parallel", parallel.to_string());
+        }
+        let separator = match args.join {
+            Some(ref sep) => Some(&**sep),
+            None if args.lines => Some(args.sep.as_deref().unwrap_or("\n")),
+            None => None,
+        };
+        if let Some(separator) = separator {
+            subs.insert("separator", format!("{:?}", separator));
+        }
+        for (key, value) in &args.define {
+            if subs.contains_key(&**key) || key == "script" || key == "prelude" {
+                return Err((
+                    Blame::Human,
+                    format!("cannot redefine template substitution `{}`", key),
+                )
+                    .into());
+            }
+            subs.insert(&**key, value.clone());
+        }
+        subs
+    };
+    info!("template_subs: {:?}", template_subs);
+
+    let mut mani_overrides = match args.manifest_override {
+        Some(ref text) => parse_manifest_override(text)?,
+        None => toml::value::Table::new(),
+    };
+    let profile = match args.profile {
+        Some(ref profile) => profile,
+        None if args.debug => "dev",
+        None => "release",
+    };
+    if let Some(ref strip) = args.strip {
+        set_profile_default(&mut mani_overrides, profile, "strip", &**strip);
+    }
+    if let Some(ref panic) = args.panic {
+        set_profile_default(&mut mani_overrides, profile, "panic", &**panic);
+    }
+    if let Some(ref crate_type) = args.crate_type {
+        manifest::set_lib_crate_type(&mut mani_overrides, crate_type, &input);
+    }
+    if let Some(ref name) = args.pkg_name {
+        // Only the package is renamed; the executable is still named after the script, so it ends up in the same place as usual.
+        manifest::set_package_key(&mut mani_overrides, "name", name);
+    }
+    if let Some(ref version) = args.rust_version {
+        manifest::set_package_key(&mut mani_overrides, "rust-version", version);
+    }
+    if let Some(units) = args.codegen_units {
+        set_profile_default(
+            &mut mani_overrides,
+            profile,
+            "codegen-units",
+            i64::from(units),
+        );
+    }
+
     // Work out what to do.
-    let action = decide_action_for(&input, deps, prelude_items, &args)?;
+    let action = decide_action_for(
+        &input,
+        deps,
+        target_deps,
+        prelude_items,
+        project_config.manifest(),
+        mani_overrides,
+        template_subs,
+        &args,
+    )?;
     info!("action: {:?}", action);
 
+    let build_start = Instant::now();
     gen_pkg_and_compile(&input, &action)?;
+    if args.log_timings && !args.gen_pkg_only && matches!(action.build_kind, BuildKind::Normal) {
+        let script = match input.path() {
+            Some(path) => path.display().to_string(),
+            None => action
+                .pkg_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let duration = if action.compile {
+            build_start.elapsed()
+        } else {
+            Duration::from_secs(0)
+        };
+        if let Err(err) = timings::log_build(&script, action.compile, duration) {
+            eprintln!("warning: could not log build timings: {}", err);
+        }
+    }
+
+    let pkg_name = args
+        .pkg_name
+        .clone()
+        .unwrap_or_else(|| input.package_name());
+
+    if args.emit_runner {
+        let cmd = action.cargo("run")?;
+        runner::write_runner(
+            &action.pkg_path,
+            &cmd,
+            &action.manifest_path().to_string_lossy(),
+        )?;
+    }
+
+    if let Some(ref path) = args.emit_binary {
+        let exe_path = get_exe_path(action.build_kind, &action.pkg_path)?;
+        info!("copying {:?} to {:?}", exe_path, path);
+        fs::copy(&exe_path, path)
+            .map_err(MainError::from)
+            .err_tag(format!("could not write executable to `{}`", path))
+            .shift_blame(Blame::Human)?;
+    }
+
+    if let Some(ref name) = args.alias {
+        let script = match (input.path(), &script_literal) {
+            (Some(path), _) => path.display().to_string(),
+            (None, Some(literal)) => format!("--expr {}", literal),
+            (None, None) => action
+                .pkg_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let env = vec![
+            (
+                "CARGO_EVAL_SCRIPT_PATH".to_owned(),
+                input
+                    .path()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "CARGO_EVAL_SAFE_NAME".to_owned(),
+                input.safe_name().to_owned(),
+            ),
+            ("CARGO_EVAL_PKG_NAME".to_owned(), pkg_name.clone()),
+            (
+                "CARGO_EVAL_BASE_PATH".to_owned(),
+                input.base_path().display().to_string(),
+            ),
+        ];
+        let exe_path = get_exe_path(action.build_kind, &action.pkg_path)?;
+        alias::save(name, &exe_path, alias::Alias { script, env })?;
+    }
+
+    if let Some(ref hook) = args.after_build {
+        let exe_path = get_exe_path(action.build_kind, &action.pkg_path)?;
+        run_after_build(hook, &exe_path)?;
+    }
+
+    if let Some(ref kind) = args.emit {
+        println!("{}", emit_path(&input, &action.pkg_path, kind)?.display());
+    }
+
+    if args.crate_type.is_some() {
+        let lib_path = get_exe_path(action.build_kind, &action.pkg_path)?;
+        println!("{}", lib_path.display());
+    }
+
+    if let Some(ref path) = args.emit_tarball {
+        tarball::write_tarball(&action.pkg_path, &pkg_name, Path::new(path))
+            .err_tag(format!("could not write tarball to `{}`", path))
+            .shift_blame(Blame::Human)?;
+    }
+
+    // If we just resolved any dependencies for which we assumed the latest version, let the user know what they actually got.
+    if action.compile {
+        report_resolved_deps(&pkg_name, &action)?;
+    }
+
+    if args.print_deps {
+        print_dep_tree(&action)?;
+    }
+
+    if args.print_features {
+        print_enabled_features(&action)?;
+    }
+
+    if args.print_binary_size {
+        print_binary_size(&action)?;
+    }
+
+    if args.warn_unused_deps {
+        report_unused_deps(&action);
+    }
+
+    // If we just added to the cache, make sure it hasn't grown too large.
+    if let (true, true, Some(max_entries)) =
+        (action.compile, action.using_cache, args.max_cache_entries)
+    {
+        evict_cache_entries(max_entries, &action.pkg_path)?;
+    }
 
     // Once we're done, clean out old packages from the cache.  There's no point if we've already done a full clear, though.
     let _defer_clear = {
@@ -356,28 +1260,85 @@ fn try_main() -> Result<i32> {
 
     // Run it!
     if action.execute {
-        fn hint<F: FnOnce(&mut Command) -> &mut Command>(f: F) -> F {
+        fn hint<F: Fn(&mut Command) -> &mut Command>(f: F) -> F {
             f
         }
+        let script_env = args.env.clone();
+        let fetch_url = args.fetch.clone();
         let add_env = hint(move |cmd| {
             cmd.env(
                 "CARGO_EVAL_SCRIPT_PATH",
                 input.path().unwrap_or_else(|| Path::new("")),
             );
             cmd.env("CARGO_EVAL_SAFE_NAME", input.safe_name());
-            cmd.env("CARGO_EVAL_PKG_NAME", input.package_name());
+            cmd.env("CARGO_EVAL_PKG_NAME", &pkg_name);
             cmd.env("CARGO_EVAL_BASE_PATH", input.base_path());
+            if let Some(ref url) = fetch_url {
+                cmd.env("CARGO_EVAL_FETCH_URL", url);
+            }
+            if let Some(ref files) = files_env {
+                cmd.env("CARGO_EVAL_FILES", files);
+            }
+            if let Some(ref path) = state_env {
+                cmd.env("CARGO_EVAL_STATE", path);
+            }
+            // `--env` wins over anything from a `.env` file.
+            for (key, value) in dotenv_vars.iter().chain(&script_env) {
+                cmd.env(key, value);
+            }
             cmd
         });
 
+        // With `--check`, the tests have to pass before the script gets to run.
+        if args.check {
+            info!("running `cargo test`");
+            let mut cmd = action.cargo("test")?;
+            cmd.args(action.verbosity.cargo_args());
+            add_env(&mut cmd);
+            match cmd
+                .status()
+                .map(|st| st.code().unwrap_or(1))
+                .map_err(|err| explain_cargo_err(err.into()))?
+            {
+                0 => (),
+                n => return Ok(n),
+            }
+        }
+
         if action.build_kind.can_exec_directly() {
             let exe_path = get_exe_path(action.build_kind, &action.pkg_path)?;
             info!("executing {:?}", exe_path);
             match {
                 let mut cmd = Command::new(exe_path);
                 cmd.args(&args.args);
+                if let Some(ref output) = args.output {
+                    let file = fs::File::create(output)
+                        .map_err(MainError::from)
+                        .err_tag(format!("could not create output file `{}`", output))
+                        .shift_blame(Blame::Human)?;
+                    cmd.stdout(file);
+                }
+                if let Some(ref working_dir) = args.working_dir {
+                    cmd.current_dir(working_dir);
+                }
+                // Otherwise, the script shares our stdin, so interactive scripts work.
+                if args.no_stdin {
+                    cmd.stdin(process::Stdio::null());
+                }
+                let sandbox = if args.sandbox {
+                    let mut sandbox = sandbox::Sandbox::new(args.keep_temps)?;
+                    sandbox.apply(&mut cmd)?;
+                    Some(sandbox)
+                } else {
+                    if args.clean_env {
+                        sandbox::clear_env(&mut cmd);
+                    }
+                    None
+                };
                 add_env(&mut cmd);
-                cmd.status().map(|st| st.code().unwrap_or(1))
+                let status = cmd.status().map(|st| st.code().unwrap_or(1));
+                drop(sandbox);
+                status
             }? {
                 0 => (),
                 n => return Ok(n),
@@ -386,8 +1347,13 @@ fn try_main() -> Result<i32> {
             let cmd_name = action.build_kind.exec_command();
             info!("running `cargo {}`", cmd_name);
             let mut cmd = action.cargo(cmd_name)?;
+            cmd.args(action.verbosity.cargo_args());
             add_env(&mut cmd);
-            match cmd.status().map(|st| st.code().unwrap_or(1))? {
+            match cmd
+                .status()
+                .map(|st| st.code().unwrap_or(1))
+                .map_err(|err| explain_cargo_err(err.into()))?
+            {
                 0 => (),
                 n => return Ok(n),
             }
@@ -414,9 +1380,23 @@ fn clean_cache(max_age: u128) -> Result<()> {
                 error!("failed to remove binary cache {:?}: {}", cache_dir, err);
             }
         }
-    }
 
-    let cutoff = platform::current_time() - max_age;
+        let lock_dir = shared_lock_cache_path();
+        if lock_dir.is_dir() {
+            if let Err(err) = fs::remove_dir_all(&lock_dir) {
+                error!("failed to remove shared lockfiles {:?}: {}", lock_dir, err);
+            }
+        }
+
+        let remote_dir = remote_cache_path();
+        if remote_dir.is_dir() {
+            if let Err(err) = fs::remove_dir_all(&remote_dir) {
+                error!("failed to remove fetched scripts {:?}: {}", remote_dir, err);
+            }
+        }
+    }
+
+    let cutoff = platform::current_time() - max_age;
     info!("cutoff:     {:>20?} ms", cutoff);
 
     let cache_dir = script_cache_path();
@@ -442,16 +1422,13 @@ fn clean_cache(max_age: u128) -> Result<()> {
 
             So, instead, we're just going to go by the timestamp on the metadata file *itself*.
             */
-            let meta_mtime = {
-                let meta_path = get_pkg_metadata_path(&path);
-                let meta_file = match fs::File::open(&meta_path) {
-                    Ok(file) => file,
-                    Err(..) => {
-                        info!("couldn't open metadata for {:?}", path);
-                        return true;
-                    }
-                };
-                platform::file_last_modified(&meta_file)
+            let meta_mtime = match pkg_metadata_mtime(&path) {
+                Some(mtime) => mtime,
+                None => {
+                    // This could be a package another instance is still building, so only remove it if it's been sitting around a while.
+                    info!("couldn't open metadata for {:?}", path);
+                    pkg_dir_mtime(&path)
+                }
             };
             info!("meta_mtime: {:>20?} ms", meta_mtime);
 
@@ -469,6 +1446,108 @@ fn clean_cache(max_age: u128) -> Result<()> {
     Ok(())
 }
 
+/**
+Evicts the least recently used packages from the cache, so that at most `max_entries` remain.
+
+Like `clean_cache`, this goes by the timestamp on each package's metadata file, which is rewritten every time the package is used.  The package at `current`, which is about to be run, is always kept, even if the limit (*e.g.* from a project configuration file) is zero.
+*/
+fn evict_cache_entries(max_entries: usize, current: &Path) -> Result<()> {
+    info!("evicting cache entries beyond {}", max_entries);
+
+    let cache_dir = script_cache_path();
+    if !cache_dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut entries = vec![];
+    for child in fs::read_dir(cache_dir)? {
+        let path = child?.path();
+        if path.is_dir() {
+            // Packages without metadata are either broken or still being built; go by the directory instead.
+            let mtime = pkg_metadata_mtime(&path).unwrap_or_else(|| pkg_dir_mtime(&path));
+            entries.push((mtime, path));
+        }
+    }
+
+    let max_entries = max_entries.max(1);
+    if entries.len() <= max_entries {
+        return Ok(());
+    }
+
+    // Newest first, but with the current package at the front, whatever its timestamp says.
+    entries.sort_by(|a, b| b.cmp(a));
+    if let Some(i) = entries
+        .iter()
+        .position(|(_, path)| path.file_name() == current.file_name())
+    {
+        let entry = entries.remove(i);
+        entries.insert(0, entry);
+    }
+    for (_, path) in entries.drain(max_entries..) {
+        for removed in remove_cache_pkg(&path)? {
+            debug!("evicted {:?}", removed);
+        }
+    }
+    Ok(())
+}
+
+/**
+Marks the package at `pkg_path` as just used, by bumping the timestamp on its metadata, which is what cleaning and evicting go by.  Returns `false` if there's no (finished) package there.
+*/
+fn touch_cache_pkg(pkg_path: &Path) -> Result<bool> {
+    let meta_path = get_pkg_metadata_path(pkg_path);
+    if !meta_path.is_file() {
+        return Ok(false);
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&meta_path)?
+        .set_modified(std::time::SystemTime::now())?;
+    Ok(true)
+}
+
+/**
+Returns the last-modified time of the metadata for the package at `pkg_path`, if it has any.
+*/
+fn pkg_metadata_mtime(pkg_path: &Path) -> Option<u128> {
+    fs::File::open(get_pkg_metadata_path(pkg_path))
+        .ok()
+        .map(|file| platform::file_last_modified(&file))
+}
+
+/**
+Returns the last-modified time of the package directory itself, or zero if that can't be determined.
+*/
+fn pkg_dir_mtime(pkg_path: &Path) -> u128 {
+    fs::metadata(pkg_path)
+        .and_then(|md| md.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+#[test]
+fn test_package_name() {
+    let path = PathBuf::from("p");
+    let file = |name| Input::File(name, &path, "", 0);
+    assert_eq!(file("hello-world").package_name(), "hello-world");
+    assert_eq!(file("2fast").package_name(), "_2fast");
+    assert_eq!(file("a.b").package_name(), "a_b");
+    assert_eq!(file("build").package_name(), "build_");
+    assert_eq!(file("builder").package_name(), "builder");
+}
+
+/**
+What the build script is called inside the generated package.  It's not `build.rs`, so it can't collide with a script called that.
+*/
+const BUILD_SCRIPT_NAME: &str = "cargo-eval-build.rs";
+
+/**
+The byte order mark some editors put at the start of UTF-8 files.
+*/
+const UTF8_BOM: char = '\u{feff}';
+
 /**
 Generate and compile a package from the input.
 
@@ -487,8 +1566,12 @@ fn gen_pkg_and_compile(input: &Input, action: &InputAction) -> Result<()> {
     let cleanup_dir: Defer<_, MainError> = Defer::defer(|| {
         // DO NOT try deleting ANYTHING if we're not cleaning up inside our own cache.  We *DO NOT* want to risk killing user files.
         if action.using_cache {
-            info!("cleaning up cache directory {:?}", pkg_path);
-            fs::remove_dir_all(pkg_path)?;
+            if action.keep_temps {
+                eprintln!("kept package directory {}", pkg_path.display());
+            } else {
+                info!("cleaning up cache directory {:?}", pkg_path);
+                fs::remove_dir_all(pkg_path)?;
+            }
         }
         Ok(())
     });
@@ -527,6 +1610,50 @@ fn gen_pkg_and_compile(input: &Input, action: &InputAction) -> Result<()> {
         }
     }
 
+    if let Some(ref build_script) = action.build_script {
+        let build_script_path = pkg_path.join(BUILD_SCRIPT_NAME);
+        let build_script_hash = old_meta.and_then(|m| m.build_script_hash.as_deref());
+        match overwrite_file(&build_script_path, build_script, build_script_hash)? {
+            FileOverwrite::Same => (),
+            FileOverwrite::Changed { new_hash } => {
+                meta.build_script_hash = Some(new_hash);
+            }
+        }
+    } else if old_meta.is_some_and(|m| m.build_script_hash.is_some()) {
+        // Left over from an earlier `--build-script`.
+        let build_script_path = pkg_path.join(BUILD_SCRIPT_NAME);
+        if build_script_path.exists() {
+            info!("removing old build script {:?}", build_script_path);
+            fs::remove_file(&build_script_path)?;
+        }
+    }
+
+    for (name, content) in &action.includes {
+        let old_hash = old_meta.and_then(|m| {
+            m.include_hashes
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, h)| &**h)
+        });
+        match overwrite_file(pkg_path.join(name), content, old_hash)? {
+            FileOverwrite::Same => (),
+            FileOverwrite::Changed { new_hash } => {
+                if let Some(entry) = meta.include_hashes.iter_mut().find(|(n, _)| n == name) {
+                    entry.1 = new_hash;
+                }
+            }
+        }
+    }
+
+    // Anything included last time, but not this time, has to go, or the script would still compile against it.
+    for (name, _) in old_meta.iter().flat_map(|m| &m.include_hashes) {
+        let path = pkg_path.join(name);
+        if !action.includes.iter().any(|(n, _)| n == name) && path.exists() {
+            info!("removing old include {:?}", path);
+            fs::remove_file(&path)?;
+        }
+    }
+
     let meta = meta;
 
     /*
@@ -540,38 +1667,93 @@ fn gen_pkg_and_compile(input: &Input, action: &InputAction) -> Result<()> {
     */
     let mut compile_err = Ok(());
     if action.compile {
+        let shared_lock = if action.use_bincache {
+            shared_lock_path(input, &meta)
+        } else {
+            None
+        };
+        let lock_path = pkg_path.join("Cargo.lock");
+        if let Some(ref shared_lock) = shared_lock {
+            if !lock_path.exists() && shared_lock.is_file() {
+                info!("reusing lockfile {:?}", shared_lock);
+                fs::copy(shared_lock, &lock_path)?;
+            }
+        }
+
+        check_rust_version(mani_str)?;
+        if meta.sanitizer.is_some() {
+            check_nightly()?;
+        }
+
+        if action.retry > 0 {
+            let cmd = cargo(
+                "fetch",
+                &mani_path.to_string_lossy(),
+                action.use_bincache,
+                &meta,
+                action.rustc_wrapper.as_deref(),
+            )?;
+            fetch_deps(cmd, action.retry)?;
+        }
+
         info!("compiling...");
         let mut cmd = cargo(
             "build",
             &*mani_path.to_string_lossy(),
             action.use_bincache,
             &meta,
+            action.rustc_wrapper.as_deref(),
         )?;
+        cmd.args(action.verbosity.cargo_args());
+        cmd.args(emit_rustc_args(input, pkg_path, &meta)?);
+
+        // So that if the build has to be stopped, everything Cargo started can be stopped along with it.  `wait_timeout` passes Ctrl-C on, since the group isn't in the foreground any more.
+        #[cfg(unix)]
+        {
+            if action.max_compile_time.is_some() {
+                use std::os::unix::process::CommandExt;
+                cmd.process_group(0);
+            }
+        }
 
+        // There's no point asking for verbose output, and then hiding it.
         #[cfg(feature = "suppress-cargo-output")]
         macro_rules! get_status {
             ($cmd:expr) => {
-                util::suppress_child_output(
-                    &mut $cmd,
-                    ::std::time::Duration::from_millis(CARGO_OUTPUT_TIMEOUT),
-                )?
-                .status()
+                if action.verbosity == Verbosity::Normal {
+                    util::suppress_child_output(
+                        &mut $cmd,
+                        ::std::time::Duration::from_millis(CARGO_OUTPUT_TIMEOUT),
+                        action.strip_ansi,
+                    )
+                    .map_err(explain_cargo_err)?
+                    .status_timeout(action.max_compile_time)
+                } else {
+                    $cmd.spawn().and_then(|mut child| {
+                        util::wait_timeout(&mut child, action.max_compile_time)
+                    })
+                }
             };
         }
 
         #[cfg(not(feature = "suppress-cargo-output"))]
         macro_rules! get_status {
             ($cmd:expr) => {
-                $cmd.status()
+                $cmd.spawn()
+                    .and_then(|mut child| util::wait_timeout(&mut child, action.max_compile_time))
             };
         }
 
         compile_err = get_status!(cmd)
-            .map_err(Into::<MainError>::into)
-            .and_then(|st| match st.code() {
-                Some(0) => Ok(()),
-                Some(st) => Err(format!("cargo failed with status {}", st).into()),
-                None => Err("cargo failed".into()),
+            .map_err(|err| explain_cargo_err(err.into()))
+            .and_then(|st| match st.map(|st| st.code()) {
+                Some(Some(0)) => Ok(()),
+                Some(Some(st)) => Err(format!("cargo failed with status {}", st).into()),
+                Some(None) => Err("cargo failed".into()),
+                None => Err(MainError::Other(
+                    Blame::Human,
+                    Box::new(error::CompileTimeout::new(action.max_compile_time.unwrap())),
+                )),
             });
 
         // Drop out now if compilation failed.
@@ -579,6 +1761,15 @@ fn gen_pkg_and_compile(input: &Input, action: &InputAction) -> Result<()> {
             return Err(err);
         }
 
+        if let Some(ref shared_lock) = shared_lock {
+            info!("saving lockfile to {:?}", shared_lock);
+            let saved = fs::create_dir_all(shared_lock.parent().unwrap())
+                .and_then(|_| fs::copy(&lock_path, shared_lock));
+            if let Err(err) = saved {
+                warn!("could not save lockfile to {:?}: {}", shared_lock, err);
+            }
+        }
+
         // Find out and cache what the executable was called.
         let _ = cargo_target(
             input,
@@ -586,6 +1777,7 @@ fn gen_pkg_and_compile(input: &Input, action: &InputAction) -> Result<()> {
             &*mani_path.to_string_lossy(),
             action.use_bincache,
             &meta,
+            action.rustc_wrapper.as_deref(),
         )?;
 
         if action.use_bincache {
@@ -598,16 +1790,1049 @@ fn gen_pkg_and_compile(input: &Input, action: &InputAction) -> Result<()> {
         }
     }
 
-    // Write out metadata *now*.  Remember that we check the timestamp in the metadata, *not* on the executable.
-    if action.emit_metadata {
-        info!("emitting metadata...");
-        write_pkg_metadata(pkg_path, &meta)?;
-    }
-
-    info!("disarming pkg dir cleanup...");
-    cleanup_dir.disarm();
+    // Write out metadata *now*.  Remember that we check the timestamp in the metadata, *not* on the executable.
+    if action.emit_metadata {
+        info!("emitting metadata...");
+        write_pkg_metadata(pkg_path, &meta)?;
+    }
+
+    info!("disarming pkg dir cleanup...");
+    cleanup_dir.disarm();
+
+    compile_err
+}
+
+/**
+Gets the `--dep` specs out of a `--deps-file`: one per line, ignoring blank lines and `#` comments.
+*/
+fn parse_deps_file(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(Into::into)
+        .collect()
+}
+
+#[test]
+fn test_parse_deps_file() {
+    let text = "# shared deps\n\nboolinator\n  regex=1.5+unicode  # for parsing\r\n";
+    assert_eq!(
+        parse_deps_file(text),
+        vec!["boolinator", "regex=1.5+unicode"]
+    );
+}
+
+/**
+Parses `--dep` specs into a list of `(name, version)` pairs.
+
+We want to do a few things:
+
+- Sort them so that they hash consistently.
+- Check for duplicates.
+- Expand `pkg` into `pkg=*`.
+- Turn `pkg=ver+feat,...` into a table with those features.
+- If `smart_deps` is set, turn on the usual features for crates in `consts::SMART_DEP_FEATURES`, unless the spec already gives features or a full table.
+*/
+fn parse_deps(specs: &[String], smart_deps: bool) -> Result<Vec<(String, String)>> {
+    use std::collections::hash_map::Entry::{Occupied, Vacant};
+
+    let mut deps: HashMap<String, String> = HashMap::new();
+    for dep in specs {
+        let (name, version, features) = split_dep_spec(dep)?;
+
+        let features = features.or_else(|| {
+            consts::SMART_DEP_FEATURES
+                .iter()
+                .find(|&&(smart_name, _)| smart_deps && smart_name == name)
+                .filter(|_| !version.starts_with('{'))
+                .map(|&(_, features)| features.to_vec())
+        });
+        let version = match features {
+            Some(features) => {
+                let default_features = !features.contains(&NO_DEFAULT_FEATURES);
+                let features = features
+                    .into_iter()
+                    .filter(|&f| f != NO_DEFAULT_FEATURES)
+                    .collect::<Vec<_>>();
+                let mut table = format!("{{ version = {}", toml::Value::from(version));
+                if !default_features {
+                    table.push_str(", default-features = false");
+                }
+                if !features.is_empty() {
+                    table.push_str(&format!(", features = {}", toml::Value::from(features)));
+                }
+                table.push_str(" }");
+                table
+            }
+            None => version.to_owned(),
+        };
+        let version = &*version;
+
+        match deps.entry(name.into()) {
+            Vacant(ve) => {
+                ve.insert(version.into());
+            }
+            Occupied(oe) => {
+                // This is *only* a problem if the versions don't match.  We won't try to do anything clever in terms of upgrading or resolving or anything... exact match or go home.
+                let existing = oe.get();
+                if version != existing {
+                    return Err((
+                        Blame::Human,
+                        format!(
+                            "conflicting versions for dependency '{}': '{}', '{}'",
+                            name, existing, version
+                        ),
+                    )
+                        .into());
+                }
+            }
+        }
+    }
+
+    // Sort and turn into a regular vec.
+    let mut deps: Vec<(String, String)> = deps.into_iter().collect();
+    deps.sort();
+    Ok(deps)
+}
+
+/**
+The pseudo-feature in a `--dep` spec which turns off default features.
+
+Real feature names can't start with `-`, so this can't clash with one.
+*/
+const NO_DEFAULT_FEATURES: &str = "-default";
+
+/**
+Splits a single `--dep` spec into its name, version and features.
+
+The grammar is `NAME[=VERSION][+FEATURE,...]`, where a missing version means `*`, and a feature of `-default` turns off default features.  If the version is a table (*i.e.* starts with `{`), it's taken as-is, and can't have features.
+*/
+fn split_dep_spec(spec: &str) -> Result<(&str, &str, Option<Vec<&str>>)> {
+    let (name, version) = match spec.find('=') {
+        Some(i) => (&spec[..i], &spec[i + 1..]),
+        None => (spec, ""),
+    };
+
+    let (name, version, features) = if version.starts_with('{') {
+        (name, version, None)
+    } else {
+        let (rest, features) = match spec.find('+') {
+            Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+            None => (spec, None),
+        };
+        match rest.find('=') {
+            Some(i) => (&rest[..i], &rest[i + 1..], features),
+            None => (rest, "*", features),
+        }
+    };
+
+    if name.is_empty() {
+        return Err((Blame::Human, "cannot have empty dependency package name").into());
+    }
+
+    if version.is_empty() {
+        return Err((Blame::Human, "cannot have empty dependency version").into());
+    }
+
+    let features = match features {
+        None => None,
+        Some(features) => {
+            let features = features.split(',').collect::<Vec<_>>();
+            let is_valid = |f: &str| {
+                f == NO_DEFAULT_FEATURES
+                    || (!f.is_empty()
+                        && !f.starts_with('-')
+                        && f.chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "-_./:?".contains(c)))
+            };
+            if let Some(bad) = features.iter().find(|f| !is_valid(f)) {
+                return Err((
+                    Blame::Human,
+                    format!("invalid feature `{}` in dependency `{}`", bad, spec),
+                )
+                    .into());
+            }
+            Some(features)
+        }
+    };
+
+    Ok((name, version, features))
+}
+
+#[test]
+fn test_split_dep_spec() {
+    assert_eq!(split_dep_spec("serde").unwrap(), ("serde", "*", None));
+    assert_eq!(split_dep_spec("serde=1").unwrap(), ("serde", "1", None));
+    assert_eq!(
+        split_dep_spec("serde+derive").unwrap(),
+        ("serde", "*", Some(vec!["derive"]))
+    );
+    assert_eq!(
+        split_dep_spec("serde=1.0+derive,rc").unwrap(),
+        ("serde", "1.0", Some(vec!["derive", "rc"]))
+    );
+    assert_eq!(
+        split_dep_spec("serde={ version = \"1+x\" }").unwrap(),
+        ("serde", "{ version = \"1+x\" }", None)
+    );
+
+    assert!(split_dep_spec("=1").is_err());
+    assert!(split_dep_spec("+derive").is_err());
+    assert!(split_dep_spec("serde=").is_err());
+    assert!(split_dep_spec("serde=+derive").is_err());
+    assert!(split_dep_spec("serde=1+").is_err());
+    assert!(split_dep_spec("serde=1+derive,,rc").is_err());
+    assert!(split_dep_spec("serde=1+de rive").is_err());
+    assert!(split_dep_spec("serde=1+-derive").is_err());
+}
+
+/**
+Parses `--dep-target` arguments into a sorted list of `(target, name, version)` triples.
+
+Each argument is `TARGET=SPEC`, where `TARGET` is either a `cfg(...)` expression or a target triple, and `SPEC` is anything `--dep` accepts.  Specs for the same target are checked against one another just like `--dep`s are.
+*/
+fn parse_target_deps(args: &[String], smart_deps: bool) -> Result<Vec<(String, String, String)>> {
+    let mut by_target: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for arg in args {
+        let (target, spec) = split_dep_target(arg)?;
+        by_target.entry(target).or_default().push(spec.into());
+    }
+
+    let mut target_deps = vec![];
+    for (target, specs) in by_target {
+        for (name, version) in parse_deps(&specs, smart_deps)? {
+            target_deps.push((target.to_owned(), name, version));
+        }
+    }
+    Ok(target_deps)
+}
+
+/**
+Splits a single `--dep-target` argument into its target and dependency spec.
+
+A `cfg(...)` expression can contain `=` itself, so it runs up to its matching closing parenthesis.
+*/
+fn split_dep_target(arg: &str) -> Result<(&str, &str)> {
+    let bad_target = || -> MainError {
+        (
+            Blame::Human,
+            format!(
+                "invalid `--dep-target` `{}`: expected `cfg(...)=SPEC` or `TRIPLE=SPEC`",
+                arg
+            ),
+        )
+            .into()
+    };
+
+    let end = if arg.starts_with("cfg(") {
+        let mut depth = 0;
+        let mut in_str = false;
+        let mut end = None;
+        for (i, c) in arg.char_indices() {
+            match c {
+                '"' => in_str = !in_str,
+                '(' if !in_str => depth += 1,
+                ')' if !in_str => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(i + 1);
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+        let end = end.ok_or_else(bad_target)?;
+        let inner = &arg["cfg(".len()..end - 1];
+        let is_valid = |c: char| c.is_ascii_alphanumeric() || " _-.,=\"()".contains(c);
+        if inner.trim().is_empty() || !inner.chars().all(is_valid) {
+            return Err(bad_target());
+        }
+        end
+    } else {
+        let end = arg.find('=').ok_or_else(bad_target)?;
+        let is_valid = |c: char| c.is_ascii_alphanumeric() || "_-.".contains(c);
+        if end == 0 || !arg[..end].chars().all(is_valid) {
+            return Err(bad_target());
+        }
+        end
+    };
+
+    match arg[end..].strip_prefix('=') {
+        Some(spec) if !spec.is_empty() => Ok((&arg[..end], spec)),
+        _ => Err(bad_target()),
+    }
+}
+
+#[test]
+fn test_split_dep_target() {
+    assert_eq!(
+        split_dep_target("cfg(windows)=winapi=0.3").unwrap(),
+        ("cfg(windows)", "winapi=0.3")
+    );
+    assert_eq!(
+        split_dep_target("cfg(target_os = \"linux\")=libc").unwrap(),
+        ("cfg(target_os = \"linux\")", "libc")
+    );
+    assert_eq!(
+        split_dep_target("cfg(all(unix, not(target_os = \"macos\")))=nix+fs").unwrap(),
+        ("cfg(all(unix, not(target_os = \"macos\")))", "nix+fs")
+    );
+    assert_eq!(
+        split_dep_target("x86_64-pc-windows-msvc=winapi").unwrap(),
+        ("x86_64-pc-windows-msvc", "winapi")
+    );
+
+    assert!(split_dep_target("cfg(windows)").is_err());
+    assert!(split_dep_target("cfg(windows)=").is_err());
+    assert!(split_dep_target("cfg(windows=winapi").is_err());
+    assert!(split_dep_target("cfg()=winapi").is_err());
+    assert!(split_dep_target("cfg(windows)winapi").is_err());
+    assert!(split_dep_target("cfg(a; b)=winapi").is_err());
+    assert!(split_dep_target("=winapi").is_err());
+    assert!(split_dep_target("not a triple=winapi").is_err());
+}
+
+#[test]
+fn test_parse_deps_no_default_features() {
+    let specs = vec![
+        "serde=1+-default,derive".to_owned(),
+        "tokio+-default".to_owned(),
+    ];
+    assert_eq!(
+        parse_deps(&specs, true).unwrap(),
+        vec![
+            (
+                "serde".to_owned(),
+                r#"{ version = "1", default-features = false, features = ["derive"] }"#.to_owned()
+            ),
+            (
+                "tokio".to_owned(),
+                r#"{ version = "*", default-features = false }"#.to_owned()
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_parse_deps_features() {
+    let specs = vec!["serde=1+derive".to_owned(), "tokio+rt".to_owned()];
+    assert_eq!(
+        parse_deps(&specs, true).unwrap(),
+        vec![
+            (
+                "serde".to_owned(),
+                r#"{ version = "1", features = ["derive"] }"#.to_owned()
+            ),
+            (
+                "tokio".to_owned(),
+                r#"{ version = "*", features = ["rt"] }"#.to_owned()
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_parse_deps_smart() {
+    let specs = vec!["tokio".to_owned(), "time=0.1".to_owned()];
+    assert_eq!(
+        parse_deps(&specs, false).unwrap(),
+        vec![
+            ("time".to_owned(), "0.1".to_owned()),
+            ("tokio".to_owned(), "*".to_owned())
+        ]
+    );
+    assert_eq!(
+        parse_deps(&specs, true).unwrap(),
+        vec![
+            ("time".to_owned(), "0.1".to_owned()),
+            (
+                "tokio".to_owned(),
+                r#"{ version = "*", features = ["full"] }"#.to_owned()
+            )
+        ]
+    );
+
+    // An explicit table is left alone.
+    let specs = vec![r#"reqwest={ version = "0.11" }"#.to_owned()];
+    assert_eq!(
+        parse_deps(&specs, true).unwrap(),
+        vec![("reqwest".to_owned(), r#"{ version = "0.11" }"#.to_owned())]
+    );
+}
+
+/**
+Parses the argument to `--manifest-override`, which is either some TOML, or `@` followed by the path to a TOML file.
+*/
+fn parse_manifest_override(arg: &str) -> Result<toml::value::Table> {
+    let (text, source) = match arg.strip_prefix('@') {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(MainError::from)
+                .err_tag(format!("could not read manifest override `{}`", path))
+                .shift_blame(Blame::Human)?;
+            (text, format!("manifest override `{}`", path))
+        }
+        None => (arg.to_owned(), "--manifest-override".to_owned()),
+    };
+    toml::from_str(&text).map_err(|err| {
+        (
+            Blame::Human,
+            format!("could not parse {} as TOML: {}", source, err),
+        )
+            .into()
+    })
+}
+
+/**
+Sets `key` in the named Cargo profile of the manifest overrides, unless `--manifest-override` already set it.
+*/
+fn set_profile_default<V>(overrides: &mut toml::value::Table, profile: &str, key: &str, value: V)
+where
+    V: Into<toml::Value>,
+{
+    let profiles = overrides
+        .entry("profile")
+        .or_insert_with(|| toml::value::Table::new().into());
+    if let Some(profiles) = profiles.as_table_mut() {
+        let profile = profiles
+            .entry(profile)
+            .or_insert_with(|| toml::value::Table::new().into());
+        if let Some(profile) = profile.as_table_mut() {
+            profile.entry(key).or_insert_with(|| value.into());
+        }
+    }
+}
+
+/**
+If `expr` is a closure, returns how many arguments it takes.
+
+This only looks at the closure's parameter list, so it can be fooled by a closure that's immediately called, or used as part of a larger expression.  Those get printed as usual, or fail to compile.
+*/
+fn closure_arity(expr: &str) -> Option<usize> {
+    let expr = expr.trim_start();
+    let expr = match expr.strip_prefix("move") {
+        Some(rest) if rest.starts_with(|c: char| c.is_whitespace() || c == '|') => {
+            rest.trim_start()
+        }
+        _ => expr,
+    };
+    if expr.starts_with("||") {
+        return Some(0);
+    }
+    let params = expr.strip_prefix('|')?;
+
+    let mut depth = 0usize;
+    let mut arity = 0;
+    let mut in_param = false;
+    for c in params.chars() {
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => return Some(arity),
+            ',' if depth == 0 => {
+                in_param = false;
+                continue;
+            }
+            _ if c.is_whitespace() => continue,
+            _ => (),
+        }
+        if !in_param {
+            in_param = true;
+            arity += 1;
+        }
+    }
+    None
+}
+
+#[test]
+fn test_closure_arity() {
+    assert_eq!(closure_arity("|x: i32| x * 2"), Some(1));
+    assert_eq!(closure_arity("  move |x: String| x.len()"), Some(1));
+    assert_eq!(closure_arity("move|x: u8| x"), Some(1));
+    assert_eq!(closure_arity("|| 5"), Some(0));
+    assert_eq!(closure_arity("|a: i32, b: i32| a + b"), Some(2));
+    assert_eq!(closure_arity("|(a, b): (i32, i32)| a + b"), Some(1));
+    assert_eq!(closure_arity("|v: Vec<(u8, u8)>,| v.len()"), Some(1));
+    assert_eq!(closure_arity("1 | 2"), None);
+    assert_eq!(closure_arity("moved | 2"), None);
+    assert_eq!(closure_arity("|x: i32"), None);
+}
+
+/**
+Reads the variables out of a `.env` file for `--dotenv`.  Lines that can't be parsed get a warning, but don't stop the script from running.
+*/
+fn load_dotenv(path: &Path) -> Result<Vec<(String, String)>> {
+    let text = fs::read_to_string(path)
+        .map_err(MainError::from)
+        .err_tag(format!("could not read `{}`", path.display()))
+        .shift_blame(Blame::Human)?;
+    let (vars, malformed) = util::parse_dotenv(&text);
+    for line in malformed {
+        eprintln!(
+            "warning: {}:{}: ignoring malformed line",
+            path.display(),
+            line
+        );
+    }
+    Ok(vars)
+}
+
+/**
+Fetches the package's dependencies by running `cmd`, a `cargo fetch`, trying again up to `retries` times if it fails because of what looks like a network problem.  Anything else is reported straight away, since trying again won't help.
+
+This is done before building, so the build itself only ever fails for reasons that are the script's fault.
+*/
+fn fetch_deps(mut cmd: Command, retries: u32) -> Result<()> {
+    let mut delay = Duration::from_millis(consts::RETRY_INITIAL_DELAY_MS);
+    let mut attempt: u32 = 0;
+    loop {
+        attempt = attempt.saturating_add(1);
+        info!("fetching dependencies (attempt {})...", attempt);
+        let out = cmd.output().map_err(|err| explain_cargo_err(err.into()))?;
+        if out.status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        if attempt > retries || !is_network_error(&stderr) {
+            eprint!("{}", stderr);
+            return Err(match out.status.code() {
+                Some(st) => format!("cargo failed with status {}", st).into(),
+                None => "cargo failed".into(),
+            });
+        }
+
+        eprintln!(
+            "warning: fetching dependencies failed because of the network; trying again in {:?} ({} of {})",
+            delay, attempt, retries
+        );
+        std::thread::sleep(delay);
+        delay = next_retry_delay(delay);
+    }
+}
+
+/**
+How long to wait before the next `--retry`, given how long we waited before this one: twice as long, up to `RETRY_MAX_DELAY_MS`.
+*/
+fn next_retry_delay(delay: Duration) -> Duration {
+    delay
+        .saturating_mul(2)
+        .min(Duration::from_millis(consts::RETRY_MAX_DELAY_MS))
+}
+
+#[test]
+fn test_next_retry_delay() {
+    let mut delay = Duration::from_millis(consts::RETRY_INITIAL_DELAY_MS);
+    for _ in 0..100 {
+        delay = next_retry_delay(delay);
+    }
+    assert_eq!(delay, Duration::from_millis(consts::RETRY_MAX_DELAY_MS));
+    assert_eq!(
+        next_retry_delay(Duration::from_secs(1)),
+        Duration::from_secs(2)
+    );
+    assert_eq!(
+        next_retry_delay(Duration::MAX),
+        Duration::from_millis(consts::RETRY_MAX_DELAY_MS)
+    );
+}
+
+/**
+Does Cargo's output look like it failed because of the network?
+*/
+fn is_network_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    consts::NETWORK_ERROR_HINTS
+        .iter()
+        .any(|hint| stderr.contains(hint))
+}
+
+#[test]
+fn test_is_network_error() {
+    assert!(is_network_error(
+        "error: failed to download from `https://static.crates.io/crates/ureq/2.9.1/download`\n\n\
+        Caused by:\n  [6] Couldn't resolve host name (Could not resolve host: static.crates.io)\n"
+    ));
+    assert!(is_network_error(
+        "warning: spurious network error (3 tries remaining): [28] Timeout was reached\n"
+    ));
+    assert!(!is_network_error(
+        "error: no matching package named `not-a-real-crate` found\n"
+    ));
+}
+
+/**
+Runs the `--after-build` command, with the executable's path in `CARGO_EVAL_BIN`.
+*/
+fn run_after_build(hook: &str, exe_path: &Path) -> Result<()> {
+    info!("running after-build command {:?}", hook);
+    let status = platform::shell_command(hook)
+        .env("CARGO_EVAL_BIN", exe_path)
+        .status()
+        .map_err(MainError::from)
+        .err_tag(format!("could not run after-build command `{}`", hook))
+        .shift_blame(Blame::Human)?;
+    if !status.success() {
+        return Err((
+            Blame::Human,
+            format!("after-build command `{}` failed with {}", hook, status),
+        )
+            .into());
+    }
+    Ok(())
+}
+
+/**
+Runs `source` through the `--preprocessor` command, if there is one, and returns what it wrote to stdout.
+
+The command is split into words like a shell would, but is *not* run by a shell.
+*/
+fn preprocess(preprocessor: Option<&str>, source: String) -> Result<String> {
+    use std::process::Stdio;
+
+    let preprocessor = match preprocessor {
+        Some(preprocessor) => preprocessor,
+        None => return Ok(source),
+    };
+    info!("preprocessing with {:?}", preprocessor);
+
+    let words = util::split_shell_words(preprocessor);
+    let (program, args) = words
+        .split_first()
+        .ok_or((Blame::Human, "preprocessor command is empty"))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(MainError::from)
+        .err_tag(format!("could not run preprocessor `{}`", preprocessor))
+        .shift_blame(Blame::Human)?;
+
+    // Write from another thread, so a preprocessor that doesn't read all its input before writing can't deadlock us.
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(source.as_bytes()));
+    let out = child.wait_with_output()?;
+    // If it stopped reading early, that's its business.
+    let _ = writer.join();
+
+    if !out.status.success() {
+        return Err((
+            Blame::Human,
+            format!("preprocessor `{}` failed with {}", preprocessor, out.status),
+        )
+            .into());
+    }
+
+    String::from_utf8(out.stdout).map_err(|_| {
+        (
+            Blame::Human,
+            format!("preprocessor `{}` did not output UTF-8", preprocessor),
+        )
+            .into()
+    })
+}
+
+/**
+Sets up logging.  `RUST_LOG` wins if it's set; otherwise, `-v` turns on our own logging.
+*/
+fn init_logging(verbosity: Verbosity) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if std::env::var_os("RUST_LOG").is_none() {
+        if let Some(level) = verbosity.log_level() {
+            builder.filter_module(module_path!(), level);
+        }
+    }
+    builder.init();
+}
+
+/**
+Returns the package name part of a `--dep` spec.
+*/
+fn dep_spec_name(spec: &str) -> &str {
+    spec.split(&['=', '+'][..]).next().unwrap_or(spec)
+}
+
+/**
+Looks up an `--async-runtime` by name; clap has already made sure it's one we know.
+*/
+fn async_runtime(name: &str) -> (&'static str, &'static str, &'static str) {
+    *consts::ASYNC_RUNTIMES
+        .iter()
+        .find(|(runtime, _, _)| *runtime == name)
+        .expect("unknown async runtime")
+}
+
+/**
+Reports the versions Cargo resolved for dependencies given without a version (*i.e.* those we assumed to be `*`).
+
+This reads the `Cargo.lock` generated next to the package manifest.
+*/
+fn report_resolved_deps(pkg_name: &str, action: &InputAction) -> Result<()> {
+    let latest_deps = action
+        .metadata
+        .deps
+        .iter()
+        .filter(|(_, ver)| is_latest_version(ver))
+        .map(|(name, _)| &**name)
+        .collect::<Vec<_>>();
+
+    if latest_deps.is_empty() {
+        return Ok(());
+    }
+
+    let lock_path = action.pkg_path.join("Cargo.lock");
+    let lock_str = match fs::read_to_string(&lock_path) {
+        Ok(s) => s,
+        Err(err) => {
+            info!("couldn't read {:?}: {}", lock_path, err);
+            return Ok(());
+        }
+    };
+
+    let resolved = resolved_dep_versions(&lock_str, pkg_name, &latest_deps)?;
+    for (name, version) in resolved {
+        eprintln!("resolved dependency `{}` to version {}", name, version);
+    }
+
+    Ok(())
+}
+
+/**
+Works out if a dependency's version, as it ends up in the manifest, asks for whatever's latest.  That's either a bare `*`, or a table (*e.g.* from giving features) whose `version` is `*`.
+*/
+fn is_latest_version(version: &str) -> bool {
+    if !version.starts_with('{') {
+        return version == "*";
+    }
+    toml::from_str::<toml::value::Table>(&format!("dep = {}", version))
+        .ok()
+        .and_then(|table| {
+            table
+                .get("dep")?
+                .get("version")
+                .map(|v| v.as_str() == Some("*"))
+        })
+        .unwrap_or(false)
+}
+
+#[test]
+fn test_is_latest_version() {
+    assert!(is_latest_version("*"));
+    assert!(!is_latest_version("1.2"));
+    assert!(is_latest_version(r#"{ version = "*", features = ["rt"] }"#));
+    assert!(!is_latest_version(
+        r#"{ version = "1", features = ["derive"] }"#
+    ));
+    assert!(!is_latest_version(r#"{ path = "../foo" }"#));
+}
+
+/**
+Prints the dependency tree of the generated package to stderr, courtesy of `cargo tree`.
+*/
+fn print_dep_tree(action: &InputAction) -> Result<()> {
+    let mut cmd = action.cargo("tree")?;
+
+    info!("running {:?}", cmd);
+    let out = cmd.output().map_err(|err| explain_cargo_err(err.into()))?;
+    let stderr = &mut std::io::stderr();
+    stderr.write_all(&out.stdout)?;
+    stderr.write_all(&out.stderr)?;
+    if !out.status.success() {
+        return Err(format!("`cargo tree` failed with {}", out.status).into());
+    }
+    Ok(())
+}
+
+/**
+Prints the features Cargo turned on for each crate in the package's dependency graph to stderr.
+
+This is worked out by `cargo metadata`, so it takes embedded manifests, `--features`, optional dependencies and default features into account.
+*/
+fn print_enabled_features(action: &InputAction) -> Result<()> {
+    #[derive(Deserialize)]
+    struct Package {
+        id: String,
+        name: String,
+        version: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Node {
+        id: String,
+        features: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Resolve {
+        nodes: Vec<Node>,
+    }
+
+    #[derive(Deserialize)]
+    struct Metadata {
+        packages: Vec<Package>,
+        resolve: Resolve,
+    }
+
+    let mut cmd = action.cargo("metadata")?;
+    cmd.arg("--format-version").arg("1");
+
+    info!("running {:?}", cmd);
+    let out = cmd.output().map_err(|err| explain_cargo_err(err.into()))?;
+    if !out.status.success() {
+        std::io::stderr().write_all(&out.stderr)?;
+        return Err(format!("`cargo metadata` failed with {}", out.status).into());
+    }
+    let metadata: Metadata = serde_json::from_slice(&out.stdout).map_err(|err| err.to_string())?;
+
+    let mut lines = metadata
+        .resolve
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            let pkg = metadata.packages.iter().find(|p| p.id == node.id)?;
+            let features = if node.features.is_empty() {
+                "(none)".to_owned()
+            } else {
+                node.features.join(", ")
+            };
+            Some(format!("{} {}: {}", pkg.name, pkg.version, features))
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+    for line in lines {
+        eprintln!("{}", line);
+    }
+    Ok(())
+}
+
+/**
+Prints the size of the package's compiled executable to stderr.
+*/
+fn print_binary_size(action: &InputAction) -> Result<()> {
+    let exe_path = get_exe_path(action.build_kind, &action.pkg_path)?;
+    let size = fs::metadata(&exe_path)?.len();
+    eprintln!("binary size: {} bytes ({})", size, human_size(size));
+    Ok(())
+}
+
+/**
+Formats a number of bytes using the largest binary unit that keeps it above one.
+*/
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[test]
+fn test_human_size() {
+    assert_eq!(human_size(0), "0 B");
+    assert_eq!(human_size(1023), "1023 B");
+    assert_eq!(human_size(1024), "1.0 KiB");
+    assert_eq!(human_size(1536), "1.5 KiB");
+    assert_eq!(human_size(3 * 1024 * 1024), "3.0 MiB");
+    assert_eq!(human_size(5 << 40), "5.0 TiB");
+    assert_eq!(human_size(2048 << 40), "2048.0 TiB");
+}
+
+/**
+Warns about any dependencies which the generated source doesn't seem to mention.
+*/
+fn report_unused_deps(action: &InputAction) {
+    for (name, _) in &action.metadata.deps {
+        if !source_mentions_crate(&action.script, name) {
+            eprintln!("warning: dependency `{}` appears to be unused", name);
+        }
+    }
+}
+
+/**
+Does `source` appear to refer to the crate for package `pkg_name`?
+
+This doesn't parse anything; it just looks for `extern crate NAME`, `use NAME` or `NAME::`, so it can be fooled by comments and strings, and doesn't know about renamed crates.
+*/
+fn source_mentions_crate(source: &str, pkg_name: &str) -> bool {
+    let crate_name = regex::escape(&pkg_name.replace('-', "_"));
+    let re = regex::Regex::new(&format!(
+        r"\bextern\s+crate\s+{0}\b|\buse\s+(::)?{0}\b|\b{0}\s*::",
+        crate_name
+    ))
+    .unwrap();
+    re.is_match(source)
+}
+
+#[test]
+fn test_source_mentions_crate() {
+    assert!(source_mentions_crate("extern crate time;", "time"));
+    assert!(source_mentions_crate("use ::time;", "time"));
+    assert!(source_mentions_crate("let t = time::now();", "time"));
+    assert!(source_mentions_crate(
+        "use serde_json::Value;",
+        "serde-json"
+    ));
+    assert!(!source_mentions_crate("let time = 0;", "time"));
+    assert!(!source_mentions_crate("use chrono::Local;", "time"));
+    assert!(!source_mentions_crate("overtime::now()", "time"));
+}
+
+/**
+The subset of a `Cargo.lock` file we care about.
+*/
+#[derive(Deserialize)]
+struct LockFile {
+    #[serde(default)]
+    package: Vec<LockPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/**
+Looks up the versions of the given direct dependencies of `pkg_name` in the contents of a `Cargo.lock` file.
+*/
+fn resolved_dep_versions<'a>(
+    lock_str: &str,
+    pkg_name: &str,
+    deps: &[&'a str],
+) -> Result<Vec<(&'a str, String)>> {
+    let lock: LockFile = toml::from_str(lock_str).map_err(|err| err.to_string())?;
+
+    let root_deps = lock
+        .package
+        .iter()
+        .find(|p| p.name == pkg_name)
+        .map(|p| &p.dependencies[..])
+        .unwrap_or(&[]);
+
+    let mut resolved = vec![];
+    for &dep in deps {
+        let mut candidates = lock.package.iter().filter(|p| p.name == dep);
+        let version = match (candidates.next(), candidates.next()) {
+            (Some(p), None) => Some(p.version.clone()),
+            // There are several versions in the graph; the root package's dependency list says which one is ours.
+            (Some(_), Some(_)) => root_deps.iter().find_map(|d| {
+                let mut parts = d.split(' ');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(ver)) if name == dep => Some(ver.into()),
+                    _ => None,
+                }
+            }),
+            (None, _) => None,
+        };
+        if let Some(version) = version {
+            resolved.push((dep, version));
+        }
+    }
+
+    Ok(resolved)
+}
+
+/**
+Pins any dependency specs given without a version to the exact version recorded in a `Cargo.lock`.
+
+Dependencies the lockfile doesn't mention, or which appear in it more than once, are left alone for Cargo to resolve as usual.
+*/
+fn pin_locked_deps(lock_str: &str, specs: &[String]) -> Result<Vec<String>> {
+    let mut pinned = vec![];
+    for spec in specs {
+        let (name, version, features) = split_dep_spec(spec)?;
+        if version != "*" {
+            pinned.push(spec.clone());
+            continue;
+        }
+
+        // There's no root package to disambiguate with, so only unambiguous entries get pinned.
+        match resolved_dep_versions(lock_str, "", &[name])?.pop() {
+            Some((_, locked)) => {
+                info!("pinning {} to {} from lockfile", name, locked);
+                let mut spec = format!("{}=={}", name, locked);
+                if let Some(features) = features {
+                    spec.push('+');
+                    spec.push_str(&features.join(","));
+                }
+                pinned.push(spec);
+            }
+            None => pinned.push(spec.clone()),
+        }
+    }
+    Ok(pinned)
+}
+
+#[test]
+fn test_pin_locked_deps() {
+    let lock = r#"
+[[package]]
+name = "boolinator"
+version = "2.4.0"
+
+[[package]]
+name = "time"
+version = "0.1.44"
+
+[[package]]
+name = "time"
+version = "0.2.0"
+"#;
+    let specs = ["boolinator+foo,bar", "time", "regex", "boolinator=2.3"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        pin_locked_deps(lock, &specs).unwrap(),
+        vec![
+            "boolinator==2.4.0+foo,bar",
+            "time",
+            "regex",
+            "boolinator=2.3"
+        ]
+    );
+}
 
-    compile_err
+#[test]
+fn test_resolved_dep_versions() {
+    let lock = r#"
+[[package]]
+name = "boolinator"
+version = "2.4.0"
+
+[[package]]
+name = "expr"
+version = "0.1.0"
+dependencies = [
+ "boolinator",
+ "time 0.2.0",
+]
+
+[[package]]
+name = "time"
+version = "0.1.42"
+
+[[package]]
+name = "time"
+version = "0.2.0"
+"#;
+
+    assert_eq!(
+        resolved_dep_versions(lock, "expr", &["boolinator", "time", "missing"]).unwrap(),
+        vec![("boolinator", "2.4.0".into()), ("time", "0.2.0".into())]
+    );
 }
 
 /**
@@ -656,8 +2881,32 @@ struct InputAction {
     /// The script source.
     script: String,
 
+    /// The build script source, if there is one.
+    build_script: Option<String>,
+
+    /// File names and contents of extra modules to put alongside the script.
+    includes: Vec<(String, String)>,
+
     /// Did the user ask to run tests or benchmarks?
     build_kind: BuildKind,
+
+    /// How chatty should Cargo be?
+    verbosity: Verbosity,
+
+    /// Leave the package directory behind if the build fails, rather than cleaning it up?
+    keep_temps: bool,
+
+    /// How long to let the build run before giving up on it.
+    max_compile_time: Option<Duration>,
+
+    /// How many times to try fetching dependencies again, if the network lets us down.
+    retry: u32,
+
+    /// Remove ANSI escape sequences from Cargo's output, when it's been held back?
+    strip_ansi: bool,
+
+    /// What to set `RUSTC_WRAPPER` to for Cargo, from `--rustc-wrapper`.
+    rustc_wrapper: Option<OsString>,
 }
 
 impl InputAction {
@@ -671,6 +2920,7 @@ impl InputAction {
             &*self.manifest_path().to_string_lossy(),
             self.use_bincache,
             &self.metadata,
+            self.rustc_wrapper.as_deref(),
         )
     }
 }
@@ -692,23 +2942,53 @@ struct PackageMetadata {
     /// Template used.
     template: Option<String>,
 
+    /// Variant of the template used.
+    template_variant: Option<String>,
+
     /// Was the script compiled in debug mode?
     debug: bool,
 
+    /// Custom Cargo profile the script was compiled with, if any.
+    profile: Option<String>,
+
     /// Sorted list of dependencies.
     deps: Vec<(String, String)>,
 
+    /// Sorted list of target-specific dependencies, as `(target, name, version)`.
+    target_deps: Vec<(String, String, String)>,
+
     /// Sorted list of injected prelude items.
     prelude: Vec<String>,
 
     /// Cargo features
     features: Option<String>,
 
+    /// Additional flags passed to `rustc`.
+    rustflags: Vec<String>,
+
+    /// Was the script built with `--reproducible`?
+    reproducible: bool,
+
+    /// Sanitizer the script was built with, if any.
+    sanitizer: Option<String>,
+
+    /// What `rustc` was asked to `--emit`, besides the executable.
+    emit: Option<String>,
+
+    /// Kind of library the script was built as, if it wasn't an executable.
+    crate_type: Option<String>,
+
     /// Hash of the generated `Cargo.toml` file.
     manifest_hash: String,
 
     /// Hash of the generated source file.
     script_hash: String,
+
+    /// Hash of the build script, if there is one.
+    build_script_hash: Option<String>,
+
+    /// File names and hashes of included modules.
+    include_hashes: Vec<(String, String)>,
 }
 
 impl PackageMetadata {
@@ -721,10 +3001,15 @@ impl PackageMetadata {
 /**
 For the given input, this constructs the package metadata and checks the cache to see what should be done.
 */
+#[allow(clippy::too_many_arguments)]
 fn decide_action_for(
     input: &Input,
     deps: Vec<(String, String)>,
+    target_deps: Vec<(String, String, String)>,
     prelude: Vec<String>,
+    mut mani_defaults: toml::value::Table,
+    mut mani_overrides: toml::value::Table,
+    template_subs: HashMap<&str, String>,
     args: &Args,
 ) -> Result<InputAction> {
     let (pkg_path, using_cache) = args
@@ -732,29 +3017,73 @@ fn decide_action_for(
         .as_ref()
         .map(|p| (p.into(), false))
         .unwrap_or_else(|| {
-            // This can't fail.  Seriously, we're *fucked* if we can't work this out.
-            let cache_path = script_cache_path();
-            info!("cache_path: {:?}", cache_path);
-
-            let id = {
-                let deps_iter = deps.iter().map(|&(ref n, ref v)| (n as &str, v as &str));
-
-                // Again, also fucked if we can't work this out.
-                input.compute_id(deps_iter).unwrap()
-            };
-            info!("id: {:?}", id);
-
-            (cache_path.join(&id), true)
+            let hash = args.hash.unwrap_or_default();
+            (
+                cache_pkg_path(input, &deps, &target_deps, &args.rustflags, hash),
+                true,
+            )
         });
     info!("pkg_path: {:?}", pkg_path);
     info!("using_cache: {:?}", using_cache);
 
     info!("splitting input...");
-    let (mani_str, script_str) = manifest::split_input(input, &deps, &prelude)?;
+    let build_script = match args.build_script {
+        Some(ref path) => {
+            let text = fs::read_to_string(path)
+                .map_err(MainError::from)
+                .err_tag(format!("could not read build script `{}`", path))
+                .shift_blame(Blame::Human)?;
+
+            // This has to be absolute, or it'll be taken as relative to the script.  `--manifest-override` still gets the last word.
+            let build_path = std::env::current_dir()?
+                .join(&pkg_path)
+                .join(BUILD_SCRIPT_NAME);
+            let package = mani_overrides
+                .entry("package")
+                .or_insert_with(|| toml::value::Table::new().into());
+            if let Some(package) = package.as_table_mut() {
+                package
+                    .entry("build")
+                    .or_insert_with(|| build_path.to_string_lossy().into_owned().into());
+            }
+
+            Some(text)
+        }
+        None => {
+            // Otherwise, Cargo goes looking for a `build.rs` on its own, and finds the script itself, if that's what it's called.  The script's manifest can still ask for a build script.
+            let package = mani_defaults
+                .entry("package")
+                .or_insert_with(|| toml::value::Table::new().into());
+            if let Some(package) = package.as_table_mut() {
+                package.entry("build").or_insert(false.into());
+            }
+            None
+        }
+    };
+
+    let includes = read_includes(input, &args.include)?;
+
+    manifest::add_target_deps(&mut mani_overrides, &target_deps)?;
+
+    let (mani_str, script_str) = manifest::split_input(
+        input,
+        &deps,
+        &prelude,
+        &mani_defaults,
+        &mani_overrides,
+        &template_subs,
+        args.template_variant.as_deref(),
+        args.raw,
+        args.panic_location,
+    )?;
 
     // Forcibly override some flags based on build kind.
     let (debug, force, build_only) = match args.build_kind {
-        BuildKind::Normal => (args.debug, args.force, args.build_only),
+        BuildKind::Normal => (
+            args.debug,
+            args.force,
+            args.build_only || args.emit.is_some() || args.crate_type.is_some(),
+        ),
         BuildKind::Test => (true, false, false),
         BuildKind::Bench => (false, false, false),
     };
@@ -772,12 +3101,25 @@ fn decide_action_for(
             path,
             modified: mtime,
             template: template.map(Into::into),
+            template_variant: args.template_variant.clone(),
             debug,
+            profile: args.profile.clone(),
             deps,
+            target_deps,
             prelude,
             features: args.features.clone(),
+            rustflags: args.rustflags.clone(),
+            reproducible: args.reproducible,
+            sanitizer: args.sanitizer.clone(),
+            emit: args.emit.clone(),
+            crate_type: args.crate_type.clone(),
             manifest_hash: hash_str(&mani_str),
             script_hash: hash_str(&script_str),
+            build_script_hash: build_script.as_ref().map(|s| hash_str(s)),
+            include_hashes: includes
+                .iter()
+                .map(|(name, content)| (name.clone(), hash_str(content)))
+                .collect(),
         }
     };
     info!("input_meta: {:?}", input_meta);
@@ -790,12 +3132,24 @@ fn decide_action_for(
         execute: !build_only,
         pkg_path,
         using_cache,
-        use_bincache: args.use_bincache.unwrap_or(using_cache),
+        // The package's own target directory is inside its cache folder, so it's only used by scripts with the same cache key.  Rustflags change how every dependency is built, so there'd be nothing to share, and builds with different flags would take turns overwriting the same executable.
+        use_bincache: !args.isolated
+            && args
+                .use_bincache
+                .unwrap_or(using_cache && args.rustflags.is_empty()),
         metadata: input_meta,
         old_metadata: None,
         manifest: mani_str,
         script: script_str,
+        build_script,
+        includes,
         build_kind: args.build_kind,
+        verbosity: args.verbosity,
+        keep_temps: args.keep_temps,
+        max_compile_time: args.max_compile_time,
+        retry: args.retry,
+        strip_ansi: args.no_color_errors || !platform::stderr_is_tty(),
+        rustc_wrapper: rustc_wrapper(args)?,
     };
 
     macro_rules! bail {
@@ -821,19 +3175,66 @@ fn decide_action_for(
         }
     }
 
+    // For `--why-rebuild`.  If we were forced, that's the only reason worth giving.
+    if args.why_rebuild && force {
+        eprintln!("rebuilding because of `--force`");
+    }
+    let why_rebuild = |reason: &str| {
+        if args.why_rebuild && !force {
+            eprintln!("rebuilding because {}", reason);
+        }
+    };
+
     let cache_meta = match get_pkg_metadata(&action.pkg_path) {
         Ok(meta) => meta,
         Err(err) => {
             info!("recompiling because: failed to load metadata");
             debug!("get_pkg_metadata error: {}", err);
+            if get_pkg_metadata_path(&action.pkg_path).exists() {
+                why_rebuild("its cached metadata couldn't be read");
+            } else {
+                why_rebuild("it hasn't been built before");
+            }
             bail!(compile: true)
         }
     };
 
+    /*
+    With `--rebuild-on mtime`, we don't care whether the content has changed: if the script's modification time is the same as when it was cached, we trust whatever is already in the package.  This only makes sense for files; expressions and loops don't *have* a modification time.
+    */
+    if args.rebuild_on == RebuildOn::Mtime && action.metadata.modified.is_some() {
+        let mut meta = action.metadata.clone();
+        meta.manifest_hash = cache_meta.manifest_hash.clone();
+        meta.script_hash = cache_meta.script_hash.clone();
+        if meta == cache_meta {
+            let script_path = action.pkg_path.join(format!("{}.rs", input.safe_name()));
+            match (
+                fs::read_to_string(action.manifest_path()),
+                fs::read_to_string(script_path),
+            ) {
+                (Ok(manifest), Ok(script)) => {
+                    info!("script modification time matches cache; not checking content");
+                    action.metadata = meta;
+                    action.manifest = manifest;
+                    action.script = script;
+                }
+                _ => {
+                    info!("recompiling because: cached package files could not be read");
+                    why_rebuild("its cached package couldn't be read");
+                    bail!(old_metadata: Some(cache_meta), compile: true)
+                }
+            }
+        }
+    }
+
     if cache_meta != action.metadata {
         info!("recompiling because: metadata did not match");
         debug!("input metadata: {:?}", action.metadata);
         debug!("cache metadata: {:?}", cache_meta);
+        why_rebuild(&format!(
+            "of changes to: {}",
+            changed_inputs(&cache_meta, &action.metadata).join(", ")
+        ));
         bail!(old_metadata: Some(cache_meta), compile: true)
     }
 
@@ -842,15 +3243,35 @@ fn decide_action_for(
     /*
     Next test: does the executable exist at all?
     */
-    let exe_exists = match get_exe_path(action.build_kind, &action.pkg_path) {
-        Ok(exe_path) => exe_path.is_file(),
-        Err(_) => false,
+    let exe_path = get_exe_path(action.build_kind, &action.pkg_path).ok();
+    let exe_exists = match exe_path {
+        Some(ref exe_path) => exe_path.is_file(),
+        None => false,
     };
     if !exe_exists {
         info!("recompiling because: executable doesn't exist or isn't a file");
+        why_rebuild("its executable is missing");
         bail!(compile: true)
     }
 
+    // An executable left in the shared binary cache could be overwritten by another script at any moment, so it's not to be trusted if we're not using it.
+    if let (false, Some(exe_path)) = (action.use_bincache, exe_path) {
+        if exe_path.starts_with(binary_cache_path()) {
+            info!("recompiling because: executable is in the shared binary cache");
+            why_rebuild("its executable is in the shared binary cache");
+            bail!(compile: true)
+        }
+    }
+
+    // Same goes for anything we were asked to `--emit`.  Cargo won't know it's missing, so it has to be made to rebuild.
+    if let Some(ref kind) = action.metadata.emit {
+        if !emit_path(input, &action.pkg_path, kind)?.is_file() {
+            info!("recompiling because: emitted file doesn't exist");
+            why_rebuild("the file it emits is missing");
+            bail!(compile: true, force_compile: true)
+        }
+    }
+
     /*
     Finally: check to see if `{exe_path}.meta-hash` exists and contains a hash that matches the metadata.  Yes, this is somewhat round-about, but we need to do this to account for cases where Cargo's target directory has been set to a fixed, shared location.
 
@@ -860,6 +3281,7 @@ fn decide_action_for(
         let exe_meta_hash_path = get_meta_hash_path(action.use_bincache, &action.pkg_path).unwrap();
         if !exe_meta_hash_path.is_file() {
             info!("recompiling because: meta hash doesn't exist or isn't a file");
+            why_rebuild("its executable is missing");
             bail!(compile: true, force_compile: true)
         }
         let exe_meta_hash = {
@@ -871,6 +3293,7 @@ fn decide_action_for(
         let meta_hash = action.metadata.sha1_hash();
         if meta_hash != exe_meta_hash {
             info!("recompiling because: meta hash doesn't match");
+            why_rebuild("its executable was last built from something else");
             bail!(compile: true, force_compile: true)
         }
     }
@@ -879,6 +3302,146 @@ fn decide_action_for(
     Ok(action)
 }
 
+/**
+Works out which inputs of a cached script changed, between the metadata it was built with and the metadata it has now, for `--why-rebuild`.
+
+The manifest is only blamed when nothing else explains it changing, since most of the other inputs end up in it.
+*/
+fn changed_inputs(old: &PackageMetadata, new: &PackageMetadata) -> Vec<&'static str> {
+    let mut changed = vec![];
+    if old.path != new.path {
+        changed.push("script path");
+    }
+    if old.script_hash != new.script_hash {
+        changed.push("script");
+    } else if old.modified != new.modified {
+        changed.push("script modification time");
+    }
+    if old.build_script_hash != new.build_script_hash {
+        changed.push("build script");
+    }
+    if old.include_hashes != new.include_hashes {
+        changed.push("included modules");
+    }
+    if (&old.template, &old.template_variant, &old.prelude)
+        != (&new.template, &new.template_variant, &new.prelude)
+    {
+        changed.push("template");
+    }
+    if (&old.deps, &old.target_deps) != (&new.deps, &new.target_deps) {
+        changed.push("dependencies");
+    }
+    if old.features != new.features {
+        changed.push("features");
+    }
+    if (old.debug, &old.profile) != (new.debug, &new.profile) {
+        changed.push("profile");
+    }
+    if (&old.rustflags, old.reproducible, &old.sanitizer)
+        != (&new.rustflags, new.reproducible, &new.sanitizer)
+    {
+        changed.push("rustc flags");
+    }
+    if (&old.emit, &old.crate_type) != (&new.emit, &new.crate_type) {
+        changed.push("output kind");
+    }
+    if changed.is_empty() && old.manifest_hash != new.manifest_hash {
+        changed.push("manifest");
+    }
+    changed
+}
+
+#[test]
+fn test_changed_inputs() {
+    let old = PackageMetadata {
+        path: Some("script.rs".into()),
+        modified: Some(1),
+        template: None,
+        template_variant: None,
+        debug: false,
+        profile: None,
+        deps: vec![("regex".into(), "1".into())],
+        target_deps: vec![],
+        prelude: vec![],
+        features: None,
+        rustflags: vec![],
+        reproducible: false,
+        sanitizer: None,
+        emit: None,
+        crate_type: None,
+        manifest_hash: "m1".into(),
+        script_hash: "s1".into(),
+        build_script_hash: None,
+        include_hashes: vec![],
+    };
+    assert!(changed_inputs(&old, &old).is_empty());
+
+    let mut new = old.clone();
+    new.deps.push(("time".into(), "0.1".into()));
+    new.manifest_hash = "m2".into();
+    assert_eq!(changed_inputs(&old, &new), vec!["dependencies"]);
+
+    new.modified = Some(2);
+    new.debug = true;
+    assert_eq!(
+        changed_inputs(&old, &new),
+        vec!["script modification time", "dependencies", "profile"]
+    );
+
+    let mut new = old.clone();
+    new.modified = Some(2);
+    new.script_hash = "s2".into();
+    assert_eq!(changed_inputs(&old, &new), vec!["script"]);
+
+    let mut new = old.clone();
+    new.manifest_hash = "m2".into();
+    assert_eq!(changed_inputs(&old, &new), vec!["manifest"]);
+}
+
+/**
+Reads the files given with `--include`, returning the name each should have in the package, along with its contents.
+
+Each file becomes a module named after its file stem, so the names have to be distinct, and can't clash with the script itself.  They can't be called `build`, either, since Cargo would take `build.rs` for a build script.
+*/
+fn read_includes(input: &Input, paths: &[String]) -> Result<Vec<(String, String)>> {
+    let mut includes: Vec<(String, String)> = vec![];
+    for path in paths {
+        let stem = Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .ok_or_else(|| (Blame::Human, format!("`{}` is not a file", path)))?;
+        let name = format!("{}.rs", stem);
+        if stem == "build" {
+            return Err((
+                Blame::Human,
+                format!(
+                    "cannot include `{}`: Cargo would treat `build.rs` as a build script; \
+                    use `--build-script` for that, or rename the module",
+                    path
+                ),
+            )
+                .into());
+        }
+        if stem == input.safe_name() || includes.iter().any(|(n, _)| *n == name) {
+            return Err((
+                Blame::Human,
+                format!(
+                    "cannot include `{}`: module `{}` already exists",
+                    path, stem
+                ),
+            )
+                .into());
+        }
+
+        let text = fs::read_to_string(path)
+            .map_err(MainError::from)
+            .err_tag(format!("could not read included file `{}`", path))
+            .shift_blame(Blame::Human)?;
+        includes.push((name, text));
+    }
+    Ok(includes)
+}
+
 /**
 Figures out where the output executable for the input should be.
 
@@ -967,18 +3530,211 @@ where
     Ok(())
 }
 
+/**
+Returns the path to the package directory the input gets in the cache.
+*/
+fn cache_pkg_path(
+    input: &Input,
+    deps: &[(String, String)],
+    target_deps: &[(String, String, String)],
+    rustflags: &[String],
+    hash: HashAlgorithm,
+) -> PathBuf {
+    // This can't fail.  Seriously, we're *fucked* if we can't work this out.
+    let cache_path = script_cache_path();
+    info!("cache_path: {:?}", cache_path);
+
+    let id = {
+        // Target-specific dependencies are told apart from the rest by prefixing them with their target.
+        let target_deps = target_deps
+            .iter()
+            .map(|(t, n, v)| (format!("{}:{}", t, n), v))
+            .collect::<Vec<_>>();
+        let deps_iter = deps
+            .iter()
+            .map(|&(ref n, ref v)| (n as &str, v as &str))
+            .chain(target_deps.iter().map(|(n, v)| (n as &str, v as &str)));
+
+        // Again, also fucked if we can't work this out.
+        input.compute_id(deps_iter, rustflags, hash).unwrap()
+    };
+    info!("id: {:?}", id);
+
+    cache_path.join(&id)
+}
+
+/**
+Removes the cache entry for a single input: the generated package, and the compiled executable if it lives in the shared binary cache.
+
+Returns the paths which were removed.
+*/
+fn clean_cache_entry(
+    input: &Input,
+    deps: &[(String, String)],
+    target_deps: &[(String, String, String)],
+    rustflags: &[String],
+    hash: HashAlgorithm,
+) -> Result<Vec<PathBuf>> {
+    let pkg_path = cache_pkg_path(input, deps, target_deps, rustflags, hash);
+    if !pkg_path.is_dir() {
+        return Ok(vec![]);
+    }
+    remove_cache_pkg(&pkg_path)
+}
+
+/**
+Removes a package from the cache, along with its compiled executable if it lives in the shared binary cache.
+
+Returns the paths which were removed.
+*/
+fn remove_cache_pkg(pkg_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+
+    /*
+    Only remove the executable if it's in the binary cache; we *do not* want to go deleting things elsewhere.  Packages with the same name (like all expressions) share an executable in the binary cache, so leave it alone if any other package is still using it.
+    */
+    if let Ok(exe_path) = get_exe_path(BuildKind::Normal, pkg_path) {
+        if exe_path.starts_with(binary_cache_path())
+            && exe_path.is_file()
+            && !exe_used_by_other_pkgs(&exe_path, pkg_path)?
+        {
+            info!("removing {:?}", exe_path);
+            fs::remove_file(&exe_path)?;
+            removed.push(exe_path);
+        }
+    }
+
+    info!("removing {:?}", pkg_path);
+    fs::remove_dir_all(pkg_path)?;
+    removed.push(pkg_path.to_owned());
+
+    Ok(removed)
+}
+
+/**
+Returns `true` if any cached package other than `pkg_path` runs the executable at `exe_path`.
+*/
+fn exe_used_by_other_pkgs(exe_path: &Path, pkg_path: &Path) -> Result<bool> {
+    for child in fs::read_dir(script_cache_path())? {
+        let other_path = child?.path();
+        if other_path == pkg_path || !other_path.is_dir() {
+            continue;
+        }
+        if let Ok(other_exe_path) = get_exe_path(BuildKind::Normal, &other_path) {
+            if other_exe_path == exe_path {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
 /**
 Returns the path to the cache directory.
 */
 fn script_cache_path() -> PathBuf {
-    app::cache_dir().unwrap().join("scripts")
+    app::cache_dir().join("scripts")
+}
+
+/**
+Returns the path to the directory `--remote` scripts are fetched into.
+*/
+fn remote_cache_path() -> PathBuf {
+    app::cache_dir().join("remote")
+}
+
+/**
+Returns the path to the directory of lockfiles shared between expressions and loops.
+*/
+fn shared_lock_cache_path() -> PathBuf {
+    app::cache_dir().join("locks")
+}
+
+/**
+Works out where to keep the lockfile shared by every expression (or loop) with the same dependencies as this one.
+
+Everything in the cache builds into the same target directory, so Cargo will happily reuse compiled dependencies from one expression to the next... but only if they resolve to exactly the same versions.  Giving every package with the same set of dependencies the same lockfile makes sure they do, so a new expression only needs to compile itself.  Scripts don't get this, since they keep their own package (and lockfile) around anyway.
+*/
+fn shared_lock_path(input: &Input, meta: &PackageMetadata) -> Option<PathBuf> {
+    match *input {
+        Input::File(..) => None,
+        Input::Expr(..) | Input::Loop(..) => {
+            let mut digest = hash_str(&shared_lock_key(meta));
+            digest.truncate(consts::ID_DIGEST_LEN_MAX);
+            let name = format!("{}-{}.lock", input.package_name(), digest);
+            Some(shared_lock_cache_path().join(name))
+        }
+    }
+}
+
+/**
+Everything that decides what goes in a shared lockfile: the dependencies (features and all), the target-specific ones, and the package's own features.
+*/
+fn shared_lock_key(meta: &PackageMetadata) -> String {
+    let mut key = meta
+        .deps
+        .iter()
+        .map(|(name, ver)| format!("{}={};", name, ver))
+        .collect::<String>();
+    for (target, name, ver) in &meta.target_deps {
+        key.push_str(&format!("{}:{}={};", target, name, ver));
+    }
+    if let Some(ref features) = meta.features {
+        key.push_str(&format!("features={};", features));
+    }
+    key
+}
+
+#[test]
+fn test_shared_lock_key() {
+    let meta = PackageMetadata {
+        path: None,
+        modified: None,
+        template: None,
+        template_variant: None,
+        debug: false,
+        profile: None,
+        deps: vec![("serde".into(), "1".into())],
+        target_deps: vec![],
+        prelude: vec![],
+        features: None,
+        rustflags: vec![],
+        reproducible: false,
+        sanitizer: None,
+        emit: None,
+        crate_type: None,
+        manifest_hash: "m".into(),
+        script_hash: "s".into(),
+        build_script_hash: None,
+        include_hashes: vec![],
+    };
+    assert_eq!(shared_lock_key(&meta), "serde=1;");
+
+    // The script itself doesn't come into it.
+    let mut other = meta.clone();
+    other.script_hash = "t".into();
+    assert_eq!(shared_lock_key(&other), shared_lock_key(&meta));
+
+    let mut other = meta.clone();
+    other.deps[0].1 = "{ version = \"1\", default-features = false }".into();
+    assert_ne!(shared_lock_key(&other), shared_lock_key(&meta));
+
+    let mut other = meta.clone();
+    other
+        .target_deps
+        .push(("cfg(windows)".into(), "winapi".into(), "0.3".into()));
+    assert_ne!(shared_lock_key(&other), shared_lock_key(&meta));
+
+    let mut other = meta.clone();
+    other.features = Some("extra".into());
+    assert_ne!(shared_lock_key(&other), shared_lock_key(&meta));
 }
 
 /**
 Returns the path to the binary cache directory.
 */
 fn binary_cache_path() -> PathBuf {
-    app::cache_dir().unwrap().join("bin")
+    app::cache_dir().join("bin")
 }
 
 /**
@@ -1070,6 +3826,8 @@ impl<'a> Input<'a> {
 
     /**
     Return the package name for the input.  This should be a valid Rust identifier.
+
+    Cargo won't have a binary called the same as one of the directories it puts in the target directory, so those get an underscore on the end.
     */
     pub fn package_name(&self) -> String {
         let name = self.safe_name();
@@ -1090,6 +3848,9 @@ impl<'a> Input<'a> {
             }
         }
 
+        if ["build", "deps", "examples", "incremental"].contains(&&*r) {
+            r.push('_');
+        }
         r
     }
 
@@ -1111,16 +3872,31 @@ impl<'a> Input<'a> {
     /**
     Compute the package ID for the input.  This is used as the name of the cache folder into which the Cargo package will be generated.
     */
-    pub fn compute_id<'dep, DepIt>(&self, deps: DepIt) -> Result<OsString>
+    pub fn compute_id<'dep, DepIt>(
+        &self,
+        deps: DepIt,
+        rustflags: &[String],
+        hash: HashAlgorithm,
+    ) -> Result<OsString>
     where
         DepIt: IntoIterator<Item = (&'dep str, &'dep str)>,
     {
-        use shaman::digest::Digest;
-        use shaman::sha1::Sha1;
         use Input::*;
 
+        // The flags change what's built, so they get an entry of their own.  They're left out when there aren't any, so existing IDs stay the same.
+        let hash_rustflags = |hasher: &mut dyn cache_key::KeyHasher| {
+            if !rustflags.is_empty() {
+                hasher.input_str("rustflags:");
+                for flag in rustflags {
+                    hasher.input_str(flag);
+                    hasher.input_str("\x1f");
+                }
+                hasher.input_str(";");
+            }
+        };
+
         let hash_deps = || {
-            let mut hasher = Sha1::new();
+            let mut hasher = hash.hasher();
             for dep in deps {
                 hasher.input_str("dep=");
                 hasher.input_str(dep.0);
@@ -1133,10 +3909,11 @@ impl<'a> Input<'a> {
 
         match *self {
             File(name, path, _, _) => {
-                let mut hasher = Sha1::new();
+                let mut hasher = hash.hasher();
 
                 // Hash the path to the script.
                 hasher.input_str(&path.to_string_lossy());
+                hash_rustflags(&mut *hasher);
                 let mut digest = hasher.result_str();
                 digest.truncate(consts::ID_DIGEST_LEN_MAX);
 
@@ -1153,6 +3930,7 @@ impl<'a> Input<'a> {
                 hasher.input_str("template:");
                 hasher.input_str(template.unwrap_or(""));
                 hasher.input_str(";");
+                hash_rustflags(&mut *hasher);
 
                 hasher.input_str(&content);
                 let mut digest = hasher.result_str();
@@ -1169,6 +3947,7 @@ impl<'a> Input<'a> {
                 // Make sure to include the [non-]presence of the `--count` flag in the flag, since it changes the actual generated script output.
                 hasher.input_str("count:");
                 hasher.input_str(if count { "true;" } else { "false;" });
+                hash_rustflags(&mut *hasher);
 
                 hasher.input_str(&content);
                 let mut digest = hasher.result_str();
@@ -1194,6 +3973,25 @@ fn hash_str(s: &str) -> String {
     hasher.result_str()
 }
 
+/**
+Returns the SHA-256 hash of `s`, for checking scripts against a hash given by the user.
+*/
+fn sha256_str(s: &str) -> String {
+    use shaman::digest::Digest;
+    use shaman::sha2::Sha256;
+    let mut hasher = Sha256::new();
+    hasher.input_str(s);
+    hasher.result_str()
+}
+
+#[test]
+fn test_sha256_str() {
+    assert_eq!(
+        sha256_str("abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
 enum FileOverwrite {
     Same,
     Changed { new_hash: String },
@@ -1228,7 +4026,14 @@ fn cargo(
     manifest: &str,
     use_bincache: bool,
     meta: &PackageMetadata,
+    rustc_wrapper: Option<&OsStr>,
 ) -> Result<Command> {
+    // Flags for `--emit` have to go to `rustc` for the script alone; see `emit_rustc_args`.
+    let cmd_name = match cmd_name {
+        "build" if meta.emit.is_some() => "rustc",
+        cmd_name => cmd_name,
+    };
+
     let mut cmd = Command::new("cargo");
     cmd.arg(cmd_name).arg("--manifest-path").arg(manifest);
 
@@ -1240,18 +4045,230 @@ fn cargo(
         cmd.env("CARGO_TARGET_DIR", binary_cache_path());
     }
 
-    // Block `--release` on `bench`.
-    if !meta.debug && cmd_name != "bench" {
-        cmd.arg("--release");
+    if let Some(wrapper) = rustc_wrapper {
+        cmd.env("RUSTC_WRAPPER", wrapper);
     }
 
-    if let Some(ref features) = meta.features {
-        cmd.arg("--features").arg(features);
+    // `fetch`, `tree` and `metadata` don't build anything, so don't take a profile; `fetch` doesn't take features either.
+    if !matches!(cmd_name, "fetch" | "tree" | "metadata") {
+        if let Some(ref profile) = meta.profile {
+            cmd.arg("--profile").arg(profile);
+        } else if !meta.debug && cmd_name != "bench" {
+            // Block `--release` on `bench`.
+            cmd.arg("--release");
+        }
+    }
+
+    if cmd_name != "fetch" {
+        if let Some(ref features) = meta.features {
+            cmd.arg("--features").arg(features);
+        }
+    }
+
+    let mut rustflags = meta.rustflags.clone();
+    if meta.reproducible {
+        rustflags.extend(reproducible_rustflags(Path::new(manifest), use_bincache)?);
+        // Respect a timestamp someone's already picked.
+        if std::env::var_os("SOURCE_DATE_EPOCH").is_none() {
+            cmd.env("SOURCE_DATE_EPOCH", "0");
+        }
+    }
+
+    // Giving the target explicitly keeps the sanitizer flags away from build scripts and proc macros, which would otherwise fall over.
+    if let Some(ref sanitizer) = meta.sanitizer {
+        rustflags.push(format!("-Zsanitizer={}", sanitizer));
+        // `metadata` only knows `--filter-platform`.
+        if cmd_name != "metadata" {
+            cmd.arg("--target").arg(env!("CARGO_EVAL_TARGET"));
+        }
+    }
+
+    // This takes precedence over `RUSTFLAGS`, and doesn't need the flags to be re-quoted.
+    if !rustflags.is_empty() {
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"));
     }
 
     Ok(cmd)
 }
 
+/**
+The flags `--reproducible` adds, so the paths of the package, the target directory and Cargo's home directory (where dependencies' sources live) don't end up in the executable.
+*/
+fn reproducible_rustflags(manifest: &Path, use_bincache: bool) -> Result<Vec<String>> {
+    let cwd = std::env::current_dir()?;
+    // The target directory is inside the package, unless the binary cache is used.
+    let mut prefixes = vec![(
+        cwd.join(manifest.parent().unwrap_or_else(|| Path::new(""))),
+        "/cargo-eval/package",
+    )];
+    if use_bincache {
+        prefixes.push((cwd.join(binary_cache_path()), "/cargo-eval/target"));
+    }
+    let cargo_home = match std::env::var_os("CARGO_HOME") {
+        Some(path) => Some(cwd.join(path)),
+        None => dirs::home_dir().map(|home| home.join(".cargo")),
+    };
+    if let Some(cargo_home) = cargo_home {
+        prefixes.push((cargo_home, "/cargo"));
+    }
+
+    Ok(prefixes
+        .into_iter()
+        .map(|(from, to)| format!("--remap-path-prefix={}={}", from.display(), to))
+        .collect())
+}
+
+/**
+Where `rustc` writes what it was asked to `--emit`.
+*/
+fn emit_path(input: &Input, pkg_path: &Path, kind: &str) -> Result<PathBuf> {
+    let ext = match kind {
+        "asm" => "s",
+        "llvm-ir" => "ll",
+        "mir" => "mir",
+        _ => unreachable!(),
+    };
+    // `rustc` doesn't run in our current directory, so this has to be absolute.
+    Ok(std::env::current_dir()?
+        .join(pkg_path)
+        .join(format!("{}.{}", input.safe_name(), ext)))
+}
+
+/**
+Checks that the `rustc` Cargo is going to use is at least as new as the manifest's `rust-version`, if it has one.
+
+Cargo does check this itself, but only once it's already resolved (and possibly downloaded) all the dependencies, and it's not very clear about what to do about it.
+*/
+fn check_rust_version(mani_str: &str) -> Result<()> {
+    let manifest: toml::Value = toml::from_str(mani_str).map_err(|err| err.to_string())?;
+    let required = match manifest
+        .get("package")
+        .and_then(|p| p.get("rust-version"))
+        .and_then(|v| v.as_str())
+    {
+        Some(required) => required,
+        None => return Ok(()),
+    };
+    let required_version = util::parse_rust_version(required).ok_or_else(|| -> MainError {
+        (
+            Blame::Human,
+            format!("`{}` is not a valid Rust version", required),
+        )
+            .into()
+    })?;
+
+    let (rustc, version_str) = rustc_version()?;
+    let version = version_str.split_whitespace().nth(1).unwrap_or("");
+    info!("rustc version: {:?}, required: {:?}", version, required);
+    match util::parse_rust_version(version) {
+        Some(version) if version < required_version => Err((
+            Blame::Human,
+            format!(
+                "this script needs Rust {} or newer, but `{}` is {}; \
+                update it (e.g. with `rustup update`), or pick a newer toolchain",
+                required,
+                rustc.to_string_lossy(),
+                version_str.trim()
+            ),
+        )
+            .into()),
+        // If we can't tell, let Cargo sort it out.
+        _ => Ok(()),
+    }
+}
+
+/**
+Works out the `RUSTC_WRAPPER` builds get: the one from `--rustc-wrapper`, or else whatever's already in the environment.
+
+A wrapper given as a path is made absolute, since Cargo doesn't run it from here.  A bare name is left to be looked up on `PATH`.
+*/
+fn rustc_wrapper(args: &Args) -> Result<Option<OsString>> {
+    let wrapper = match args.rustc_wrapper {
+        Some(ref wrapper) => wrapper,
+        None => {
+            return Ok(std::env::var_os("RUSTC_WRAPPER")
+                .or_else(|| std::env::var_os("CARGO_BUILD_RUSTC_WRAPPER"))
+                .filter(|wrapper| !wrapper.is_empty()))
+        }
+    };
+    if Path::new(wrapper).components().count() > 1 {
+        Ok(Some(
+            std::env::current_dir()?.join(wrapper).into_os_string(),
+        ))
+    } else {
+        Ok(Some(wrapper.into()))
+    }
+}
+
+/**
+Checks that the `rustc` Cargo is going to use can do `--sanitizer`, which needs a nightly compiler.
+*/
+fn check_nightly() -> Result<()> {
+    let (rustc, version_str) = rustc_version()?;
+    let version = version_str.split_whitespace().nth(1).unwrap_or("");
+    info!("rustc version: {:?}", version);
+    // A compiler built from source says `-dev`, and will take `-Z` flags too.
+    if version.contains("-nightly") || version.contains("-dev") {
+        return Ok(());
+    }
+    Err((
+        Blame::Human,
+        format!(
+            "`--sanitizer` needs a nightly toolchain, but `{}` is {}; \
+            try `cargo +nightly eval --sanitizer ...`",
+            rustc.to_string_lossy(),
+            version_str.trim()
+        ),
+    )
+        .into())
+}
+
+/**
+Runs `rustc --version` (or whatever `RUSTC` says to use), returning the command and what it said, which looks like `rustc 1.70.0 (90c541806 2023-05-31)`.
+*/
+fn rustc_version() -> Result<(OsString, String)> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let out = Command::new(&rustc)
+        .arg("--version")
+        .output()
+        .map_err(MainError::from)
+        .err_tag("could not get the version of `rustc`")?;
+    let version_str = String::from_utf8_lossy(&out.stdout).into_owned();
+    Ok((rustc, version_str))
+}
+
+/**
+The arguments to tack onto the end of `cargo rustc` for `--emit`, if it's being used.
+
+These are only passed to `rustc` for the script itself, so dependencies aren't rebuilt, and can still be shared with other scripts.
+*/
+fn emit_rustc_args(
+    input: &Input,
+    pkg_path: &Path,
+    meta: &PackageMetadata,
+) -> Result<Vec<OsString>> {
+    let kind = match meta.emit {
+        Some(ref kind) => kind,
+        None => return Ok(vec![]),
+    };
+    let mut emit = OsString::from(format!("--emit=link,{}=", kind));
+    emit.push(emit_path(input, pkg_path, kind)?);
+    // With more than one codegen unit, `rustc` ignores the path, and writes a file per unit instead.
+    Ok(vec!["--".into(), emit, "-Ccodegen-units=1".into()])
+}
+
+/**
+`cargo` not being installed shows up as a rather baffling "file not found" when we try to run it, so this swaps that for an explanation.
+*/
+fn explain_cargo_err(err: MainError) -> MainError {
+    match err {
+        MainError::Io(_, ref io_err) if io_err.kind() == io::ErrorKind::NotFound => {
+            MainError::Other(Blame::Human, Box::new(error::CargoNotFound::new()))
+        }
+        err => err,
+    }
+}
+
 /**
 Tries to find the path to a package's target file.
 
@@ -1263,6 +4280,7 @@ fn cargo_target<P>(
     manifest: &str,
     use_bincache: bool,
     meta: &PackageMetadata,
+    rustc_wrapper: Option<&OsStr>,
 ) -> Result<PathBuf>
 where
     P: AsRef<Path>,
@@ -1274,7 +4292,7 @@ where
         use_bincache
     );
 
-    let exe_path = cargo_target_by_message(input, manifest, use_bincache, meta)?;
+    let exe_path = cargo_target_by_message(input, manifest, use_bincache, meta, rustc_wrapper)?;
 
     trace!(".. exe_path: {:?}", exe_path);
 
@@ -1299,6 +4317,7 @@ fn cargo_target_by_message(
     manifest: &str,
     use_bincache: bool,
     meta: &PackageMetadata,
+    rustc_wrapper: Option<&OsStr>,
 ) -> Result<PathBuf> {
     use std::io::{BufRead, BufReader};
 
@@ -1308,14 +4327,19 @@ fn cargo_target_by_message(
         use_bincache
     );
 
-    let mut cmd = cargo("build", manifest, use_bincache, meta)?;
+    let mut cmd = cargo("build", manifest, use_bincache, meta, rustc_wrapper)?;
     cmd.arg("--message-format=json");
+    cmd.args(emit_rustc_args(
+        input,
+        Path::new(manifest).parent().unwrap(),
+        meta,
+    )?);
     cmd.stdout(process::Stdio::piped());
     cmd.stderr(process::Stdio::null());
 
     trace!(".. cmd: {:?}", cmd);
 
-    let mut child = cmd.spawn()?;
+    let mut child = cmd.spawn().map_err(|err| explain_cargo_err(err.into()))?;
 
     let package_name = input.package_name();
 
@@ -1325,8 +4349,17 @@ fn cargo_target_by_message(
     #[derive(Deserialize)]
     struct Target {
         name: String,
+        kind: Vec<String>,
     }
 
+    // Cargo names library targets after the crate, which can't have hyphens.
+    let is_ours = |target: &Target| match meta.crate_type {
+        Some(ref crate_type) => {
+            target.name == package_name.replace('-', "_") && target.kind.contains(crate_type)
+        }
+        None => target.name == package_name,
+    };
+
     #[derive(Deserialize)]
     struct Line {
         reason: String,
@@ -1336,7 +4369,7 @@ fn cargo_target_by_message(
 
     while let Some(Ok(line)) = lines.next() {
         if let Ok(mut l) = serde_json::from_str::<Line>(&line).map_err(Box::new) {
-            if l.reason == "compiler-artifact" && l.target.name == package_name {
+            if l.reason == "compiler-artifact" && is_ours(&l.target) {
                 let _ = child.kill();
                 return Ok(l.filenames.swap_remove(0));
             }
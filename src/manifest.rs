@@ -7,8 +7,10 @@ use std::path::Path;
 use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
 use regex::Regex;
 
+use crate::consts;
 use crate::error::{Blame, Result};
 use crate::templates;
+use crate::util::SubsliceOffset;
 use crate::Input;
 
 lazy_static! {
@@ -18,6 +20,8 @@ lazy_static! {
     static ref RE_SPACE: Regex = Regex::new(r"^(\s+)").unwrap();
     static ref RE_NESTING: Regex = Regex::new(r"/\*|\*/").unwrap();
     static ref RE_COMMENT: Regex = Regex::new(r"^\s*//!").unwrap();
+    static ref RE_FN_MAIN: Regex =
+        Regex::new(r"(?m)^\s*(pub\s+)?(async\s+)?fn\s+main\s*\(").unwrap();
     static ref RE_HASHBANG: Regex = Regex::new(r"^#![^\[].*?(\r\n|\n)").unwrap();
     static ref RE_CRATE_COMMENT: Regex = {
         Regex::new(
@@ -34,51 +38,137 @@ lazy_static! {
 Splits input into a complete Cargo manifest and unadultered Rust source.
 
 Unless we have prelude items to inject, in which case it will be *slightly* adulterated.
+
+`defaults` is merged over the built-in default manifest, but *under* anything the script itself specifies.
+
+`overrides` is merged over *everything* else, all the way down, so it has the final say.
+
+`template_subs` are additional substitutions made available to templates.
+
+If `raw` is set, a script file is used exactly as written, rather than being put through the `file` template.
+
+If `panic_location` is set, the script's `main` is renamed, so the `file` template's `#{panic_hook}` can add one which installs a panic hook first.
 */
+#[allow(clippy::too_many_arguments)]
 pub fn split_input(
     input: &Input,
     deps: &[(String, String)],
     prelude_items: &[String],
+    defaults: &toml::value::Table,
+    overrides: &toml::value::Table,
+    template_subs: &HashMap<&str, String>,
+    template_variant: Option<&str>,
+    raw: bool,
+    panic_location: bool,
 ) -> Result<(String, String)> {
     let template_buf;
+    let script_buf;
+    let mut panic_hook_buf = String::new();
     let (part_mani, source, template, sub_prelude) = match *input {
-        Input::File(_, _, content, _) => {
+        Input::File(_, _, content, _) if raw => {
+            assert_eq!(prelude_items.len(), 0);
+            let manifest = find_embedded_manifest(strip_hashbang(content))
+                .map(|(manifest, _)| manifest)
+                .unwrap_or(Manifest::Toml(""));
+
+            (manifest, content, "#{script}".into(), false)
+        }
+        Input::File(_, path, original, _) => {
             assert_eq!(prelude_items.len(), 0);
-            let content = strip_hashbang(content).trim_end();
+            let content = strip_hashbang(original).trim_end();
             let (manifest, source) =
                 find_embedded_manifest(content).unwrap_or((Manifest::Toml(""), content));
 
-            (manifest, source, templates::get_template("file")?, false)
+            let template = templates::get_template("file")?;
+            // Two `main`s won't compile, and rustc's complaint about it won't mention the template.
+            if RE_FN_MAIN.is_match(&template) && RE_FN_MAIN.is_match(source) {
+                return Err((
+                    Blame::Human,
+                    "the script defines its own `main`, but the `file` template wraps it in \
+                    another one; use `--raw` to compile the script as-is",
+                )
+                    .into());
+            }
+
+            if panic_location {
+                if !template.contains("#{panic_hook}") {
+                    return Err((
+                        Blame::Human,
+                        "`--panic-location` needs the `file` template to use `#{panic_hook}`",
+                    )
+                        .into());
+                }
+                script_buf = match rename_main(source) {
+                    Some(renamed) => renamed,
+                    None => {
+                        return Err((
+                            Blame::Human,
+                            "`--panic-location` needs the script to have exactly one `main`, \
+                            which isn't `async`",
+                        )
+                            .into())
+                    }
+                };
+
+                // Lines the generated source has lost (the hashbang), less any the template puts in front of the script.
+                let lost = original
+                    .subslice_offset_stable(source)
+                    .map_or(0, |offset| original[..offset].matches('\n').count());
+                let added = template
+                    .find("#{script}")
+                    .map_or(0, |offset| template[..offset].matches('\n').count());
+                panic_hook_buf = panic_hook(
+                    &path.to_string_lossy(),
+                    &format!("{}.rs", input.safe_name()),
+                    lost as i64 - added as i64,
+                );
+                (manifest, &script_buf[..], template, false)
+            } else {
+                (manifest, source, template, false)
+            }
         }
-        Input::Expr("meaning-of-life", None) | Input::Expr("meaning_of_life", None) => (
-            Manifest::Toml(""),
-            r#"
+        Input::Expr("meaning-of-life", None) | Input::Expr("meaning_of_life", None) => {
+            template_buf =
+                templates::select_variant(&templates::get_template("expr")?, None)?.into_owned();
+            (
+                Manifest::Toml(""),
+                r#"
                 println!("42");
                 std::process::exit(42);
             "#,
-            templates::get_template("expr")?,
-            true,
-        ),
+                template_buf.as_str().into(),
+                true,
+            )
+        }
         Input::Expr(content, template) => {
-            template_buf = templates::get_template(template.unwrap_or("expr"))?;
+            let template_text = templates::get_template(template.unwrap_or("expr"))?;
+            template_buf =
+                templates::select_variant(&template_text, template_variant)?.into_owned();
             let (manifest, template_src) = find_embedded_manifest(&template_buf)
                 .unwrap_or((Manifest::Toml(""), &template_buf));
             (manifest, content, template_src.into(), true)
         }
         Input::Loop(content, count) => {
             let templ = if count { "loop-count" } else { "loop" };
+            template_buf =
+                templates::select_variant(&templates::get_template(templ)?, template_variant)?
+                    .into_owned();
             (
                 Manifest::Toml(""),
                 content,
-                templates::get_template(templ)?,
+                template_buf.as_str().into(),
                 true,
             )
         }
     };
 
     let mut prelude_str;
-    let mut subs = HashMap::with_capacity(2);
+    let mut subs = HashMap::with_capacity(2 + template_subs.len());
+    for (&k, v) in template_subs {
+        subs.insert(k, &v[..]);
+    }
     subs.insert("script", &source[..]);
+    subs.insert("panic_hook", &panic_hook_buf[..]);
 
     if sub_prelude {
         prelude_str =
@@ -100,10 +190,12 @@ pub fn split_input(
 
     // It's-a mergin' time!
     let def_mani = default_manifest(input)?;
+    let def_mani = merge_manifest(def_mani, defaults.clone())?;
     let dep_mani = deps_manifest(deps)?;
 
     let mani = merge_manifest(def_mani, part_mani)?;
     let mani = merge_manifest(mani, dep_mani)?;
+    let mani = merge_manifest_deep(mani, overrides.clone());
 
     // Fix up relative paths.
     let mani = fix_manifest_paths(mani, &input.base_path())?;
@@ -119,7 +211,18 @@ pub fn split_input(
 fn test_split_input() {
     macro_rules! si {
         ($i:expr) => {
-            split_input(&$i, &[], &[]).ok()
+            split_input(
+                &$i,
+                &[],
+                &[],
+                &toml::value::Table::new(),
+                &toml::value::Table::new(),
+                &HashMap::new(),
+                None,
+                false,
+                false,
+            )
+            .ok()
         };
     }
 
@@ -292,6 +395,79 @@ time = "0.1.25"
 fn main() {}"#
         )
     );
+
+    // With `--panic-location`, the script's `main` is renamed, and the `file` template adds one which installs the panic hook.
+    assert_eq!(
+        split_input(
+            &f(r#"fn main() {}"#),
+            &[],
+            &[],
+            &toml::value::Table::new(),
+            &toml::value::Table::new(),
+            &HashMap::new(),
+            None,
+            false,
+            true,
+        )
+        .unwrap()
+        .1,
+        format!("fn __cargo_eval_main() {{}}{}", panic_hook("p", "n.rs", 0))
+    );
+}
+
+/**
+Renames the script's `main` to `__cargo_eval_main`, so the one `panic_hook` adds can call it.
+
+Returns `None` if there isn't exactly one `main`, or if it's `async`, since that needs an attribute from a runtime to work, which might well care what it's called.
+*/
+fn rename_main(source: &str) -> Option<String> {
+    let mut mains = RE_FN_MAIN.captures_iter(source);
+    let main = mains.next()?;
+    if mains.next().is_some() || main.get(2).is_some() {
+        return None;
+    }
+    let m = main.get(0).unwrap();
+    let at = m.start() + m.as_str().rfind("main").unwrap();
+    Some(format!(
+        "{}__cargo_eval_main{}",
+        &source[..at],
+        &source[at + "main".len()..]
+    ))
+}
+
+/**
+Generates what the `file` template's `#{panic_hook}` expands to with `--panic-location`: a `main` which installs a panic hook, then calls the script's own (renamed) `main`.
+
+The hook reports panics in `generated` (the source file the package is built from) as being in `script` instead, with `line_offset` added to the line number.
+*/
+fn panic_hook(script: &str, generated: &str, line_offset: i64) -> String {
+    format!(
+        "\n{}\nfn main() -> impl std::process::Termination {{\n    \
+        __cargo_eval_panic_hook({:?}, {:?}, {});\n    \
+        __cargo_eval_main()\n}}\n",
+        consts::PANIC_HOOK_PRELUDE.trim(),
+        script,
+        generated,
+        line_offset
+    )
+}
+
+#[test]
+fn test_rename_main() {
+    assert_eq!(
+        rename_main("use std::io;\n\npub fn main() {}").unwrap(),
+        "use std::io;\n\npub fn __cargo_eval_main() {}"
+    );
+    assert_eq!(
+        rename_main("fn main ( ) -> Result<(), String> { Ok(()) }").unwrap(),
+        "fn __cargo_eval_main ( ) -> Result<(), String> { Ok(()) }"
+    );
+    assert_eq!(rename_main("fn mainly() {}"), None);
+    assert_eq!(rename_main("#[tokio::main]\nasync fn main() {}"), None);
+    assert_eq!(
+        rename_main("fn main() {}\nmod m {\n    fn main() {}\n}"),
+        None
+    );
 }
 
 /**
@@ -1042,6 +1218,119 @@ fn deps_manifest(deps: &[(String, String)]) -> Result<toml::value::Table> {
     toml::from_str(&mani_str).map_err(|_| "could not parse dependency manifest".into())
 }
 
+/**
+Adds target-specific dependencies, given as `(target, name, version)`, to `overrides`.
+
+These go *under* whatever `overrides` already has, so a `--manifest-override` still has the final say.
+*/
+pub fn add_target_deps(
+    overrides: &mut toml::value::Table,
+    target_deps: &[(String, String, String)],
+) -> Result<()> {
+    let mut targets = toml::value::Table::new();
+    for (target, name, version) in target_deps {
+        let dep = deps_manifest(&[(name.clone(), version.clone())])?;
+        let mut target_mani = toml::value::Table::new();
+        target_mani.insert(target.clone(), dep.into());
+        let mut mani = toml::value::Table::new();
+        mani.insert("target".into(), target_mani.into());
+        targets = merge_manifest_deep(targets, mani);
+    }
+    *overrides = merge_manifest_deep(targets, std::mem::take(overrides));
+    Ok(())
+}
+
+/**
+Makes the package build the input's script as a library of the given `crate-type`, instead of an executable, by adding it to `overrides`.
+
+Like `add_target_deps`, this goes under whatever `overrides` already has.
+*/
+pub fn set_lib_crate_type(overrides: &mut toml::value::Table, crate_type: &str, input: &Input) {
+    let mut lib = toml::value::Table::new();
+    // Named explicitly, so it doesn't change with `--pkg-name`.
+    lib.insert("name".into(), input.package_name().replace('-', "_").into());
+    lib.insert("path".into(), format!("{}.rs", input.safe_name()).into());
+    lib.insert("crate-type".into(), vec![crate_type].into());
+    let mut mani = toml::value::Table::new();
+    mani.insert("lib".into(), lib.into());
+    mani.insert("bin".into(), toml::Value::Array(vec![]));
+    *overrides = merge_manifest_deep(mani, std::mem::take(overrides));
+}
+
+/**
+Sets `key` in `[package]` to `value`, by adding it to `overrides`.
+
+Like `set_lib_crate_type`, this goes under whatever `overrides` already has.
+*/
+pub fn set_package_key(overrides: &mut toml::value::Table, key: &str, value: &str) {
+    let mut package = toml::value::Table::new();
+    package.insert(key.into(), value.into());
+    let mut mani = toml::value::Table::new();
+    mani.insert("package".into(), package.into());
+    *overrides = merge_manifest_deep(mani, std::mem::take(overrides));
+}
+
+/**
+Given two Cargo manifests, merges the second *into* the first, at every level.
+
+Unlike `merge_manifest`, tables are merged however deeply they're nested, and anything in the second manifest replaces whatever was there in the first, even if the types don't match.
+*/
+fn merge_manifest_deep(
+    mut into_t: toml::value::Table,
+    from_t: toml::value::Table,
+) -> toml::value::Table {
+    for (k, v) in from_t {
+        let merged = match (into_t.remove(&k), v) {
+            (Some(toml::Value::Table(into_v)), toml::Value::Table(from_v)) => {
+                toml::Value::Table(merge_manifest_deep(into_v, from_v))
+            }
+            (_, v) => v,
+        };
+        into_t.insert(k, merged);
+    }
+    into_t
+}
+
+#[test]
+fn test_merge_manifest_deep() {
+    let into_t = toml::from_str(
+        r#"
+[package]
+name = "foo"
+[profile.release]
+lto = true
+debug = false
+"#,
+    )
+    .unwrap();
+    let from_t = toml::from_str(
+        r#"
+[package]
+build = "build.rs"
+[profile.release]
+debug = true
+[profile.dev]
+opt-level = 1
+"#,
+    )
+    .unwrap();
+
+    let merged = merge_manifest_deep(into_t, from_t);
+    assert_eq!(
+        toml::Value::Table(merged).to_string(),
+        r#"[package]
+build = "build.rs"
+name = "foo"
+[profile.dev]
+opt-level = 1
+
+[profile.release]
+debug = true
+lto = true
+"#
+    );
+}
+
 /**
 Given two Cargo manifests, merges the second *into* the first.
 
@@ -0,0 +1,137 @@
+/*!
+This module contains the `new` subcommand, which writes out a skeleton script to start from.
+
+The script gets a hashbang line (on UNIX, where it can be used), an embedded manifest with any dependencies asked for, and a `main` that says hello.
+*/
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Blame, MainError, Result};
+
+#[derive(Debug)]
+pub struct Args {
+    name: String,
+    dep: Vec<String>,
+}
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name("new")
+            .about("Creates a new script, with an embedded manifest.")
+            .arg(
+                Arg::with_name("name")
+                    .help("Path of the script to create.  If it doesn't have an extension, `.rs` is added.")
+                    .index(1)
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("dep")
+                    .help("Add a dependency to the script's manifest.  SPEC is the same as for `cargo eval --dep`.")
+                    .long("dep")
+                    .short("d")
+                    .takes_value(true)
+                    .value_name("SPEC")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            name: m.value_of("name").unwrap().into(),
+            dep: m
+                .values_of("dep")
+                .map(|vs| vs.map(Into::into).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    let path = script_path(&args.name);
+    if path.exists() {
+        return Err((Blame::Human, format!("`{}` already exists", path.display())).into());
+    }
+
+    let deps = crate::parse_deps(&args.dep, false)?;
+    fs::write(&path, scaffold(&deps)).map_err(|err| -> MainError {
+        (
+            Blame::Human,
+            format!("could not create `{}`: {}", path.display(), err),
+        )
+            .into()
+    })?;
+    make_executable(&path)?;
+
+    eprintln!("created `{}`", path.display());
+    Ok(0)
+}
+
+/**
+Works out where to write the script called `name`.
+*/
+fn script_path(name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.extension().is_some() {
+        path
+    } else {
+        path.with_extension("rs")
+    }
+}
+
+/**
+Generates the text of a new script, depending on `deps`.
+*/
+fn scaffold(deps: &[(String, String)]) -> String {
+    let mut text = String::new();
+    if cfg!(unix) {
+        text.push_str("#!/usr/bin/env -S cargo eval --\n");
+    }
+    text.push_str("//! ```cargo\n//! [dependencies]\n");
+    for (name, version) in deps {
+        let quotes = if version.starts_with('{') { "" } else { "\"" };
+        text.push_str(&format!("//! {} = {}{}{}\n", name, quotes, version, quotes));
+    }
+    text.push_str("//! ```\n\nfn main() {\n    println!(\"Hello, world!\");\n}\n");
+    text
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = fs::metadata(path)?.permissions();
+    perms.set_mode(perms.mode() | 0o111);
+    fs::set_permissions(path, perms)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[test]
+fn test_scaffold() {
+    let deps = vec![
+        ("itertools".to_owned(), "0.10".to_owned()),
+        (
+            "regex".to_owned(),
+            "{ version = \"1\", features = [\"unicode\"] }".to_owned(),
+        ),
+    ];
+    let text = scaffold(&deps);
+    assert!(text.contains(
+        "//! ```cargo\n\
+        //! [dependencies]\n\
+        //! itertools = \"0.10\"\n\
+        //! regex = { version = \"1\", features = [\"unicode\"] }\n\
+        //! ```\n"
+    ));
+    assert!(text.ends_with("fn main() {\n    println!(\"Hello, world!\");\n}\n"));
+    assert_eq!(text.starts_with("#!"), cfg!(unix));
+
+    assert_eq!(script_path("hello"), PathBuf::from("hello.rs"));
+    assert_eq!(script_path("hello.crs"), PathBuf::from("hello.crs"));
+}
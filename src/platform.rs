@@ -54,6 +54,29 @@ mod inner {
     pub fn force_cargo_color() -> bool {
         atty::is(atty::Stream::Stderr)
     }
+
+    /**
+    Returns `true` if `cargo-eval`'s STDOUT is connected to a TTY.
+    */
+    pub fn stdout_is_tty() -> bool {
+        atty::is(atty::Stream::Stdout)
+    }
+
+    /**
+    Returns `true` if `cargo-eval`'s STDERR is connected to a TTY.
+    */
+    pub fn stderr_is_tty() -> bool {
+        atty::is(atty::Stream::Stderr)
+    }
+
+    /**
+    Returns a command which runs `cmd` with the shell.
+    */
+    pub fn shell_command(cmd: &str) -> std::process::Command {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c").arg(cmd);
+        shell
+    }
 }
 
 #[cfg(windows)]
@@ -104,4 +127,31 @@ pub mod inner {
     pub fn force_cargo_color() -> bool {
         false
     }
+
+    /**
+    Returns `true` if `cargo-eval`'s STDOUT is connected to a console.
+    */
+    pub fn stdout_is_tty() -> bool {
+        use std::io::IsTerminal;
+        io::stdout().is_terminal()
+    }
+
+    /**
+    Returns `true` if `cargo-eval`'s STDERR is connected to a console.
+    */
+    pub fn stderr_is_tty() -> bool {
+        use std::io::IsTerminal;
+        io::stderr().is_terminal()
+    }
+
+    /**
+    Returns a command which runs `cmd` with `cmd.exe`.
+    */
+    pub fn shell_command(cmd: &str) -> std::process::Command {
+        use std::os::windows::process::CommandExt;
+        let mut shell = std::process::Command::new("cmd");
+        // `cmd` does its own thing with quotes, so the command has to be passed through untouched.
+        shell.arg("/C").raw_arg(cmd);
+        shell
+    }
 }
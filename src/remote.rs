@@ -0,0 +1,155 @@
+/*!
+This module fetches scripts over HTTP(S), for `--remote`.
+
+Fetched scripts are kept in the cache, keyed by URL, along with the `ETag` the server gave for them.  The next time the same URL is run, the server is asked whether that copy is still current, and it's only downloaded again if it isn't.  Either way, the script is then run from the cached copy, exactly as if it were a local file.
+
+Running code straight off the network is risky, so the host has to be trusted first: either with `--allow-remote`, or by listing it in `trusted_hosts` in a project configuration file.
+*/
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Blame, MainError, Result};
+
+/**
+Name of the file the `ETag` of a fetched script is kept in.
+*/
+const ETAG_FILE: &str = "etag";
+
+/**
+Checks that scripts may be fetched from `url`.
+*/
+pub fn check_trusted(url: &str, allow_remote: bool, trusted_hosts: &[String]) -> Result<()> {
+    let host = url_host(url).ok_or_else(|| -> MainError {
+        (
+            Blame::Human,
+            format!("`{}` is not an `http://` or `https://` URL", url),
+        )
+            .into()
+    })?;
+
+    if allow_remote || trusted_hosts.iter().any(|h| h.eq_ignore_ascii_case(host)) {
+        return Ok(());
+    }
+
+    Err((
+        Blame::Human,
+        format!(
+            "refusing to run a script from `{}`; pass `--allow-remote` if you trust it, \
+            or add it to `trusted_hosts` in a project configuration file",
+            host
+        ),
+    )
+        .into())
+}
+
+/**
+Fetches the script at `url` into `cache_dir`, and returns the path to the cached copy.
+*/
+pub fn fetch(url: &str, cache_dir: &Path) -> Result<PathBuf> {
+    let mut digest = crate::hash_str(url);
+    digest.truncate(crate::consts::ID_DIGEST_LEN_MAX);
+    let dir = cache_dir.join(digest);
+    let script_path = dir.join(format!("{}.rs", script_name(url)));
+    let etag_path = dir.join(ETAG_FILE);
+
+    let mut req = ureq::get(url);
+    if script_path.is_file() {
+        if let Ok(etag) = fs::read_to_string(&etag_path) {
+            req = req.set("If-None-Match", &etag);
+        }
+    }
+
+    info!("fetching {:?}", url);
+    let resp = req.call().map_err(|err| -> MainError {
+        let msg = match err {
+            ureq::Error::Status(code, _) => format!("server responded with status {}", code),
+            ureq::Error::Transport(err) => err.to_string(),
+        };
+        (Blame::Human, format!("could not fetch `{}`: {}", url, msg)).into()
+    })?;
+
+    if resp.status() == 304 {
+        info!("cached copy of {:?} is still current", url);
+        return Ok(script_path);
+    }
+
+    let etag = resp.header("ETag").map(String::from);
+    let body = resp.into_string().map_err(|err| -> MainError {
+        (Blame::Human, format!("could not fetch `{}`: {}", url, err)).into()
+    })?;
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&script_path, body)?;
+    match etag {
+        Some(etag) => fs::write(&etag_path, etag)?,
+        None if etag_path.exists() => fs::remove_file(&etag_path)?,
+        None => (),
+    }
+
+    Ok(script_path)
+}
+
+/**
+Returns the host part of an `http://` or `https://` URL.
+*/
+fn url_host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(&['/', '?', '#'][..]).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = if host_port.starts_with('[') {
+        &host_port[..=host_port.find(']')?]
+    } else {
+        host_port.split(':').next()?
+    };
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+/**
+Works out a name for the script from the last part of its URL's path.
+*/
+fn script_name(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.split(&['?', '#'][..]).next().unwrap_or(rest);
+    let path = rest.find('/').map_or("", |i| &rest[i..]);
+    let last = path.rsplit('/').find(|s| !s.is_empty()).unwrap_or("");
+    let name = last
+        .trim_end_matches(".rs")
+        .chars()
+        .filter(|&c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        .collect::<String>();
+    if name.is_empty() {
+        "remote".into()
+    } else {
+        name
+    }
+}
+
+#[test]
+fn test_url_host() {
+    assert_eq!(url_host("https://example.com/a.rs"), Some("example.com"));
+    assert_eq!(url_host("http://example.com:8080"), Some("example.com"));
+    assert_eq!(
+        url_host("http://user:pw@example.com/x"),
+        Some("example.com")
+    );
+    assert_eq!(url_host("http://[::1]:80/x"), Some("[::1]"));
+    assert_eq!(url_host("https://example.com?x=1"), Some("example.com"));
+    assert_eq!(url_host("ftp://example.com/a.rs"), None);
+    assert_eq!(url_host("https:///a.rs"), None);
+}
+
+#[test]
+fn test_script_name() {
+    assert_eq!(script_name("https://example.com/scripts/hello.rs"), "hello");
+    assert_eq!(script_name("https://example.com/hello.rs?raw=1"), "hello");
+    assert_eq!(script_name("https://example.com/a/b/"), "b");
+    assert_eq!(script_name("https://example.com"), "remote");
+    assert_eq!(script_name("http://127.0.0.1:8080?x=a/b.rs"), "remote");
+    assert_eq!(script_name("https://example.com/%%%"), "remote");
+}
@@ -0,0 +1,199 @@
+/*!
+This module contains the `repl` subcommand, which reads expressions from standard input one line at a time, and evaluates them.
+
+Each expression is compiled using the same template as `--expr`.  To keep things quick, every expression in a session is built as the same package, into a target directory which is shared between *all* sessions.  As such, dependencies only need to be compiled once, and only the expression itself gets rebuilt each time.
+*/
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::app;
+use crate::error::Result;
+use crate::manifest;
+use crate::platform;
+use crate::Input;
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or one of the following commands:
+
+    :dep SPEC...    add dependencies, in the same format as `--dep`
+    :deps           list the current dependencies
+    :help           show this message
+    :quit           exit the session";
+
+#[derive(Debug)]
+pub struct Args {
+    deps: Vec<String>,
+}
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name("repl")
+            .about("Evaluate expressions interactively, one per line.")
+            .arg(Arg::with_name("dep")
+                .help("Add an additional Cargo dependency.  Each SPEC can be either just the package name (which will assume the latest version) or a full `name=version` spec, optionally followed by `+feature,...` to turn on features.")
+                .long("dep")
+                .short("d")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            deps: m
+                .values_of("dep")
+                .map(|vs| vs.map(Into::into).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    // Make sure the initial dependencies are valid before we start.
+    crate::parse_deps(&args.deps, false)?;
+
+    let mut session = Session::new(args.deps)?;
+
+    let stdin = io::stdin();
+    prompt()?;
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if let Some(command) = line.strip_prefix(':') {
+            let mut words = command.split_whitespace();
+            match words.next() {
+                Some("q") | Some("quit") => break,
+                Some("h") | Some("help") => eprintln!("{}", HELP),
+                Some("dep") => session.add_deps(words.map(Into::into).collect()),
+                Some("deps") => {
+                    for dep in &session.deps {
+                        eprintln!("{}", dep);
+                    }
+                }
+                _ => eprintln!("unknown command `{}`; try `:help`", line),
+            }
+        } else if !line.is_empty() {
+            if let Err(err) = session.eval(line) {
+                eprintln!("error: {}", err);
+            }
+        }
+
+        prompt()?;
+    }
+
+    Ok(0)
+}
+
+fn prompt() -> Result<()> {
+    let stderr = &mut io::stderr();
+    write!(stderr, "> ")?;
+    stderr.flush()?;
+    Ok(())
+}
+
+/**
+The state of a single REPL session.
+
+The session's package directory is removed when this is dropped.
+*/
+struct Session {
+    /// `--dep` specs for every expression.
+    deps: Vec<String>,
+
+    /// Where the package for this session lives.
+    pkg_path: PathBuf,
+
+    /// Target directory shared between all sessions.
+    target_path: PathBuf,
+}
+
+impl Session {
+    fn new(deps: Vec<String>) -> Result<Session> {
+        let repl_path = app::cache_dir().join("repl");
+        let pkg_path = repl_path.join(format!("session-{}", std::process::id()));
+        let target_path = repl_path.join("target");
+        fs::create_dir_all(&pkg_path)?;
+        info!("repl pkg_path: {:?}", pkg_path);
+
+        Ok(Session {
+            deps,
+            pkg_path,
+            target_path,
+        })
+    }
+
+    /**
+    Adds dependencies to the session, so long as they're compatible with the existing ones.
+    */
+    fn add_deps(&mut self, specs: Vec<String>) {
+        let mut deps = self.deps.clone();
+        deps.extend(specs);
+        match crate::parse_deps(&deps, false) {
+            Ok(_) => self.deps = deps,
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+
+    /**
+    Compiles and runs a single expression.
+    */
+    fn eval(&self, expr: &str) -> Result<()> {
+        let deps = crate::parse_deps(&self.deps, false)?;
+        let input = Input::Expr(expr, None);
+
+        let mut subs = HashMap::new();
+        subs.insert("format", "{:?}".to_owned());
+        subs.insert("bind", "v".to_owned());
+        subs.insert("print", "print".to_owned());
+        subs.insert("println", "println".to_owned());
+        let no_mani = toml::value::Table::new();
+        let (mani_str, script_str) = manifest::split_input(
+            &input,
+            &deps,
+            &[],
+            &no_mani,
+            &no_mani,
+            &subs,
+            None,
+            false,
+            false,
+        )?;
+
+        let mani_path = self.pkg_path.join("Cargo.toml");
+        fs::write(&mani_path, mani_str)?;
+        fs::write(
+            self.pkg_path.join(format!("{}.rs", input.safe_name())),
+            script_str,
+        )?;
+
+        let mut cmd = Command::new("cargo");
+        cmd.arg("run")
+            .arg("--quiet")
+            .arg("--manifest-path")
+            .arg(&mani_path)
+            .env("CARGO_TARGET_DIR", &self.target_path);
+        if platform::force_cargo_color() {
+            cmd.arg("--color").arg("always");
+        }
+
+        // Failures have already been reported by Cargo or the expression itself.
+        let status = cmd.status()?;
+        info!("expression exited with {:?}", status);
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.pkg_path) {
+            error!("failed to remove repl session dir {:?}: {}", self.pkg_path, err);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+/*!
+This module writes out a small shell script (or batch file, on Windows) next to a generated package, which builds and runs it the same way `cargo eval` would.
+
+The idea is that a package from `--gen-pkg-only --emit-runner` can be handed to someone without `cargo-eval`, and still be run with the same profile, features and flags.
+*/
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::Result;
+
+/**
+Writes a runner for the `cargo run` command `cmd` into `pkg_path`, returning its path.
+
+`manifest` is the path `cmd` uses for `--manifest-path`; in the runner, it's replaced by a path relative to the runner itself, so the package can be moved around.
+*/
+pub fn write_runner(pkg_path: &Path, cmd: &Command, manifest: &str) -> Result<PathBuf> {
+    let path = pkg_path.join(self::inner::RUNNER_NAME);
+    info!("writing runner to {:?}", path);
+
+    let mut text = String::from(self::inner::HEADER);
+    for (key, value) in cmd.get_envs() {
+        if let Some(value) = value {
+            text.push_str(&self::inner::set_env(
+                &key.to_string_lossy(),
+                &value.to_string_lossy(),
+            ));
+        }
+    }
+
+    text.push_str(&self::inner::quote(&cmd.get_program().to_string_lossy()));
+    for arg in cmd.get_args() {
+        let arg = arg.to_string_lossy();
+        text.push(' ');
+        if arg == manifest {
+            text.push_str(self::inner::MANIFEST_PATH);
+        } else {
+            text.push_str(&self::inner::quote(&arg));
+        }
+    }
+    text.push_str(" -- ");
+    text.push_str(self::inner::ALL_ARGS);
+    text.push_str(self::inner::NEWLINE);
+
+    fs::write(&path, text)?;
+    self::inner::make_executable(&path)?;
+    Ok(path)
+}
+
+#[cfg(unix)]
+mod inner {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use crate::error::Result;
+
+    pub const RUNNER_NAME: &str = "run.sh";
+    pub const HEADER: &str = "#!/bin/sh\nset -e\n";
+    pub const MANIFEST_PATH: &str = "\"$(dirname \"$0\")/Cargo.toml\"";
+    pub const ALL_ARGS: &str = "\"$@\"";
+    pub const NEWLINE: &str = "\n";
+
+    pub fn quote(s: &str) -> String {
+        let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+        if !s.is_empty() && s.chars().all(is_safe) {
+            s.into()
+        } else {
+            format!("'{}'", s.replace('\'', "'\\''"))
+        }
+    }
+
+    pub fn set_env(key: &str, value: &str) -> String {
+        format!("export {}={}\n", key, quote(value))
+    }
+
+    pub fn make_executable(path: &Path) -> Result<()> {
+        let mut perms = fs::metadata(path)?.permissions();
+        perms.set_mode(perms.mode() | 0o111);
+        fs::set_permissions(path, perms)?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod inner {
+    use std::path::Path;
+
+    use crate::error::Result;
+
+    pub const RUNNER_NAME: &str = "run.bat";
+    pub const HEADER: &str = "@echo off\r\nsetlocal\r\n";
+    pub const MANIFEST_PATH: &str = "\"%~dp0Cargo.toml\"";
+    pub const ALL_ARGS: &str = "%*";
+    pub const NEWLINE: &str = "\r\n";
+
+    pub fn quote(s: &str) -> String {
+        let s = s.replace('%', "%%");
+        if !s.is_empty() && !s.contains(|c: char| c.is_whitespace() || "&|<>^\"".contains(c)) {
+            s
+        } else {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+    }
+
+    pub fn set_env(key: &str, value: &str) -> String {
+        format!("set \"{}={}\"\r\n", key, value.replace('%', "%%"))
+    }
+
+    pub fn make_executable(_path: &Path) -> Result<()> {
+        Ok(())
+    }
+}
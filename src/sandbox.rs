@@ -0,0 +1,285 @@
+/*!
+This module contains the `--sandbox` support, which restricts what a compiled script can do when it is run.
+
+The restrictions are deliberately simple, so that it's clear what is and isn't covered:
+
+- The script is run with an empty environment, save for `PATH`, and with `HOME`, `TMPDIR`, `TMP` and `TEMP` pointing to a fresh, private temporary directory.
+- On Linux, the script is prevented (via Landlock) from creating, modifying or removing anything outside of that temporary directory (which makes the working directory effectively read-only), and from making or accepting TCP connections if the kernel supports it.
+
+On other platforms, only the environment restrictions apply.  This is *not* a security boundary against a determined attacker; it's a seatbelt for running scripts you haven't read closely.
+*/
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::error::Result;
+use crate::platform;
+
+/**
+A sandbox for a single execution of a script.
+
+The private temporary directory is removed when this is dropped, unless it's being kept for inspection.
+*/
+pub struct Sandbox {
+    temp_dir: PathBuf,
+    keep: bool,
+
+    /// Whatever the restrictions need kept open until the script has been started, and closed afterwards.
+    ruleset: Option<self::inner::Ruleset>,
+}
+
+impl Sandbox {
+    /**
+    Creates the sandbox's private temporary directory.  If `keep` is set, it's left behind afterwards.
+    */
+    pub fn new(keep: bool) -> Result<Sandbox> {
+        let temp_dir = env::temp_dir().join(format!(
+            "cargo-eval-sandbox-{}-{}",
+            std::process::id(),
+            platform::current_time()
+        ));
+        fs::create_dir_all(&temp_dir)?;
+        info!("sandbox temp dir: {:?}", temp_dir);
+        Ok(Sandbox {
+            temp_dir,
+            keep,
+            ruleset: None,
+        })
+    }
+
+    /**
+    Sets up `cmd` to run inside the sandbox.
+
+    This clears the environment of `cmd`, so any additional environment variables must be set *after* calling this.  The sandbox has to outlive starting `cmd`.
+    */
+    pub fn apply(&mut self, cmd: &mut Command) -> Result<()> {
+        clear_env(cmd);
+        for &var in &["HOME", "TMPDIR", "TMP", "TEMP"] {
+            cmd.env(var, &self.temp_dir);
+        }
+
+        self.ruleset = Some(self::inner::restrict(cmd, &self.temp_dir)?);
+        Ok(())
+    }
+}
+
+/**
+Clears the environment of `cmd`, save for `PATH`, and whatever else is needed for a process to start at all.
+*/
+pub fn clear_env(cmd: &mut Command) {
+    cmd.env_clear();
+    if let Some(path) = env::var_os("PATH") {
+        cmd.env("PATH", path);
+    }
+    #[cfg(windows)]
+    {
+        // Processes generally won't start without these.
+        for &var in &["SystemRoot", "SystemDrive", "windir"] {
+            if let Some(value) = env::var_os(var) {
+                cmd.env(var, value);
+            }
+        }
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if self.keep {
+            eprintln!("kept sandbox directory {}", self.temp_dir.display());
+            return;
+        }
+        if let Err(err) = fs::remove_dir_all(&self.temp_dir) {
+            error!("failed to remove sandbox dir {:?}: {}", self.temp_dir, err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inner {
+    /*!
+    Landlock support, via raw system calls.
+
+    See `Documentation/userspace-api/landlock.rst` in the Linux source tree for the interface being used here.
+    */
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::Command;
+
+    use crate::error::{Blame, MainError, Result, ResultExt};
+
+    const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    const ACCESS_NET_BIND_TCP: u64 = 1 << 0;
+    const ACCESS_NET_CONNECT_TCP: u64 = 1 << 1;
+
+    /**
+    The Landlock ruleset.  The child restricts itself with it just before running the script, so it has to stay open until then; after that, it's only closed.
+    */
+    pub type Ruleset = OwnedFd;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+        handled_access_net: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /**
+    Arranges for `cmd` to be unable to write anywhere except beneath `writable`, or to use TCP.
+    */
+    pub fn restrict(cmd: &mut Command, writable: &Path) -> Result<Ruleset> {
+        let abi = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Err(MainError::from(io::Error::last_os_error()))
+                .err_tag("cannot sandbox script: Landlock is not supported by this kernel")
+                .shift_blame(Blame::Human);
+        }
+        info!("landlock ABI: {}", abi);
+
+        let mut write_access = ACCESS_FS_WRITE_FILE
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            write_access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            write_access |= ACCESS_FS_TRUNCATE;
+        }
+
+        // Network restrictions only exist from ABI 4 onwards; older kernels will reject the larger struct.
+        let attr = RulesetAttr {
+            handled_access_fs: write_access,
+            handled_access_net: ACCESS_NET_BIND_TCP | ACCESS_NET_CONNECT_TCP,
+        };
+        let attr_size = if abi >= 4 {
+            std::mem::size_of::<RulesetAttr>()
+        } else {
+            std::mem::size_of::<u64>()
+        };
+
+        let ruleset_fd = unsafe {
+            libc::syscall(SYS_LANDLOCK_CREATE_RULESET, &attr as *const _, attr_size, 0)
+        };
+        if ruleset_fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset_fd as RawFd) };
+        let ruleset_fd = ruleset.as_raw_fd();
+
+        add_path_rule(ruleset_fd, writable, write_access)?;
+        add_path_rule(
+            ruleset_fd,
+            Path::new("/dev/null"),
+            ACCESS_FS_WRITE_FILE | (write_access & ACCESS_FS_TRUNCATE),
+        )?;
+
+        // The ruleset fd is close-on-exec, so the script itself never sees it.
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset_fd, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(ruleset)
+    }
+
+    fn add_path_rule(ruleset_fd: RawFd, path: &Path, access: u64) -> Result<()> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| format!("path {:?} contains a nul byte", path))?;
+        let parent_fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if parent_fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd,
+        };
+        let res = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_ADD_RULE,
+                ruleset_fd,
+                LANDLOCK_RULE_PATH_BENEATH,
+                &attr as *const _,
+                0,
+            )
+        };
+        let err = io::Error::last_os_error();
+        unsafe {
+            libc::close(parent_fd);
+        }
+        if res != 0 {
+            return Err(MainError::from(err)).err_tag(format!(
+                "could not allow sandboxed writes to {}",
+                path.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod inner {
+    use std::path::Path;
+    use std::process::Command;
+
+    use crate::error::Result;
+
+    pub type Ruleset = ();
+
+    /**
+    Filesystem and network restrictions are only implemented on Linux.
+    */
+    pub fn restrict(_cmd: &mut Command, _writable: &Path) -> Result<Ruleset> {
+        warn!("--sandbox only restricts the environment on this platform");
+        Ok(())
+    }
+}
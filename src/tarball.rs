@@ -0,0 +1,87 @@
+/*!
+This module packs a generated package up into a `.tar.gz`, for `--emit-tarball`.
+
+The archive is meant to be reproducible: the same package always gives byte-for-byte the same tarball.  So, files are added in sorted order, and timestamps, owners and permissions are all normalised.  Anything `cargo-eval` keeps in the package directory for its own use (build output, cache metadata) is left out.
+*/
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::{Compression, GzBuilder};
+
+use crate::error::Result;
+
+/**
+Files and directories at the top of a package that only `cargo-eval` cares about.
+*/
+const SKIPPED: &[&str] = &[
+    "metadata.json",
+    "target",
+    "target.exe_path",
+    "target.meta-hash",
+];
+
+/**
+Writes the package in `pkg_path` to `dest` as a gzipped tarball, with everything inside a directory called `prefix`.
+*/
+pub fn write_tarball(pkg_path: &Path, prefix: &str, dest: &Path) -> Result<()> {
+    info!("writing tarball of {:?} to {:?}", pkg_path, dest);
+
+    let mut files = vec![];
+    collect_files(pkg_path, Path::new(""), &mut files)?;
+    files.sort();
+
+    // The gzip header has a timestamp too; `GzBuilder` leaves it zeroed.
+    let gz = GzBuilder::new().write(fs::File::create(dest)?, Compression::default());
+    let mut tar = tar::Builder::new(gz);
+    for rel_path in files {
+        let path = pkg_path.join(&rel_path);
+        let data = fs::read(&path)?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(if is_executable(&path)? { 0o755 } else { 0o644 });
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        tar.append_data(&mut header, Path::new(prefix).join(&rel_path), &data[..])?;
+    }
+
+    let gz: GzEncoder<fs::File> = tar.into_inner()?;
+    gz.finish()?.flush()?;
+    Ok(())
+}
+
+/**
+Adds the paths of all files under `dir` to `files`, relative to the package directory.
+*/
+fn collect_files(dir: &Path, rel_dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if rel_dir.as_os_str().is_empty() && SKIPPED.iter().any(|&s| name == s) {
+            continue;
+        }
+
+        let rel_path = rel_dir.join(&name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &rel_path, files)?;
+        } else if file_type.is_file() {
+            files.push(rel_path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::metadata(path)?.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> Result<bool> {
+    Ok(path.extension().map_or(false, |ext| ext == "bat"))
+}
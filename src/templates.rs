@@ -11,6 +11,8 @@ use std::path::PathBuf;
 
 lazy_static! {
     static ref RE_SUB: Regex = Regex::new(r#"#\{([A-Za-z_][A-Za-z0-9_]*)}"#).unwrap();
+    static ref RE_VARIANT: Regex =
+        Regex::new(r#"(?m)^//#\s*variant\s+([A-Za-z0-9_-]+)[ \t]*\r?\n?"#).unwrap();
 }
 
 #[derive(Debug)]
@@ -108,6 +110,61 @@ pub fn expand(src: &str, subs: &HashMap<&str, &str>) -> Result<String> {
     Ok(result)
 }
 
+/**
+Picks a single variant out of a template.
+
+Templates can be split into named variants using lines of the form `//# variant NAME`.  Anything before the first such line is shared, and is kept regardless of which variant is chosen.  If no variant is asked for, the first one is used.  Templates without any variants are returned unchanged, unless a variant was asked for, in which case that's an error.
+*/
+pub fn select_variant<'a>(src: &'a str, variant: Option<&str>) -> Result<Cow<'a, str>> {
+    let markers: Vec<_> = RE_VARIANT.captures_iter(src).collect();
+
+    let first = match markers.first() {
+        Some(first) => first.get(0).unwrap().start(),
+        None => {
+            return match variant {
+                None => Ok(src.into()),
+                Some(name) => Err((
+                    Blame::Human,
+                    format!(
+                        "template does not have a `{}` variant; it has no variants at all",
+                        name
+                    ),
+                )
+                    .into()),
+            };
+        }
+    };
+
+    let index = match variant {
+        None => 0,
+        Some(name) => markers
+            .iter()
+            .position(|m| m.get(1).unwrap().as_str() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = markers.iter().map(|m| m.get(1).unwrap().as_str()).collect();
+                MainError::from((
+                    Blame::Human,
+                    format!(
+                        "template does not have a `{}` variant; it has: {}",
+                        name,
+                        names.join(", ")
+                    ),
+                ))
+            })?,
+    };
+
+    let body_start = markers[index].get(0).unwrap().end();
+    let body_end = markers
+        .get(index + 1)
+        .map(|m| m.get(0).unwrap().start())
+        .unwrap_or_else(|| src.len());
+
+    let mut result = String::with_capacity(first + body_end - body_start);
+    result.push_str(&src[..first]);
+    result.push_str(&src[body_start..body_end]);
+    Ok(result.into())
+}
+
 /**
 Returns the path to the template directory.
 */
@@ -118,7 +175,7 @@ pub fn get_template_path() -> PathBuf {
         return path.into();
     }
 
-    app::data_dir().unwrap().join("templates")
+    app::data_dir().join("templates")
 }
 
 /**
@@ -154,6 +211,7 @@ pub fn get_template(name: &str) -> Result<Cow<'static, str>> {
 
 fn builtin_template(name: &str) -> Option<&'static str> {
     Some(match name {
+        "assert" => include_str!("templates/assert.rs").trim_end(),
         "expr" => include_str!("templates/expr.rs").trim_end(),
         "file" => include_str!("templates/file.rs").trim_end(),
         "loop" => include_str!("templates/loop.rs").trim_end(),
@@ -227,3 +285,16 @@ fn show(path: bool) -> Result<()> {
         Ok(())
     }
 }
+
+#[test]
+fn test_select_variant() {
+    let src = "shared\n//# variant a\nfirst\n//# variant b\nsecond\n";
+
+    assert_eq!(select_variant(src, None).unwrap(), "shared\nfirst\n");
+    assert_eq!(select_variant(src, Some("a")).unwrap(), "shared\nfirst\n");
+    assert_eq!(select_variant(src, Some("b")).unwrap(), "shared\nsecond\n");
+    assert!(select_variant(src, Some("c")).is_err());
+
+    assert_eq!(select_variant("plain\n", None).unwrap(), "plain\n");
+    assert!(select_variant("plain\n", Some("a")).is_err());
+}
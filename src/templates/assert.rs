@@ -0,0 +1,12 @@
+#{prelude}
+
+fn main() {
+  #[allow(unused_variables)]
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let passed: bool = {#{script}};
+  if !passed {
+    eprintln!("assertion failed: {}", #{script_literal});
+    std::process::exit(1);
+  }
+}
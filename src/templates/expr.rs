@@ -1,13 +1,165 @@
 #{prelude}
 
+/// Reads all of stdin the first time it's called; later calls return the same text.
+#[allow(dead_code)]
+fn stdin() -> String {
+  thread_local! {
+    static STDIN: std::cell::RefCell<Option<String>> = std::cell::RefCell::new(None);
+  }
+  STDIN.with(|stdin| {
+    stdin.borrow_mut().get_or_insert_with(|| {
+      let mut text = String::new();
+      std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+        .expect("could not read stdin");
+      text
+    }).clone()
+  })
+}
+
+/// Reads an environment variable, giving an empty string if it isn't set (or isn't valid Unicode).
+#[allow(dead_code)]
+fn env(key: &str) -> String {
+  std::env::var(key).unwrap_or_default()
+}
+
+//# variant print
+fn main() {
+  #[allow(unused_variables)]
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let __cargo_eval_expr = || -> Result<(), Box<dyn std::error::Error>> {
+    // A `match` keeps any temporaries the expression borrows from alive until it's been printed; a `let` wouldn't.
+    match {#{script}} {
+      #{bind} => #{println}!("#{format}", #{bind}),
+    }
+    Ok(())
+  };
+
+  if let Err(__cargo_eval_e) = __cargo_eval_expr() {
+    eprintln!("Error: {}", __cargo_eval_e);
+    std::process::exit(1);
+  }
+}
+//# variant unwrap
+trait __CargoEvalUnwrap {
+  type Inner;
+  fn __cargo_eval_unwrap(self) -> Result<Self::Inner, String>;
+}
+
+impl<T> __CargoEvalUnwrap for Option<T> {
+  type Inner = T;
+  fn __cargo_eval_unwrap(self) -> Result<T, String> {
+    self.ok_or_else(|| "expression evaluated to `None`".into())
+  }
+}
+
+impl<T, E: std::fmt::Debug> __CargoEvalUnwrap for Result<T, E> {
+  type Inner = T;
+  fn __cargo_eval_unwrap(self) -> Result<T, String> {
+    self.map_err(|e| format!("expression evaluated to `Err({:?})`", e))
+  }
+}
+
+fn main() {
+  #[allow(unused_variables)]
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let __cargo_eval_expr = || -> Result<(), Box<dyn std::error::Error>> {
+    match __CargoEvalUnwrap::__cargo_eval_unwrap({#{script}}) {
+      Ok(#{bind}) => #{println}!("#{format}", #{bind}),
+      Err(__cargo_eval_msg) => {
+        eprintln!("{}", __cargo_eval_msg);
+        std::process::exit(1);
+      }
+    }
+    Ok(())
+  };
+
+  if let Err(__cargo_eval_e) = __cargo_eval_expr() {
+    eprintln!("Error: {}", __cargo_eval_e);
+    std::process::exit(1);
+  }
+}
+//# variant lines
+fn main() {
+  #[allow(unused_variables)]
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let __cargo_eval_expr = || -> Result<(), Box<dyn std::error::Error>> {
+    let mut __cargo_eval_first = true;
+    for __cargo_eval_item in {#{script}} {
+      if !__cargo_eval_first {
+        #{print}!("{}", #{separator});
+      }
+      __cargo_eval_first = false;
+      #{print}!("#{format}", __cargo_eval_item);
+    }
+    if !__cargo_eval_first {
+      #{println}!();
+    }
+    Ok(())
+  };
+
+  if let Err(__cargo_eval_e) = __cargo_eval_expr() {
+    eprintln!("Error: {}", __cargo_eval_e);
+    std::process::exit(1);
+  }
+}
+//# variant join
 fn main() {
-  let expr = || -> Result<(), Box<dyn std::error::Error>> {
-    println!("{:?}", {#{script}});
+  #[allow(unused_variables)]
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let __cargo_eval_expr = || -> Result<(), Box<dyn std::error::Error>> {
+    let __cargo_eval_items: Vec<String> = IntoIterator::into_iter({#{script}})
+      .map(|__cargo_eval_item| __cargo_eval_item.to_string())
+      .collect();
+    #{println}!("{}", __cargo_eval_items.join(#{separator}));
+    Ok(())
+  };
+
+  if let Err(__cargo_eval_e) = __cargo_eval_expr() {
+    eprintln!("Error: {}", __cargo_eval_e);
+    std::process::exit(1);
+  }
+}
+//# variant json
+fn main() {
+  #[allow(unused_variables)]
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let __cargo_eval_expr = || -> Result<(), Box<dyn std::error::Error>> {
+    match {#{script}} {
+      #{bind} => #{println}!("{}", serde_json::to_string_pretty(&#{bind})?),
+    }
+    Ok(())
+  };
+
+  if let Err(__cargo_eval_e) = __cargo_eval_expr() {
+    eprintln!("Error: {}", __cargo_eval_e);
+    std::process::exit(1);
+  }
+}
+//# variant apply
+fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let __cargo_eval_expr = || -> Result<(), Box<dyn std::error::Error>> {
+    let __cargo_eval_f = {#{script}};
+    if args.is_empty() {
+      return Err("expected at least one argument to apply the closure to".into());
+    }
+    for __cargo_eval_arg in &args {
+      let __cargo_eval_value = __cargo_eval_arg
+        .parse()
+        .map_err(|__cargo_eval_e| format!("could not parse argument `{}`: {}", __cargo_eval_arg, __cargo_eval_e))?;
+      #{println}!("#{format}", __cargo_eval_f(__cargo_eval_value));
+    }
     Ok(())
   };
 
-  if let Err(e) = expr() {
-    eprintln!("Error: {}", e);
+  if let Err(__cargo_eval_e) = __cargo_eval_expr() {
+    eprintln!("Error: {}", __cargo_eval_e);
     std::process::exit(1);
   }
 }
@@ -1 +1 @@
-#{script}
+#{script}#{panic_hook}
@@ -1,31 +1,139 @@
 use std::any::Any;
 use std::io::BufRead;
 
+fn __cargo_eval_assert_closure<F, T>(closure: F) -> F
+  where
+    F: FnMut(String, usize) -> T
+{
+  closure
+}
+
+//# variant utf8
 fn main() {
-  let mut closure = assert_closure({
+  let mut __cargo_eval_closure = __cargo_eval_assert_closure({
     #{script}
   });
 
-  let stdin = std::io::stdin();
-  let mut it = stdin.lock().lines().enumerate();
+  let __cargo_eval_stdin = std::io::stdin();
+  let mut __cargo_eval_it = __cargo_eval_stdin.lock().lines().enumerate();
 
-  while let Some((i, Ok(line))) = it.next()  {
-    let output = closure(line, i);
+  while let Some((__cargo_eval_i, Ok(__cargo_eval_line))) = __cargo_eval_it.next()  {
+    let __cargo_eval_output = __cargo_eval_closure(__cargo_eval_line, __cargo_eval_i);
 
-    let display = {
-      let output_any: &dyn Any = &output;
-      !output_any.is::<()>()
+    let __cargo_eval_display = {
+      let __cargo_eval_output_any: &dyn Any = &__cargo_eval_output;
+      !__cargo_eval_output_any.is::<()>()
     };
 
-    if display {
-      println!("{:?}", output);
+    if __cargo_eval_display {
+      #{println}!("{:?}", __cargo_eval_output);
     }
   }
 }
+//# variant decode
+fn main() {
+  let mut __cargo_eval_closure = __cargo_eval_assert_closure({
+    #{script}
+  });
 
-fn assert_closure<F, T>(closure: F) -> F
+  let __cargo_eval_label = #{input_encoding};
+  let __cargo_eval_encoding = encoding_rs::Encoding::for_label(__cargo_eval_label.as_bytes()).unwrap_or_else(|| {
+    eprintln!("unknown input encoding `{}`", __cargo_eval_label);
+    std::process::exit(1);
+  });
+
+  let __cargo_eval_stdin = std::io::stdin();
+  let mut __cargo_eval_stdin = __cargo_eval_stdin.lock();
+  let mut __cargo_eval_bytes = vec![];
+  let mut __cargo_eval_i = 0;
+  while __cargo_eval_stdin.read_until(b'\n', &mut __cargo_eval_bytes).map(|n| n > 0).unwrap_or(false) {
+    if __cargo_eval_bytes.ends_with(b"\n") {
+      __cargo_eval_bytes.pop();
+      if __cargo_eval_bytes.ends_with(b"\r") {
+        __cargo_eval_bytes.pop();
+      }
+    }
+    let __cargo_eval_line = __cargo_eval_encoding.decode_without_bom_handling(&__cargo_eval_bytes).0.into_owned();
+    __cargo_eval_bytes.clear();
+
+    let __cargo_eval_output = __cargo_eval_closure(__cargo_eval_line, __cargo_eval_i);
+    __cargo_eval_i += 1;
+
+    let __cargo_eval_display = {
+      let __cargo_eval_output_any: &dyn Any = &__cargo_eval_output;
+      !__cargo_eval_output_any.is::<()>()
+    };
+
+    if __cargo_eval_display {
+      #{println}!("{:?}", __cargo_eval_output);
+    }
+  }
+}
+//# variant parallel
+fn __cargo_eval_assert_parallel_closure<F, T>(closure: F) -> F
   where
-    F: FnMut(String, usize) -> T
+    F: Fn(String, usize) -> T + Sync,
+    T: Send
 {
   closure
 }
+
+fn main() {
+  let __cargo_eval_closure = __cargo_eval_assert_parallel_closure({
+    #{script}
+  });
+  let __cargo_eval_closure = &__cargo_eval_closure;
+
+  // One thread reads lines and hands them out to the workers; results come back tagged with their line number, so they can be put back in order.
+  let __cargo_eval_workers: usize = #{parallel};
+  let (__cargo_eval_line_tx, __cargo_eval_line_rx) = std::sync::mpsc::sync_channel(__cargo_eval_workers * 4);
+  let __cargo_eval_line_rx = &std::sync::Mutex::new(__cargo_eval_line_rx);
+  let (__cargo_eval_output_tx, __cargo_eval_output_rx) = std::sync::mpsc::channel();
+
+  std::thread::scope(|__cargo_eval_scope| {
+    __cargo_eval_scope.spawn(move || {
+      let __cargo_eval_stdin = std::io::stdin();
+      let mut __cargo_eval_it = __cargo_eval_stdin.lock().lines().enumerate();
+      while let Some((__cargo_eval_i, Ok(__cargo_eval_line))) = __cargo_eval_it.next() {
+        if __cargo_eval_line_tx.send((__cargo_eval_i, __cargo_eval_line)).is_err() {
+          break;
+        }
+      }
+    });
+
+    for _ in 0..__cargo_eval_workers {
+      let __cargo_eval_output_tx = __cargo_eval_output_tx.clone();
+      __cargo_eval_scope.spawn(move || loop {
+        let __cargo_eval_next = __cargo_eval_line_rx.lock().unwrap().recv();
+        #[allow(unused_variables)]
+        let (__cargo_eval_i, __cargo_eval_line) = match __cargo_eval_next {
+          Ok(__cargo_eval_next) => __cargo_eval_next,
+          Err(_) => break,
+        };
+        let __cargo_eval_output = __cargo_eval_closure(__cargo_eval_line, __cargo_eval_i);
+        if __cargo_eval_output_tx.send((__cargo_eval_i, __cargo_eval_output)).is_err() {
+          break;
+        }
+      });
+    }
+    drop(__cargo_eval_output_tx);
+
+    let mut __cargo_eval_pending = std::collections::BTreeMap::new();
+    let mut __cargo_eval_next_i = 0;
+    for (__cargo_eval_i, __cargo_eval_output) in __cargo_eval_output_rx {
+      __cargo_eval_pending.insert(__cargo_eval_i, __cargo_eval_output);
+      while let Some(__cargo_eval_output) = __cargo_eval_pending.remove(&__cargo_eval_next_i) {
+        __cargo_eval_next_i += 1;
+
+        let __cargo_eval_display = {
+          let __cargo_eval_output_any: &dyn Any = &__cargo_eval_output;
+          !__cargo_eval_output_any.is::<()>()
+        };
+
+        if __cargo_eval_display {
+          #{println}!("{:?}", __cargo_eval_output);
+        }
+      }
+    }
+  });
+}
@@ -0,0 +1,184 @@
+/*!
+This module keeps a log of how long scripts took to build, for `--log-timings`, and contains the `timings` subcommand, which summarises it.
+
+The log is just a text file in the data directory, one line per build, with tab-separated fields: when the build happened (in milliseconds since the UNIX epoch), which script it was, whether the cached executable was used (`hit`) or it had to be compiled (`miss`), and how long compiling took, in milliseconds.  Nothing ever leaves the machine; it's only there for your own curiosity.
+*/
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::app;
+use crate::error::Result;
+use crate::platform;
+
+/**
+Name of the log file, in the data directory.
+*/
+const LOG_FILE: &str = "timings.log";
+
+#[derive(Debug)]
+pub struct Args;
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::SubCommand;
+
+        SubCommand::with_name("timings")
+            .about("Summarises the build timings recorded with `--log-timings`.")
+    }
+
+    pub fn parse(_: &clap::ArgMatches) -> Self {
+        Args
+    }
+}
+
+pub fn try_main(_: Args) -> Result<i32> {
+    let path = log_path();
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let entries = text.lines().filter_map(parse_line).collect::<Vec<_>>();
+    if entries.is_empty() {
+        println!("no builds logged yet; use `--log-timings` to log them");
+        return Ok(0);
+    }
+
+    for line in summarise(&entries) {
+        println!("{}", line);
+    }
+    Ok(0)
+}
+
+/**
+Appends a line for a build to the log.
+*/
+pub fn log_build(script: &str, compiled: bool, duration: Duration) -> Result<()> {
+    let path = log_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    // One `write` per line, so concurrent builds don't get their lines mixed up.
+    let line = format!(
+        "{}\t{}\t{}\t{}\n",
+        platform::current_time(),
+        script.replace(['\t', '\n'], " "),
+        if compiled { "miss" } else { "hit" },
+        duration.as_millis()
+    );
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn log_path() -> PathBuf {
+    app::data_dir().join(LOG_FILE)
+}
+
+/**
+A single build from the log.
+*/
+#[derive(Debug, PartialEq)]
+struct Entry<'a> {
+    script: &'a str,
+    compiled: bool,
+    millis: u128,
+}
+
+/**
+Parses a line of the log.  Lines that don't make sense are skipped.
+*/
+fn parse_line(line: &str) -> Option<Entry<'_>> {
+    let mut fields = line.split('\t');
+    let _timestamp = fields.next()?.parse::<u128>().ok()?;
+    let script = fields.next()?;
+    let compiled = match fields.next()? {
+        "miss" => true,
+        "hit" => false,
+        _ => return None,
+    };
+    let millis = fields.next()?.parse().ok()?;
+    Some(Entry {
+        script,
+        compiled,
+        millis,
+    })
+}
+
+/**
+Works out the lines of the summary: overall numbers, then the total compile time for each script that had to be compiled (and how many times it was), slowest first.
+*/
+fn summarise(entries: &[Entry<'_>]) -> Vec<String> {
+    let compiled = entries.iter().filter(|e| e.compiled).collect::<Vec<_>>();
+    let total = compiled.iter().map(|e| e.millis).sum::<u128>();
+
+    let mut lines = vec![format!(
+        "{} builds: {} compiled, {} from the cache",
+        entries.len(),
+        compiled.len(),
+        entries.len() - compiled.len()
+    )];
+    if compiled.is_empty() {
+        return lines;
+    }
+    lines.push(format!(
+        "compile time: {} in total, {} on average",
+        seconds(total),
+        seconds(total / compiled.len() as u128)
+    ));
+
+    let mut scripts: Vec<(&str, u128, usize)> = vec![];
+    for entry in compiled {
+        match scripts.iter_mut().find(|(s, _, _)| *s == entry.script) {
+            Some((_, millis, count)) => {
+                *millis += entry.millis;
+                *count += 1;
+            }
+            None => scripts.push((entry.script, entry.millis, 1)),
+        }
+    }
+    scripts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (script, millis, count) in scripts {
+        lines.push(format!("{:>9}  {:>4}x  {}", seconds(millis), count, script));
+    }
+    lines
+}
+
+fn seconds(millis: u128) -> String {
+    format!("{}.{:01}s", millis / 1000, millis % 1000 / 100)
+}
+
+#[test]
+fn test_summarise() {
+    let log = "1000\ta.rs\tmiss\t2500\n\
+        1001\ta.rs\thit\t0\n\
+        garbage\n\
+        1002\texpr-abc\tmiss\t1200\n\
+        1003\ta.rs\tmiss\t500\n";
+    let entries = log.lines().filter_map(parse_line).collect::<Vec<_>>();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(
+        entries[1],
+        Entry {
+            script: "a.rs",
+            compiled: false,
+            millis: 0,
+        }
+    );
+
+    assert_eq!(
+        summarise(&entries),
+        vec![
+            "4 builds: 3 compiled, 1 from the cache",
+            "compile time: 4.2s in total, 1.4s on average",
+            "     3.0s     2x  a.rs",
+            "     1.2s     1x  expr-abc",
+        ]
+    );
+}
@@ -2,7 +2,11 @@
 This module just contains other random implementation stuff.
 */
 use std::error::Error;
+use std::io;
 use std::marker::PhantomData;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /**
 Used to defer a closure until the value is dropped.
@@ -52,6 +56,367 @@ where
     }
 }
 
+/**
+Splits a string into words the way a POSIX shell would, minus any of the actual expansions.
+
+Words are separated by whitespace.  Single quotes preserve everything up to the next single quote; double quotes preserve everything up to the next unescaped double quote.  Outside of single quotes, a backslash escapes the following character.
+*/
+pub fn split_shell_words(s: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+#[test]
+fn test_split_shell_words() {
+    assert_eq!(split_shell_words(""), Vec::<String>::new());
+    assert_eq!(
+        split_shell_words("  -C target-cpu=native\t-O "),
+        vec!["-C", "target-cpu=native", "-O"]
+    );
+    assert_eq!(
+        split_shell_words(r#"--cfg 'feature="a b"' "x\"y" a\ b ''"#),
+        vec!["--cfg", r#"feature="a b""#, r#"x"y"#, "a b", ""]
+    );
+}
+
+/**
+Parses the contents of a `.env` file into `KEY=VALUE` pairs.
+
+Blank lines and `#` comments are skipped, a leading `export ` is ignored, and values may be wrapped in single or double quotes.  Lines that don't make sense are left out; their (1-based) line numbers are returned alongside, so they can be warned about.
+*/
+pub fn parse_dotenv(s: &str) -> (Vec<(String, String)>, Vec<usize>) {
+    let mut vars = vec![];
+    let mut malformed = vec![];
+
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                malformed.push(i + 1);
+                continue;
+            }
+        };
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            malformed.push(i + 1);
+            continue;
+        }
+
+        let value = match value.chars().next() {
+            Some(q @ '"') | Some(q @ '\'') => match value[1..].strip_suffix(q) {
+                Some(inner) => inner,
+                None => {
+                    malformed.push(i + 1);
+                    continue;
+                }
+            },
+            // Unquoted values can have a trailing comment.
+            _ => value.split(" #").next().unwrap_or(value).trim_end(),
+        };
+        vars.push((key.to_owned(), value.to_owned()));
+    }
+
+    (vars, malformed)
+}
+
+#[test]
+fn test_parse_dotenv() {
+    let (vars, malformed) = parse_dotenv(
+        "# a comment\n\nA=1\nexport B = two words \nC=\"quoted # not a comment\"\nD='x'\nE=3 # comment\nF=\n\
+        not a var\n=5\nG=\"unclosed\nH-I=6\n",
+    );
+    assert_eq!(
+        vars,
+        vec![
+            ("A".to_owned(), "1".to_owned()),
+            ("B".to_owned(), "two words".to_owned()),
+            ("C".to_owned(), "quoted # not a comment".to_owned()),
+            ("D".to_owned(), "x".to_owned()),
+            ("E".to_owned(), "3".to_owned()),
+            ("F".to_owned(), "".to_owned()),
+        ]
+    );
+    assert_eq!(malformed, vec![9, 10, 11, 12]);
+}
+
+/**
+Parses a Rust version, like `1.70` or `1.70.0`, into its major, minor and patch numbers.  Missing parts count as zero.
+
+Anything after the numbers, like the `-nightly` in `1.72.0-nightly`, is ignored, as Cargo does when checking `rust-version`.
+*/
+pub fn parse_rust_version(s: &str) -> Option<(u64, u64, u64)> {
+    let numbers = s.split('-').next()?;
+    let mut parts = numbers.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+#[test]
+fn test_parse_rust_version() {
+    assert_eq!(parse_rust_version("1.70"), Some((1, 70, 0)));
+    assert_eq!(parse_rust_version("1.70.2"), Some((1, 70, 2)));
+    assert_eq!(parse_rust_version("1"), Some((1, 0, 0)));
+    assert_eq!(parse_rust_version("1.72.0-nightly"), Some((1, 72, 0)));
+    assert_eq!(parse_rust_version(""), None);
+    assert_eq!(parse_rust_version("1.x"), None);
+    assert_eq!(parse_rust_version("1.2.3.4"), None);
+    assert_eq!(parse_rust_version("1..2"), None);
+}
+
+/**
+Waits for a child process to exit, but only for so long.
+
+If `timeout` is given, and the child is still running once it's up, the child is killed, and this returns `None`.  On UNIX, if the child leads its own process group, the whole group is killed; otherwise, anything the child started is left to finish on its own.
+
+A child with its own process group isn't in the terminal's foreground group, so it won't get Ctrl-C.  While there's a timeout, `SIGINT` and `SIGTERM` are passed on to the child's group, and then, once it's gone, to us.
+*/
+pub fn wait_timeout(
+    child: &mut process::Child,
+    timeout: Option<Duration>,
+) -> io::Result<Option<process::ExitStatus>> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return child.wait().map(Some),
+    };
+
+    #[cfg(unix)]
+    let signals = forward_signals::ForwardSignals::install();
+
+    let start = Instant::now();
+    loop {
+        if let Some(st) = child.try_wait()? {
+            return Ok(Some(st));
+        }
+        #[cfg(unix)]
+        {
+            if let Some(sig) = forward_signals::received() {
+                info!("passing signal {} on to child process {}", sig, child.id());
+                unsafe {
+                    libc::kill(-(child.id() as libc::pid_t), sig);
+                }
+                let st = child.wait()?;
+                drop(signals);
+                unsafe {
+                    libc::raise(sig);
+                }
+                // If we're still here, whoever was handling the signal before didn't want us to stop.
+                return Ok(Some(st));
+            }
+        }
+        if start.elapsed() >= timeout {
+            info!("killing child process {} after {:?}", child.id(), timeout);
+            // It might have only *just* exited, in which case there's nothing to kill.
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(unix)]
+mod forward_signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    /**
+    The last signal caught, or zero.  Setting this is about all a signal handler can safely do.
+    */
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn handler(sig: libc::c_int) {
+        RECEIVED.store(sig, Ordering::SeqCst);
+    }
+
+    /**
+    Catches `SIGINT` and `SIGTERM` for as long as it's around, putting back whatever was handling them before when it's dropped.
+    */
+    pub struct ForwardSignals {
+        old: Vec<(libc::c_int, libc::sighandler_t)>,
+    }
+
+    impl ForwardSignals {
+        pub fn install() -> ForwardSignals {
+            RECEIVED.store(0, Ordering::SeqCst);
+            let old = SIGNALS
+                .iter()
+                .map(|&sig| unsafe {
+                    (
+                        sig,
+                        libc::signal(
+                            sig,
+                            handler as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                        ),
+                    )
+                })
+                .collect();
+            ForwardSignals { old }
+        }
+    }
+
+    impl Drop for ForwardSignals {
+        fn drop(&mut self) {
+            for &(sig, old) in &self.old {
+                unsafe {
+                    libc::signal(sig, old);
+                }
+            }
+        }
+    }
+
+    /**
+    Returns the signal caught since the last call, if there was one.
+    */
+    pub fn received() -> Option<libc::c_int> {
+        match RECEIVED.swap(0, Ordering::SeqCst) {
+            0 => None,
+            sig => Some(sig),
+        }
+    }
+}
+
+/**
+A writer which passes everything through to `inner`, except ANSI escape sequences, such as the ones Cargo colours its output with.
+
+It keeps track of where it is in a sequence between writes, so it doesn't matter if one gets split up.
+*/
+pub struct StripAnsi<W> {
+    inner: W,
+    state: AnsiState,
+}
+
+#[derive(Copy, Clone)]
+enum AnsiState {
+    Text,
+    /// Just after an `ESC`.
+    Escape,
+    /// In a control sequence (`ESC [`), which ends with a byte from `@` to `~`.
+    Csi,
+    /// In an operating system command (`ESC ]`), which ends with `BEL` or `ESC \`.
+    Osc,
+    OscEscape,
+}
+
+impl<W> StripAnsi<W> {
+    pub fn new(inner: W) -> Self {
+        StripAnsi {
+            inner,
+            state: AnsiState::Text,
+        }
+    }
+}
+
+impl<W: io::Write> io::Write for StripAnsi<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        use self::AnsiState::*;
+
+        let mut text = Vec::with_capacity(buf.len());
+        for &b in buf {
+            self.state = match (self.state, b) {
+                (Text, 0x1b) => Escape,
+                (Text, b) => {
+                    text.push(b);
+                    Text
+                }
+                (Escape, b'[') => Csi,
+                (Escape, b']') => Osc,
+                // Anything else is a two-byte sequence.
+                (Escape, _) => Text,
+                (Csi, 0x40..=0x7e) => Text,
+                (Csi, _) => Csi,
+                (Osc, 0x07) => Text,
+                (Osc, 0x1b) => OscEscape,
+                (Osc, _) => Osc,
+                (OscEscape, _) => Text,
+            };
+        }
+        self.inner.write_all(&text)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn test_strip_ansi() {
+    use std::io::Write;
+
+    let mut out = StripAnsi::new(vec![]);
+    out.write_all(b"\x1b[1m\x1b[31merror\x1b[0m: oops\n")
+        .unwrap();
+    // Split in the middle of a sequence.
+    out.write_all(b"\x1b[3").unwrap();
+    out.write_all(b"8;5;12mnote\x1b[0m\n").unwrap();
+    out.write_all(b"\x1b]8;;http://example.com\x07link\x1b]8;;\x1b\\ \xe2\x9c\x93\n")
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out.inner).unwrap(),
+        "error: oops\nnote\nlink \u{2713}\n"
+    );
+}
+
 #[cfg(feature = "suppress-cargo-output")]
 pub use self::suppress_child_output::{suppress_child_output, ChildToken};
 
@@ -69,11 +434,15 @@ mod suppress_child_output {
     - the process takes longer than `timeout` to complete, or
     - the process exits and signals a failure.
 
-    In either of those cases, the existing output is flushes to the current process' stderr, and all further output from the child is passed through.
+    In either of those cases, the existing output is flushes to the current process' stderr, and all further output from the child is passed through.  If `strip_ansi` is set, any ANSI escape sequences are removed from it on the way.
 
     In other words: if the child successfully completes quickly, it's stderr output is suppressed.  Otherwise, it's let through.
     */
-    pub fn suppress_child_output(cmd: &mut Command, timeout: Duration) -> Result<ChildToken> {
+    pub fn suppress_child_output(
+        cmd: &mut Command,
+        timeout: Duration,
+        strip_ansi: bool,
+    ) -> Result<ChildToken> {
         cmd.stderr(process::Stdio::piped());
 
         let mut child = cmd.spawn()?;
@@ -82,7 +451,7 @@ mod suppress_child_output {
         let timeout_chan = chan::after(timeout);
         let (done_sig, done_gate) = chan::sync(0);
 
-        let _ = thread::spawn(move || {
+        let stderr_join = thread::spawn(move || {
             let show_stderr;
             let mut recv_done = false;
             chan_select! {
@@ -96,7 +465,12 @@ mod suppress_child_output {
             }
             if show_stderr {
                 let mut stderr = stderr;
-                io::copy(&mut stderr, &mut io::stderr()).expect("could not copy child stderr");
+                let copied = if strip_ansi {
+                    io::copy(&mut stderr, &mut super::StripAnsi::new(io::stderr()))
+                } else {
+                    io::copy(&mut stderr, &mut io::stderr())
+                };
+                copied.expect("could not copy child stderr");
             }
             if !recv_done {
                 done_gate.recv();
@@ -106,36 +480,49 @@ mod suppress_child_output {
         Ok(ChildToken {
             child,
             done_sig: Some(done_sig),
-            // stderr_join: stderr_join,
+            stderr_join: Some(stderr_join),
         })
     }
 
     pub struct ChildToken {
         child: process::Child,
         done_sig: Option<chan::Sender<bool>>,
-        // stderr_join: Option<thread::JoinHandle<()>>,
+        stderr_join: Option<thread::JoinHandle<()>>,
     }
 
     impl ChildToken {
-        pub fn status(&mut self) -> io::Result<process::ExitStatus> {
-            let st = match self.child.wait() {
+        /**
+        Waits for the child to exit, for at most `timeout`; see `wait_timeout`.
+        */
+        pub fn status_timeout(
+            &mut self,
+            timeout: Option<Duration>,
+        ) -> io::Result<Option<process::ExitStatus>> {
+            let st = match super::wait_timeout(&mut self.child, timeout) {
                 Ok(r) => r,
                 Err(e) => {
                     if let Some(done_sig) = self.done_sig.take() {
                         done_sig.send(false);
                     }
+                    self.join_stderr();
                     return Err(e);
                 }
             };
             if let Some(done_sig) = self.done_sig.take() {
-                done_sig.send(st.success());
+                done_sig.send(st.is_some_and(|st| st.success()));
             }
-            // if let Some(stderr_join) = self.stderr_join.take() {
-            //     stderr_join.join()
-            //         .expect("child stderr thread failed");
-            // }
+            self.join_stderr();
             Ok(st)
         }
+
+        /**
+        Waits for any output being passed through to be written out, so it doesn't get lost if we exit straight afterwards.
+        */
+        fn join_stderr(&mut self) {
+            if let Some(stderr_join) = self.stderr_join.take() {
+                stderr_join.join().expect("child stderr thread failed");
+            }
+        }
     }
 }
 
@@ -0,0 +1,67 @@
+/*!
+This module contains the `version` subcommand.
+*/
+use crate::app;
+use crate::error::Result;
+
+#[derive(Debug)]
+pub struct Args {
+    verbose: bool,
+}
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name("version")
+            .about("Prints version information.")
+            .arg(Arg::with_name("verbose")
+                .help("Also print information about how `cargo-eval` was built, and where it keeps its files.")
+                .long("verbose")
+                .short("v")
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            verbose: m.is_present("verbose"),
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    // This has to match what `--version` prints.
+    println!("{} {}", app::name(), env!("CARGO_PKG_VERSION"));
+
+    if args.verbose {
+        let features = enabled_features();
+        println!("rustc: {}", env!("CARGO_EVAL_RUSTC_VERSION"));
+        println!("target: {}", env!("CARGO_EVAL_TARGET"));
+        println!(
+            "features: {}",
+            if features.is_empty() {
+                "none".into()
+            } else {
+                features.join(", ")
+            }
+        );
+        println!("cache-dir: {}", app::cache_dir().display());
+        println!("data-dir: {}", app::data_dir().display());
+    }
+
+    Ok(0)
+}
+
+/**
+Returns the names of the Cargo features `cargo-eval` was built with.
+*/
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(feature = "suppress-cargo-output") {
+        features.push("suppress-cargo-output");
+    }
+    if cfg!(feature = "fetch") {
+        features.push("fetch");
+    }
+    features
+}
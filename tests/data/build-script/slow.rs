@@ -0,0 +1,4 @@
+// Takes far longer than any test is willing to wait for a build.
+fn main() {
+    std::thread::sleep(std::time::Duration::from_secs(120));
+}
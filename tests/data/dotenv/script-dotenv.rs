@@ -0,0 +1,4 @@
+fn main() {
+    println!("--output--");
+    println!("{}", std::env::var("DOTENV_GREETING").unwrap_or_else(|_| "unset".into()));
+}
@@ -0,0 +1,9 @@
+fn main() {
+    // Cargo only sets these for build scripts, which this isn't.
+    assert!(
+        std::env::var_os("CARGO_CFG_TARGET_OS").is_none(),
+        "run as a build script"
+    );
+    println!("--output--");
+    println!("not a build script");
+}
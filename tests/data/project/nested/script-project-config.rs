@@ -0,0 +1,6 @@
+use boolinator::Boolinator;
+
+fn main() {
+    println!("--output--");
+    println!("{:?}", true.as_some(1));
+}
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Notes down that it was used, then runs the compiler as usual.
+echo "$@" >> "$CARGO_EVAL_TEST_WRAPPER_LOG"
+exec "$@"
@@ -0,0 +1,2 @@
+// `--panic-location` has nothing it can do with an `async` `main`, which needs a runtime to call it.
+async fn main() {}
@@ -0,0 +1,8 @@
+﻿#!/usr/bin/env run-cargo-eval
+// cargo-deps: boolinator="=0.1.0"
+extern crate boolinator;
+use boolinator::Boolinator;
+fn main() {
+    println!("--output--");
+    println!("{:?}", true.as_some(1));
+}
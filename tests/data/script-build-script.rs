@@ -0,0 +1,6 @@
+#![allow(unexpected_cfgs)]
+
+fn main() {
+    println!("--output--");
+    println!("{}", cfg!(from_build_script));
+}
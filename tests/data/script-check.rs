@@ -0,0 +1,12 @@
+fn double(x: i32) -> i32 {
+    x * 2
+}
+
+fn main() {
+    println!("double(21) = {}", double(21));
+}
+
+#[test]
+fn test_double() {
+    assert_eq!(double(2), 4);
+}
@@ -0,0 +1,5 @@
+fn main() {
+    println!("--output--");
+    println!("{:?}", std::env::var("CARGO_EVAL_TEST_INHERITED").ok());
+    println!("{:?}", std::env::var("CARGO_EVAL_TEST_GIVEN").ok());
+}
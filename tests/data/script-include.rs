@@ -0,0 +1,6 @@
+mod helper;
+
+fn main() {
+    println!("--output--");
+    println!("{}", helper::greet("include"));
+}
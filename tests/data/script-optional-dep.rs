@@ -0,0 +1,13 @@
+/*!
+```cargo
+[dependencies]
+boolinator = { version = "=2.4.0", optional = true }
+
+[features]
+default = ["boolinator"]
+```
+*/
+fn main() {
+    println!("--output--");
+    println!("{}", cfg!(feature = "boolinator"));
+}
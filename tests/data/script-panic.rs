@@ -0,0 +1,9 @@
+#!/usr/bin/env cargo-eval
+//! ```cargo
+//! [package]
+//! edition = "2021"
+//! ```
+fn main() {
+    let items: Vec<i32> = vec![];
+    println!("{}", items[3]);
+}
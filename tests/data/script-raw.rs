@@ -0,0 +1,5 @@
+#!/usr/bin/env cargo-eval
+fn main() {
+    println!("--output--");
+    println!("raw");
+}
@@ -0,0 +1,8 @@
+//! ```cargo
+//! [package]
+//! rust-version = "99.0"
+//! ```
+fn main() {
+    println!("--output--");
+    println!("from the future");
+}
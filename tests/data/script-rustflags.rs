@@ -0,0 +1,4 @@
+fn main() {
+    println!("--output--");
+    println!("{}", cfg!(cargo_eval_test));
+}
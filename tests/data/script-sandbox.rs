@@ -0,0 +1,18 @@
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("--output--");
+
+    let inside = std::env::temp_dir().join("inside.txt");
+    match fs::write(&inside, "ok") {
+        Ok(()) => println!("inside: ok"),
+        Err(_) => println!("inside: denied"),
+    }
+
+    let outside = std::env::args().nth(1).expect("missing path argument");
+    match fs::write(Path::new(&outside).join("outside.txt"), "ok") {
+        Ok(()) => println!("outside: ok"),
+        Err(_) => println!("outside: denied"),
+    }
+}
@@ -0,0 +1,12 @@
+use std::io::BufRead;
+
+fn main() {
+    let mut line = String::new();
+    let n = std::io::stdin().lock().read_line(&mut line).unwrap();
+    println!("--output--");
+    if n == 0 {
+        println!("EOF");
+    } else {
+        println!("got {:?}", line.trim());
+    }
+}
@@ -0,0 +1,5 @@
+fn main() {
+    println!("--output--");
+    let data = std::fs::read_to_string("./data.txt").expect("could not read ./data.txt");
+    println!("{}", data.trim());
+}
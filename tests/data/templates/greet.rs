@@ -0,0 +1,6 @@
+#{prelude}
+
+fn main() {
+    println!("--output--");
+    println!("{}, {}!", "#{greeting}", {#{script}});
+}
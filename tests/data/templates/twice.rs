@@ -0,0 +1,6 @@
+#{prelude}
+
+fn main() {
+    let #{bind} = {#{script}};
+    println!("{:?} {:?}", #{bind}, #{bind} * 2);
+}
@@ -0,0 +1,10 @@
+#{prelude}
+
+//# variant plain
+fn main() {
+    println!("{}", {#{script}});
+}
+//# variant quoted
+fn main() {
+    println!("'{}'", {#{script}});
+}
@@ -13,7 +13,15 @@ extern crate scan_rules;
 mod util;
 
 mod tests {
+    mod batch;
+    mod cache;
+    mod completions;
+    mod dirs;
     mod expr;
+    mod loops;
+    mod new;
+    mod remote;
+    mod repl;
     mod script;
     mod version;
 }
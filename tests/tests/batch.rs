@@ -0,0 +1,57 @@
+const SCRIPTS: &[&str] = &[
+    "tests/data/script-no-deps.rs",
+    "tests/data/script-args.rs",
+    "tests/data/script-cs-env.rs",
+    "tests/data/script-working-dir.rs",
+];
+
+#[test]
+fn test_batch_jobs() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "batch",
+        "--jobs-scripts",
+        "2",
+        SCRIPTS[0],
+        SCRIPTS[1],
+        SCRIPTS[2],
+        SCRIPTS[3]
+    )
+    .unwrap();
+    assert!(out.success());
+
+    // Results come out in the order the scripts were given.
+    let expected = SCRIPTS
+        .iter()
+        .map(|s| format!("{}: ok\n", s))
+        .collect::<String>()
+        + "built 4 of 4 scripts\n";
+    assert_eq!(out.stdout, expected);
+    assert_eq!(
+        std::fs::read_dir(cache_path.join("scripts"))
+            .unwrap()
+            .count(),
+        4
+    );
+}
+
+#[test]
+fn test_batch_failure() {
+    let out = cargo_eval!(
+        #[temp_cache]
+        "batch",
+        "tests/data/does-not-exist.rs",
+        SCRIPTS[0]
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stdout
+        .starts_with("tests/data/does-not-exist.rs: failed: "));
+    assert!(out
+        .stdout
+        .ends_with(&format!("{}: ok\nbuilt 1 of 2 scripts\n", SCRIPTS[0])));
+    assert!(out.stderr.contains("could not find script"));
+}
@@ -0,0 +1,511 @@
+use std::fs;
+use std::path::Path;
+
+fn cache_entries(cache_dir: &Path) -> Vec<String> {
+    let mut entries = fs::read_dir(cache_dir.join("scripts"))
+        .map(|rd| {
+            rd.map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn test_clean_script() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    for script in &["tests/data/script-no-deps.rs", "tests/data/script-args.rs"] {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            script
+        )
+        .unwrap();
+        assert!(out.success());
+    }
+
+    let entries = cache_entries(cache_path);
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].starts_with("file-script-args-"));
+    assert!(entries[1].starts_with("file-script-no-deps-"));
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--clean",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("removed "));
+    assert!(out.stdout.contains("file-script-no-deps-"));
+
+    let entries = cache_entries(cache_path);
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].starts_with("file-script-args-"));
+
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.stderr.contains("recompiling because"));
+
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "tests/data/script-args.rs"
+    )
+    .unwrap();
+    assert!(!out.stderr.contains("recompiling because"));
+}
+
+#[test]
+fn test_rustflags_rebuild() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--rustflags",
+        "--cfg cargo_eval_test",
+        "tests/data/script-rustflags.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+
+    // Different flags must not reuse the cached executable, or its cache entry.
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "tests/data/script-rustflags.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("false") => ()
+    )
+    .unwrap();
+    assert_eq!(cache_entries(cache_path).len(), 2);
+
+    // So going back to the first flags finds what they built still there.
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "--rustflags",
+        "--cfg cargo_eval_test",
+        "tests/data/script-rustflags.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+    assert!(!out.stderr.contains("recompiling because"));
+    assert_eq!(cache_entries(cache_path).len(), 2);
+}
+
+#[test]
+fn test_codegen_units_rebuild() {
+    let cache_dir = crate::util::temp_cache_dir();
+
+    for units in &["1", "2"] {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--codegen-units",
+            units,
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        assert!(out.success());
+    }
+
+    // Going back to a different setting must not reuse the last executable.
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "--codegen-units",
+        "1",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.stderr.contains("recompiling because"));
+
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "--codegen-units",
+        "1",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.stderr.contains("recompiling because"));
+}
+
+#[test]
+fn test_max_cache_entries() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    let mut first = None;
+    for expr in &["1", "2", "3"] {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--max-cache-entries",
+            "2",
+            "-e",
+            expr
+        )
+        .unwrap();
+        assert!(out.success());
+        if first.is_none() {
+            first = cache_entries(cache_path).pop();
+        }
+    }
+
+    let entries = cache_entries(cache_path);
+    assert_eq!(entries.len(), 2);
+    assert!(!entries.contains(&first.unwrap()));
+
+    // That wouldn't even leave the script that's about to run.
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--max-cache-entries",
+        "0",
+        "-e",
+        "4"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("`0` is not a valid number of entries"));
+}
+
+/**
+Rewrites `path` with `content`, leaving its modification time alone.
+*/
+fn rewrite_keeping_mtime(path: &Path, content: &str) {
+    let mtime = fs::metadata(path).unwrap().modified().unwrap();
+    fs::write(path, content).unwrap();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+}
+
+#[test]
+fn test_rebuild_on_hash() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let script_dir = tempdir::TempDir::new("cargo-eval-test-script").unwrap();
+    let script = script_dir.path().join("rebuild-on-hash.rs");
+    fs::write(&script, r#"fn main() { println!("--output--\none"); }"#).unwrap();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        &script
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout_output().trim(), "one");
+
+    // The content changed, so this has to be noticed even though the modification time didn't.
+    rewrite_keeping_mtime(&script, r#"fn main() { println!("--output--\ntwo"); }"#);
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--rebuild-on",
+        "hash",
+        &script
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout_output().trim(), "two");
+}
+
+#[test]
+fn test_rebuild_on_mtime() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let script_dir = tempdir::TempDir::new("cargo-eval-test-script").unwrap();
+    let script = script_dir.path().join("rebuild-on-mtime.rs");
+    fs::write(&script, r#"fn main() { println!("--output--\none"); }"#).unwrap();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--rebuild-on",
+        "mtime",
+        &script
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout_output().trim(), "one");
+
+    // Same modification time, so the cached build is used, even though the content changed.
+    rewrite_keeping_mtime(&script, r#"fn main() { println!("--output--\ntwo"); }"#);
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "--rebuild-on",
+        "mtime",
+        &script
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(!out.stderr.contains("recompiling because"));
+    assert_eq!(out.stdout_output().trim(), "one");
+
+    // A new modification time is enough to trigger a rebuild.
+    let mtime = fs::metadata(&script).unwrap().modified().unwrap();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&script)
+        .unwrap()
+        .set_modified(mtime + std::time::Duration::from_secs(10))
+        .unwrap();
+    let out = cargo_eval!(
+        #[env(RUST_LOG = "cargo_eval=info")]
+        #[temp_cache(cache_dir)]
+        "--rebuild-on",
+        "mtime",
+        &script
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stderr.contains("recompiling because"));
+    assert_eq!(out.stdout_output().trim(), "two");
+}
+
+/**
+Returns the compiled artifacts for `krate` in the binary cache, along with when they were written.
+*/
+fn dep_artifacts(cache_dir: &Path, krate: &str) -> Vec<(String, std::time::SystemTime)> {
+    let prefix = format!("lib{}-", krate);
+    let mut artifacts = fs::read_dir(cache_dir.join("bin/release/deps"))
+        .unwrap()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|e| {
+            let mtime = e.metadata().unwrap().modified().unwrap();
+            (e.file_name().to_string_lossy().into_owned(), mtime)
+        })
+        .collect::<Vec<_>>();
+    artifacts.sort();
+    artifacts
+}
+
+#[test]
+fn test_expr_reuses_deps() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "-d",
+        "boolinator",
+        "-e",
+        "{ use boolinator::Boolinator; true.as_some(1) }"
+    )
+    .unwrap();
+    assert!(out.success());
+    let before = dep_artifacts(cache_path, "boolinator");
+    assert!(!before.is_empty());
+    assert_eq!(fs::read_dir(cache_path.join("locks")).unwrap().count(), 1);
+
+    // A different expression with the same dependencies shouldn't need to build them again.
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "-d",
+        "boolinator",
+        "-e",
+        "{ use boolinator::Boolinator; false.as_some(2) }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(dep_artifacts(cache_path, "boolinator"), before);
+    assert_eq!(cache_entries(cache_path).len(), 2);
+}
+
+#[test]
+fn test_hash_algorithm() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    let mut keys = vec![];
+    for hash in &["sha1", "siphash", "blake3", "sha256"] {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--hash",
+            hash,
+            "--build-only",
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        assert!(out.success());
+
+        let entries = cache_entries(cache_path);
+        let new = entries
+            .into_iter()
+            .filter(|e| !keys.contains(e))
+            .collect::<Vec<_>>();
+        assert_eq!(new.len(), 1, "`--hash {}` didn't add a new key", hash);
+        keys.extend(new);
+    }
+
+    // The default hasn't changed.
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--build-only",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(cache_entries(cache_path).len(), keys.len());
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--hash",
+        "md5",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_why_rebuild() {
+    let cache_dir = crate::util::temp_cache_dir();
+    for &expected in &["because it hasn't been built before", ""] {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--why-rebuild",
+            "--build-only",
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        assert!(out.success());
+        if expected.is_empty() {
+            assert!(!out.stderr.contains("rebuilding"));
+        } else {
+            assert!(out.stderr.contains(&format!("rebuilding {}", expected)));
+        }
+    }
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--why-rebuild",
+        "--build-only",
+        "--dep",
+        "boolinator=0.1.0",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out
+        .stderr
+        .contains("rebuilding because of changes to: dependencies\n"));
+}
+
+#[test]
+fn test_touch() {
+    use std::time::{Duration, SystemTime};
+
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--touch",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("nothing to touch."));
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    // Pretend it was last used a day ago.
+    let entries = cache_entries(cache_path);
+    let meta_path = cache_path
+        .join("scripts")
+        .join(&entries[0])
+        .join("metadata.json");
+    let day_ago = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&meta_path)
+        .unwrap()
+        .set_modified(day_ago)
+        .unwrap();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--touch",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("touched "));
+    // It wasn't run.
+    assert!(!out.stdout.contains("Hello, World!"));
+
+    let modified = fs::metadata(&meta_path).unwrap().modified().unwrap();
+    assert!(modified > day_ago + Duration::from_secs(60 * 60));
+}
+
+#[test]
+fn test_isolated() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+
+    for script in &["tests/data/script-no-deps.rs", "tests/data/script-args.rs"] {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--isolated",
+            script
+        )
+        .unwrap();
+        assert!(out.success());
+    }
+
+    // Each script got a target directory of its own, and nothing went in the shared one.
+    let entries = cache_entries(cache_path);
+    assert_eq!(entries.len(), 2);
+    for entry in &entries {
+        let pkg_path = cache_path.join("scripts").join(entry);
+        assert!(pkg_path.join("target").is_dir());
+    }
+    assert!(!cache_path.join("bin").exists());
+
+    // A script that was built in the shared binary cache gets its own copy.
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "-e",
+        "6 * 7"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(cache_path.join("bin").is_dir());
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--isolated",
+        "-e",
+        "6 * 7"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+    let entries = cache_entries(cache_path);
+    let expr_entry = entries.iter().find(|e| e.starts_with("expr-")).unwrap();
+    assert!(cache_path
+        .join("scripts")
+        .join(expr_entry)
+        .join("target")
+        .is_dir());
+}
@@ -0,0 +1,25 @@
+#[test]
+fn test_completions_bash() {
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "completions",
+        "bash"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("_cargo-eval()"));
+    assert!(out.stdout.contains("completions"));
+    assert!(out.stdout.contains("--expr"));
+}
+
+#[test]
+fn test_completions_bad_shell() {
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "completions",
+        "tcsh"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("isn't a valid value"));
+}
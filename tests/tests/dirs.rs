@@ -0,0 +1,189 @@
+#[test]
+fn test_print_cache_dir() {
+    let cache_dir = "tests/data/cache-dir";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_CACHE_DIR=cache_dir)]
+        #[no_pkg_path]
+        "--print-cache-dir"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout.trim(), cache_dir);
+}
+
+#[test]
+fn test_print_data_dir() {
+    let data_dir = "tests/data/data-dir";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_dir)]
+        #[no_pkg_path]
+        "--print-data-dir"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout.trim(), data_dir);
+}
+
+#[test]
+fn test_dirs_fallback() {
+    let temp_dir = tempdir::TempDir::new("cargo-eval-test-tmp").unwrap();
+    let temp_path = temp_dir.path();
+
+    for (flag, kind) in &[("--print-cache-dir", "cache"), ("--print-data-dir", "data")] {
+        let out = cargo_eval!(
+            #[env(TMPDIR=temp_path, CARGO_EVAL_TEST_NO_PLATFORM_DIRS="1")]
+            #[no_pkg_path]
+            flag
+        )
+        .unwrap();
+        assert!(out.success());
+        let dir = temp_path.join(format!("cargo-eval-{}", kind));
+        assert_eq!(out.stdout.trim(), dir.to_string_lossy());
+        assert!(out
+            .stderr
+            .contains(&format!("warning: could not find a {} directory", kind)));
+    }
+
+    let out = cargo_eval!(
+        #[env(TMPDIR=temp_path, CARGO_EVAL_TEST_NO_PLATFORM_DIRS="1")]
+        #[no_pkg_path]
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+    assert!(temp_path.join("cargo-eval-cache/scripts").is_dir());
+}
+
+#[test]
+fn test_print_cache_dir_conflicts_with_script() {
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--print-cache-dir",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_log_timings() {
+    let data_dir = tempdir::TempDir::new("cargo-eval-test-data").unwrap();
+    let data_path = data_dir.path();
+    let cache_dir = crate::util::temp_cache_dir();
+    let log_path = data_path.join("timings.log");
+
+    for _ in 0..2 {
+        let out = cargo_eval!(
+            #[env(CARGO_EVAL_DATA_DIR=data_path)]
+            #[temp_cache(cache_dir)]
+            "--log-timings",
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        assert!(out.success());
+    }
+
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    for (line, outcome) in lines.iter().zip(&["miss", "hit"]) {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        assert_eq!(fields.len(), 4);
+        assert!(fields[1].ends_with("script-no-deps.rs"));
+        assert_eq!(fields[2], *outcome);
+    }
+
+    // Nothing gets logged without asking.
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[temp_cache(cache_dir)]
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(std::fs::read_to_string(&log_path).unwrap(), log);
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[no_pkg_path]
+        "timings"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out
+        .stdout
+        .starts_with("2 builds: 1 compiled, 1 from the cache\n"));
+}
+
+#[test]
+fn test_alias() {
+    use scan_rules::scanner::QuotedString;
+    let data_dir = tempdir::TempDir::new("cargo-eval-test-data").unwrap();
+    let data_path = data_dir.path();
+    let cache_dir = crate::util::temp_cache_dir();
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[temp_cache(cache_dir)]
+        "--alias",
+        "args",
+        "tests/data/script-args.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    // The alias doesn't need the cache any more.
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--clear-cache"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[temp_cache(cache_dir)]
+        "@args",
+        "hello"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("[0]:", let _: QuotedString, "[1]:", let arg: QuotedString) => {
+            assert_eq!(arg, "hello");
+        }
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[no_pkg_path]
+        "alias",
+        "list"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.starts_with("@args: "));
+    assert!(out.stdout.trim_end().ends_with("script-args.rs"));
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[no_pkg_path]
+        "alias",
+        "remove",
+        "args"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_DATA_DIR=data_path)]
+        #[no_pkg_path]
+        "@args"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("no alias called `args`"));
+}
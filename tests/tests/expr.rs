@@ -22,6 +22,41 @@ fn test_expr_dnc() {
     assert!(!out.success());
 }
 
+#[test]
+fn test_expr_dnc_keep_temps() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--keep-temps",
+        "-e",
+        "swing begin"
+    )
+    .unwrap();
+    assert!(!out.success());
+
+    let pkg_path = out
+        .stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("kept package directory "))
+        .expect("package directory wasn't reported");
+    assert!(std::path::Path::new(pkg_path).starts_with(cache_path));
+    assert!(std::path::Path::new(pkg_path).join("Cargo.toml").is_file());
+}
+
+#[test]
+fn test_expr_time() {
+    let out = cargo_eval!("--time", "-e", with_output_marker!("6 * 7")).unwrap();
+    scan!(out.stdout_output();
+        ("42") => ()
+    )
+    .unwrap();
+    assert!(out
+        .stderr
+        .lines()
+        .any(|line| line.starts_with("evaluated in ")));
+}
+
 #[test]
 fn test_expr_temporary() {
     let out = cargo_eval!("-e", "[1].iter().max()").unwrap();
@@ -110,3 +145,929 @@ fn test_expr_template_override_expr() {
     )
     .unwrap();
 }
+
+#[test]
+fn test_expr_dep_resolved_version() {
+    let out = cargo_eval!(
+        "-d",
+        "boolinator",
+        "-e",
+        with_output_marker!(
+            prelude "use boolinator::Boolinator;";
+            "true.as_some(1)"
+        )
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    )
+    .unwrap();
+    assert!(out
+        .stderr_raw()
+        .contains("resolved dependency `boolinator` to version "));
+}
+
+#[test]
+fn test_expr_args() {
+    let out = cargo_eval!(
+        "-e",
+        with_output_marker!("args.len()"),
+        "--",
+        "a",
+        "b",
+        "c"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("3") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("-e", with_output_marker!("args.join(\"+\")"), "x", "y").unwrap();
+    scan!(out.stdout_output();
+        ("\"x+y\"") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_expr_radix() {
+    for &(radix, expected) in &[("hex", "0x100"), ("oct", "0o400"), ("bin", "0b100000000")] {
+        let out = cargo_eval!("--radix", radix, "-e", with_output_marker!("255 + 1")).unwrap();
+        assert_eq!(out.stdout_output().trim(), expected);
+    }
+}
+
+#[test]
+fn test_expr_stdin() {
+    let out = cargo_eval!(
+        #[stdin("hello\n")]
+        "-e",
+        with_output_marker!("(stdin().trim().len(), stdin() == \"hello\\n\")")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("(5, true)") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_expr_template_variant() {
+    let template_dir = "tests/data/templates";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "-t",
+        "variants",
+        "-e",
+        with_output_marker!("40 + 2")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("42") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "-t",
+        "variants",
+        "--template-variant",
+        "quoted",
+        "-e",
+        with_output_marker!("40 + 2")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("'42'") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "-t",
+        "variants",
+        "--template-variant",
+        "shouty",
+        "-e",
+        "40 + 2"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("template does not have a `shouty` variant; it has: plain, quoted"));
+}
+
+#[test]
+fn test_expr_bind() {
+    let template_dir = "tests/data/templates";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "-t",
+        "twice",
+        "--bind",
+        "n",
+        "-e",
+        with_output_marker!("21")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("21 42") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("--bind", "total", "-e", with_output_marker!("1 + 2")).unwrap();
+    scan!(out.stdout_output();
+        ("3") => ()
+    )
+    .unwrap();
+
+    for name in &["1x", "fn", "_", "a-b"] {
+        let out = cargo_eval!("--bind", name, "-e", "1 + 2").unwrap();
+        assert!(!out.success());
+        assert!(out.stderr.contains("is not a valid identifier"));
+    }
+}
+
+#[test]
+fn test_expr_warn_unused_deps() {
+    let out = cargo_eval!(
+        "-d",
+        "boolinator=2.4.0",
+        "--warn-unused-deps",
+        "-e",
+        with_output_marker!("1 + 1")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("2") => ()
+    )
+    .unwrap();
+    assert!(out
+        .stderr
+        .contains("warning: dependency `boolinator` appears to be unused"));
+
+    let out = cargo_eval!(
+        "-d",
+        "boolinator=2.4.0",
+        "--warn-unused-deps",
+        "-e",
+        with_output_marker!(
+            prelude "use boolinator::Boolinator;";
+            "true.as_some(1)"
+        )
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    )
+    .unwrap();
+    assert!(!out.stderr.contains("appears to be unused"));
+}
+
+#[test]
+fn test_expr_output_file() {
+    let out_dir = tempdir::TempDir::new("cargo-eval-test-output").unwrap();
+    let out_path = out_dir.path().join("out.txt");
+    std::fs::write(&out_path, "old contents that should be truncated\n").unwrap();
+
+    let out = cargo_eval!(
+        "--output",
+        &out_path,
+        "-e",
+        "{ eprintln!(\"to stderr\"); 40 + 2 }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "");
+    assert!(out.stderr.contains("to stderr"));
+    assert_eq!(std::fs::read_to_string(&out_path).unwrap(), "42\n");
+}
+
+#[test]
+fn test_expr_color_output_not_tty() {
+    let out = cargo_eval!("--color-output", "-e", with_output_marker!("(1, \"two\")")).unwrap();
+    assert_eq!(out.stdout_output(), "\n(\n    1,\n    \"two\",\n)\n");
+    assert!(!out.stdout.contains('\x1b'));
+}
+
+#[test]
+fn test_expr_assert() {
+    let out = cargo_eval!("--assert", "-e", "(1..=4).sum::<i32>() == 10").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "");
+
+    let out = cargo_eval!("--assert", "-e", "\"abc\".len() == 4").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("assertion failed: \"abc\".len() == 4"));
+
+    let out = cargo_eval!("--assert", "-e", "42").unwrap();
+    assert!(!out.success());
+    assert!(!out.stderr.contains("assertion failed"));
+}
+
+#[test]
+fn test_expr_unwrap() {
+    let out = cargo_eval!("--unwrap", "-e", "Some(\"hi\")").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"hi\"\n");
+
+    let out = cargo_eval!("--unwrap", "-e", "None::<i32>").unwrap();
+    assert!(!out.success());
+    assert_eq!(out.stdout, "");
+    assert!(out.stderr.contains("expression evaluated to `None`"));
+
+    let out = cargo_eval!("--unwrap", "--radix", "hex", "-e", "\"255\".parse::<u8>()").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "0xff\n");
+
+    let out = cargo_eval!("--unwrap", "-e", "\"nope\".parse::<u8>()").unwrap();
+    assert!(!out.success());
+    assert_eq!(out.stdout, "");
+    assert!(out
+        .stderr
+        .contains("expression evaluated to `Err(ParseIntError"));
+}
+
+#[test]
+fn test_expr_smart_deps() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--smart-deps",
+        "-d",
+        "tokio",
+        "-e",
+        "()"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("[dependencies.tokio]\nfeatures = [\"full\"]\nversion = \"*\"\n"));
+}
+
+#[test]
+fn test_expr_dep_no_default_features() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "-d",
+        "serde=1+-default,derive",
+        "-e",
+        "()"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains(
+        "[dependencies.serde]\ndefault-features = false\nfeatures = [\"derive\"]\nversion = \"1\"\n"
+    ));
+}
+
+#[test]
+fn test_expr_dep_target() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--dep-target",
+        "cfg(windows)=winapi=0.3",
+        "--dep-target",
+        "cfg(target_os = \"linux\")=libc+extra_traits",
+        "-e",
+        "()"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("[target.\"cfg(windows)\".dependencies]\nwinapi = \"0.3\"\n"));
+    assert!(manifest.contains(
+        "[target.\"cfg(target_os = \\\"linux\\\")\".dependencies.libc]\nfeatures = [\"extra_traits\"]\nversion = \"*\"\n"
+    ));
+
+    let out = cargo_eval!("--dep-target", "cfg(windows=winapi", "-e", "()").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("invalid `--dep-target`"));
+}
+
+#[test]
+fn test_expr_manifest_override() {
+    let out = cargo_eval!("-e", with_output_marker!("cfg!(debug_assertions)")).unwrap();
+    scan!(out.stdout_output();
+        ("false") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        "--manifest-override",
+        "[profile.release]\ndebug-assertions = true",
+        "-e",
+        with_output_marker!("cfg!(debug_assertions)")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("--manifest-override", "[profile", "-e", "()").unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("could not parse --manifest-override as TOML"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_expr_preprocessor() {
+    let out = cargo_eval!("--preprocessor", "tr a-z A-Z", "-e", "\"shout\"").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout.trim(), "\"SHOUT\"");
+
+    let out = cargo_eval!("--preprocessor", "false", "-e", "()").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("preprocessor `false` failed"));
+}
+
+#[test]
+fn test_expr_print_deps() {
+    let out = cargo_eval!(
+        "-d",
+        "boolinator=2.4.0",
+        "--print-deps",
+        "-e",
+        with_output_marker!("1 + 1")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("2") => ()
+    )
+    .unwrap();
+    assert!(out.stderr.contains("boolinator v2.4.0"));
+}
+
+#[test]
+fn test_expr_define() {
+    let template_dir = "tests/data/templates";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "-t",
+        "greet",
+        "--define",
+        "greeting=Hello",
+        "-e",
+        "\"world\""
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello, world!") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "-t",
+        "greet",
+        "-e",
+        "\"world\""
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("substitution `greeting` in template is unknown"));
+
+    let out = cargo_eval!("--define", "format={}", "-e", "\"world\"").unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("cannot redefine template substitution `format`"));
+}
+
+#[test]
+fn test_expr_lines() {
+    let out = cargo_eval!("--lines", "-e", "vec![1, 2, 3]").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1\n2\n3\n");
+
+    let out = cargo_eval!("--lines", "--sep", ", ", "-e", "vec![1, 2, 3]").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1, 2, 3\n");
+
+    let out = cargo_eval!("--lines", "--radix", "hex", "-e", "&[10, 11][..]").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "0xa\n0xb\n");
+}
+
+#[test]
+fn test_expr_join() {
+    let out = cargo_eval!("--join", ", ", "-e", "1..=5").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1, 2, 3, 4, 5\n");
+
+    // Items are displayed, not debugged.
+    let out = cargo_eval!("--join", "/", "-e", r#"vec!["a", "b"]"#).unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "a/b\n");
+}
+
+#[test]
+fn test_expr_to_stderr() {
+    let out = cargo_eval!("--to-stderr", "-e", "6 * 7").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "");
+    assert!(out.stderr.ends_with("42\n"));
+
+    // Anything else the expression prints still goes to standard output.
+    let out = cargo_eval!(
+        "--to-stderr",
+        "--lines",
+        "-e",
+        r#"{ println!("hi"); 1..3 }"#
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "hi\n");
+    assert!(out.stderr.ends_with("1\n2\n"));
+}
+
+#[test]
+fn test_expr_smart_display() {
+    let out = cargo_eval!("--smart-display", "-e", r#"format!("{} \"{}\"", 1, 2)"#).unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1 \"2\"\n");
+
+    // Borrowed strings work too.
+    let out = cargo_eval!(
+        #[stdin(b"  padded  \n")]
+        "--smart-display",
+        "-e",
+        "stdin().trim()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "padded\n");
+
+    // Anything else is displayed as usual.
+    let out = cargo_eval!("--smart-display", "-e", r#"(1, "two")"#).unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "(1, \"two\")\n");
+
+    // Without it, strings are quoted.
+    let out = cargo_eval!("-e", r#""quoted""#).unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"quoted\"\n");
+}
+
+#[test]
+fn test_expr_thousands() {
+    let out = cargo_eval!("--thousands", "-e", "1_000 * 1_000").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1,000,000\n");
+
+    let out = cargo_eval!("--thousands", "-e", "(-1234567i64) * 1000").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "-1,234,567,000\n");
+
+    let out = cargo_eval!("--thousands", "-e", "999").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "999\n");
+
+    // Only integers will do.
+    let out = cargo_eval!("--thousands", "-e", "1.5").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_expr_internal_names() {
+    // Names the templates use for themselves shouldn't get in the way.
+    let out = cargo_eval!(
+        "-e",
+        "{ let expr = 2; let closure = |x: i32| x * expr; let output = closure(21); output }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+
+    let out = cargo_eval!(
+        "--lines",
+        "-e",
+        "{ let first = 1; let item = 2; vec![first, item] }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1\n2\n");
+
+    // Nor should they be visible to the expression; `first` used to be the template's own flag.
+    let out = cargo_eval!("--lines", "-e", "vec![first]").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("cannot find value `first`"));
+
+    // The same goes for the names the wrapping options use.
+    let out = cargo_eval!("--time", "-e", "cargo_eval_start").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("cannot find value `cargo_eval_start`"));
+
+    let out = cargo_eval!(
+        "--time",
+        "-e",
+        "{ let start = 1; let value = 2; start + value }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "3\n");
+
+    let out = cargo_eval!(
+        "--time",
+        "-e",
+        "|x: i32| { let arg = 1; x + arg }",
+        "--",
+        "41"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+}
+
+#[test]
+fn test_expr_profile() {
+    let out = cargo_eval!(
+        "--manifest-override",
+        "[profile.dist]\ninherits = \"release\"\ndebug-assertions = true",
+        "--profile",
+        "dist",
+        "-e",
+        with_output_marker!("cfg!(debug_assertions)")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("--profile", "dist", "--debug", "-e", "()").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_expr_lockfile() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--lockfile",
+        "tests/data/lockfile/Cargo.lock",
+        "-d",
+        "boolinator",
+        "-d",
+        "time",
+        "-e",
+        "()"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("boolinator = \"=2.4.0\"\n"));
+    assert!(manifest.contains("time = \"*\"\n"));
+}
+
+#[test]
+fn test_expr_deps_file() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--deps-file",
+        "tests/data/deps.txt",
+        "-e",
+        "()"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("boolinator = \"2.4.0\"\n"));
+    assert!(manifest.contains("time = \"*\"\n"));
+
+    // They're usable, too.
+    let out = cargo_eval!(
+        "--deps-file",
+        "tests/data/deps.txt",
+        "-e",
+        "{ use boolinator::Boolinator; true.as_some(1) }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "Some(1)\n");
+}
+
+#[test]
+fn test_expr_apply_closure() {
+    let out = cargo_eval!("-e", "|x: i32| x * 2", "--", "21").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+
+    let out = cargo_eval!("-e", "|x: i32| x * 2", "--", "21", "nope").unwrap();
+    assert!(!out.success());
+    assert_eq!(out.stdout, "42\n");
+    assert!(out.stderr.contains("could not parse argument `nope`"));
+
+    // Options which wrap the expression still see it's a closure.
+    let out = cargo_eval!("--time", "-e", "|x: i32| x * 2", "--", "21").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+    assert!(out.stderr.contains("evaluated in"));
+
+    let out = cargo_eval!(
+        "--file",
+        "tests/data/file/lines.txt",
+        "-e",
+        "move |n: usize| contents.lines().count() + n",
+        "--",
+        "1"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "4\n");
+
+    let out = cargo_eval!("--state", "s", "-e", "|x: i32| x", "--", "1").unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("`--state` can't be used with a closure"));
+
+    let out = cargo_eval!("-e", "|a: i32, b: i32| a + b", "--", "1", "2").unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("only closures taking a single argument can be applied"));
+}
+
+#[test]
+fn test_expr_inference() {
+    let out = cargo_eval!("-e", "\"42\".parse::<i32>().unwrap()").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+
+    let out = cargo_eval!(
+        "-e",
+        "\"1,2,3\".split(',').map(|s| s.parse::<u8>().unwrap()).collect::<Vec<_>>()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "[1, 2, 3]\n");
+
+    let out = cargo_eval!("-e", "let x: f64 = \"2.5\".parse().unwrap(); x * 2.0").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "5.0\n");
+
+    // The result can borrow from temporaries in the expression.
+    let out = cargo_eval!("-e", "\" abc \".to_uppercase().trim()").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"ABC\"\n");
+}
+
+#[test]
+fn test_expr_env() {
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEST_VALUE = "hello")]
+        "-e",
+        "env(\"CARGO_EVAL_TEST_VALUE\").len()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "5\n");
+
+    let out = cargo_eval!("-e", "env(\"CARGO_EVAL_TEST_UNSET\")").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"\"\n");
+
+    // It doesn't get in the way of `std::env`.
+    let out = cargo_eval!("-e", "use std::env; env::args().count()").unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "1\n");
+}
+
+#[test]
+fn test_expr_file() {
+    let out = cargo_eval!(
+        "--file",
+        "tests/data/file/lines.txt",
+        "-e",
+        "contents.lines().count()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "3\n");
+
+    let out = cargo_eval!(
+        "--file",
+        "tests/data/file/lines.txt",
+        "--file",
+        "tests/data/file/more.txt",
+        "-e",
+        "contents.iter().map(|c| c.lines().count()).collect::<Vec<_>>()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "[3, 1]\n");
+
+    // Feature attributes have to stay ahead of the prelude's items.  `RUSTC_BOOTSTRAP` lets them through on a stable toolchain.
+    let out = cargo_eval!(
+        #[env(RUSTC_BOOTSTRAP = "1")]
+        "-u",
+        "never_type",
+        "--file",
+        "tests/data/file/lines.txt",
+        "-e",
+        "contents.lines().count()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "3\n");
+
+    let out = cargo_eval!(
+        "--file",
+        "tests/data/file/missing.txt",
+        "-e",
+        "contents.len()"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out
+        .stderr
+        .contains("error: could not read file `tests/data/file/missing.txt`"));
+}
+
+#[test]
+fn test_expr_fields() {
+    let out = cargo_eval!(
+        #[stdin("alpha,beta,gamma\n")]
+        "--fields",
+        ",",
+        "-e",
+        "(fields.len(), fields[0].clone(), fields[2].clone())"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "(3, \"alpha\", \"gamma\")\n");
+
+    // The whole of stdin is still there, too.
+    let out = cargo_eval!(
+        #[stdin("1::2::3")]
+        "--fields",
+        "::",
+        "-e",
+        "(fields.iter().map(|f| f.parse::<i32>().unwrap()).sum::<i32>(), stdin())"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "(6, \"1::2::3\")\n");
+}
+
+#[test]
+fn test_expr_stdin_bytes() {
+    let out = cargo_eval!(
+        #[stdin(b"\xff\x00\xfe\n")]
+        "--stdin-bytes",
+        "-e",
+        "(input.len(), input[0])"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "(4, 255)\n");
+}
+
+#[test]
+fn test_expr_state() {
+    let state_dir = tempdir::TempDir::new("cargo-eval-test-state").unwrap();
+    let state_path = state_dir.path().join("count");
+    for expected in &["1", "2"] {
+        let out = cargo_eval!(
+            "--state",
+            &state_path,
+            "-e",
+            "prev.map(|p| p.parse::<i32>().unwrap()).unwrap_or(0) + 1"
+        )
+        .unwrap();
+        assert!(out.success());
+        assert_eq!(out.stdout.trim(), *expected);
+        assert_eq!(std::fs::read_to_string(&state_path).unwrap(), *expected);
+    }
+}
+
+#[cfg(feature = "suppress-cargo-output")]
+#[test]
+fn test_expr_no_color_errors() {
+    // Cargo's output is held back, then shown because the build failed; stderr isn't a terminal, so it shouldn't have any colours left.
+    let out = cargo_eval!(
+        #[env(CARGO_TERM_COLOR = "always")]
+        "-e",
+        "this_is_not_defined"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("this_is_not_defined"));
+    assert!(!out.stderr.contains('\x1b'));
+}
+
+#[test]
+fn test_expr_async_runtime() {
+    let out = cargo_eval!(
+        #[temp_cache]
+        "--async-runtime",
+        "smol",
+        "-e",
+        "async { 6 * 7 }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "42\n");
+
+    let out = cargo_eval!("--async-runtime", "glommio", "-e", "async { 1 }").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_expr_with_time() {
+    let out = cargo_eval!(
+        #[temp_cache]
+        "--with-time",
+        "-e",
+        "(now().year(), now().month(), now().hour())"
+    )
+    .unwrap();
+    assert!(out.success());
+    scan!(out.stdout.trim();
+        ("(", let year: i32, ",", let month: u32, ",", let hour: u32, ")") => {
+            assert!(year >= 2024);
+            assert!((1..=12).contains(&month));
+            assert!(hour < 24);
+        }
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_expr_with_uom() {
+    let out = cargo_eval!(
+        #[temp_cache]
+        "--with-uom",
+        "-e",
+        "(Length::new::<length::kilometer>(3.0) / Time::new::<time::minute>(25.0)).get::<velocity::meter_per_second>()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout.trim(), "2.0");
+}
+
+#[test]
+fn test_expr_json() {
+    let out = cargo_eval!(
+        #[temp_cache]
+        "--json",
+        "-e",
+        "vec![(1, \"one\"), (2, \"two\")]"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(
+        out.stdout,
+        "[\n  [\n    1,\n    \"one\"\n  ],\n  [\n    2,\n    \"two\"\n  ]\n]\n"
+    );
+
+    let out = cargo_eval!(
+        #[temp_cache]
+        "--json",
+        "--smart-deps",
+        "-d",
+        "serde",
+        "-e",
+        "#[derive(serde::Serialize)] struct Point { x: i32, y: i32 } Point { x: 1, y: -2 }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "{\n  \"x\": 1,\n  \"y\": -2\n}\n");
+}
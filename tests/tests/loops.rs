@@ -0,0 +1,112 @@
+#[test]
+fn test_loop() {
+    let out = cargo_eval!(
+        #[stdin("one\ntwo\n")]
+        "--loop",
+        "|line| line.len()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "3\n3\n");
+}
+
+#[test]
+fn test_loop_internal_names() {
+    let out = cargo_eval!(
+        #[stdin("one\ntwo\n")]
+        "--loop",
+        "let mut output = 0; move |line| { let closure = |s: &str| s.len(); output += closure(&line); output }"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "3\n6\n");
+
+    let out = cargo_eval!(
+        #[stdin("one\n")]
+        "--loop",
+        "--count",
+        "let it = \"#\"; move |line, i| format!(\"{}{} {}\", it, i, line)"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"#0 one\"\n");
+
+    // The template's helpers aren't the script's to use.
+    let out = cargo_eval!(
+        #[stdin("one\n")]
+        "--loop",
+        "assert_closure(|line: String| line.len())"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("cannot find function `assert_closure`"));
+}
+
+#[test]
+fn test_loop_input_encoding() {
+    let out = cargo_eval!(
+        #[stdin(b"caf\xe9\r\nna\xefve\n")]
+        "--loop",
+        "--input-encoding",
+        "latin1",
+        "|line| line"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"café\"\n\"naïve\"\n");
+
+    let out = cargo_eval!(
+        #[stdin(b"caf\xe9\n")]
+        "--loop",
+        "--count",
+        "--input-encoding",
+        "latin1",
+        "|line, i| format!(\"{}: {}\", i, line)"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"0: café\"\n");
+}
+
+#[test]
+fn test_loop_to_stderr() {
+    let out = cargo_eval!(
+        #[stdin(b"a\nb\n")]
+        "--to-stderr",
+        "--loop",
+        "|l| l.len()"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "");
+    assert!(out.stderr.ends_with("1\n1\n"));
+}
+
+#[test]
+fn test_loop_parallel() {
+    let input = (0..100).map(|i| format!("{}\n", i)).collect::<String>();
+    let out = cargo_eval!(
+        #[stdin(input.into_bytes())]
+        "--parallel",
+        "4",
+        "--loop",
+        // Make later lines quicker than earlier ones, now and then, so they finish out of order.
+        "|l| { let n: u64 = l.parse().unwrap(); std::thread::sleep(std::time::Duration::from_millis(10 - n % 10)); n * 2 }"
+    )
+    .unwrap();
+    assert!(out.success());
+    let expected = (0..100).map(|i| format!("{}\n", i * 2)).collect::<String>();
+    assert_eq!(out.stdout, expected);
+
+    let out = cargo_eval!(
+        #[stdin(b"a\nb\nc\n")]
+        "--parallel",
+        "4",
+        "--loop",
+        "--count",
+        "|l, i| format!(\"{}: {}\", i, l)"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "\"0: a\"\n\"1: b\"\n\"2: c\"\n");
+}
@@ -0,0 +1,33 @@
+#[test]
+fn test_new() {
+    let dir = tempdir::TempDir::new("cargo-eval-test-new").unwrap();
+    let name = dir.path().join("scaffold");
+    let script = dir.path().join("scaffold.rs");
+
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "new",
+        &name,
+        "--dep",
+        "boolinator=0.1.0"
+    )
+    .unwrap();
+    assert!(out.success());
+    let text = std::fs::read_to_string(&script).unwrap();
+    assert!(text.contains("//! boolinator = \"0.1.0\"\n"));
+
+    let out = cargo_eval!(&script).unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout, "Hello, world!\n");
+
+    // It shouldn't overwrite anything.
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "new",
+        &name
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("already exists"));
+    assert_eq!(std::fs::read_to_string(&script).unwrap(), text);
+}
@@ -0,0 +1,140 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const SCRIPT: &str = "fn main() {\n    println!(\"--output--\");\n    println!(\"fetched\");\n}\n";
+
+/**
+Serves `SCRIPT` over HTTP, with an `ETag`, answering conditional requests for it with `304 Not Modified`.
+
+Returns the URL to fetch it from, and a count of how many times the script itself was sent.
+*/
+fn serve_script() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/scripts/fetched.rs",
+        listener.local_addr().unwrap()
+    );
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    let counter = sent.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => break,
+            };
+
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request).to_lowercase();
+
+            let response = if request.contains("if-none-match: \"v1\"") {
+                "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_owned()
+            } else {
+                counter.fetch_add(1, Ordering::SeqCst);
+                format!(
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    SCRIPT.len(),
+                    SCRIPT
+                )
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    (url, sent)
+}
+
+#[test]
+fn test_remote() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let (url, sent) = serve_script();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--remote",
+        &url
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("--allow-remote"));
+    assert_eq!(sent.load(Ordering::SeqCst), 0);
+
+    for _ in 0..2 {
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--remote",
+            "--allow-remote",
+            &url
+        )
+        .unwrap();
+        scan!(out.stdout_output();
+            ("fetched") => ()
+        )
+        .unwrap();
+    }
+
+    // The second run should have found its cached copy was still current.
+    assert_eq!(sent.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_remote_unreachable() {
+    // Nothing listens on a port we've just let go of.
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let out = cargo_eval!(
+        #[temp_cache]
+        "--remote",
+        "--allow-remote",
+        format!("http://{}/script.rs", addr)
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("error: could not fetch"));
+}
+
+#[test]
+#[cfg(feature = "fetch")]
+fn test_expr_fetch() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let (url, _) = serve_script();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--fetch",
+        &url,
+        "-e",
+        with_output_marker!("body.len()")
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        (let n: usize) => assert_eq!(n, SCRIPT.len())
+    )
+    .unwrap();
+
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--fetch",
+        format!("http://{}/", addr),
+        "-e",
+        with_output_marker!("body.len()")
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("error: could not fetch"));
+}
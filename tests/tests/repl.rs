@@ -0,0 +1,33 @@
+#[test]
+fn test_repl() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        #[stdin("1 + 1\n:dep boolinator=2.4.0\nuse boolinator::Boolinator; true.as_some(3)\n:quit\n")]
+        "repl"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout.lines().collect::<Vec<_>>(), vec!["2", "Some(3)"]);
+
+    // The session's package is cleaned up, but the shared target directory stays.
+    let repl_entries = std::fs::read_dir(cache_path.join("repl"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(repl_entries, vec!["target"]);
+}
+
+#[test]
+fn test_repl_bad_dep() {
+    let out = cargo_eval!(
+        #[temp_cache]
+        #[stdin(":dep =1\n40 + 2\n")]
+        "repl"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stderr.contains("cannot have empty dependency package name"));
+    assert_eq!(out.stdout.trim(), "42");
+}
@@ -64,12 +64,65 @@ fn test_script_short() {
     .unwrap()
 }
 
+#[test]
+fn test_script_bom() {
+    let out = cargo_eval!("tests/data/script-bom.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    )
+    .unwrap()
+}
+
 #[test]
 fn test_script_test() {
     let out = cargo_eval!("--test", "tests/data/script-test.rs").unwrap();
     assert!(out.success());
 }
 
+#[test]
+fn test_script_check() {
+    let out = cargo_eval!("--check", "tests/data/script-check.rs").unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("test test_double ... ok"));
+    assert!(out.stdout.ends_with("double(21) = 42\n"));
+
+    // If the tests fail, the script doesn't get run.
+    let out = cargo_eval!("--check", "tests/data/script-check-fail.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stdout.contains("test test_double ... FAILED"));
+    assert!(!out.stdout.contains("double(21)"));
+}
+
+#[test]
+fn test_script_panic() {
+    let out = cargo_eval!(
+        #[env(RUST_BACKTRACE = "0")]
+        "--panic-location",
+        "tests/data/script-panic.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    // The script's own path, and line number, hashbang and all.
+    let script = std::env::current_dir()
+        .unwrap()
+        .join("tests/data/script-panic.rs");
+    assert!(out
+        .stderr
+        .contains(&format!("script panicked at {}:8:25", script.display())));
+    // The rest is up to the usual hook.
+    assert!(out.stderr.contains("index out of bounds"));
+    assert!(out.stderr.contains("RUST_BACKTRACE=1"));
+
+    let out = cargo_eval!("tests/data/script-panic.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("index out of bounds"));
+    assert!(!out.stderr.contains("script panicked"));
+
+    let out = cargo_eval!("--panic-location", "tests/data/script-async-main.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("exactly one `main`"));
+}
+
 #[test]
 fn test_script_hyphens() {
     use scan_rules::scanner::QuotedString;
@@ -82,6 +135,46 @@ fn test_script_hyphens() {
     .unwrap()
 }
 
+#[test]
+fn test_script_passthrough() {
+    /**
+    Returns the arguments the script saw, without the program name.
+    */
+    fn script_args(out: &crate::util::Output) -> Vec<&str> {
+        out.stdout_output()
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .skip(1)
+            .map(|(_, arg)| arg)
+            .collect()
+    }
+
+    let out = cargo_eval!(
+        "tests/data/script-args.rs",
+        "--",
+        "--debug",
+        "-e",
+        "--help",
+        "--",
+        "batch"
+    )
+    .unwrap();
+    assert_eq!(
+        script_args(&out),
+        vec![
+            r#""--debug""#,
+            r#""-e""#,
+            r#""--help""#,
+            r#""--""#,
+            r#""batch""#
+        ]
+    );
+
+    // Without `--`, things that look like subcommands still go to the script.
+    let out = cargo_eval!("tests/data/script-args.rs", "templates", "list").unwrap();
+    assert_eq!(script_args(&out), vec![r#""templates""#, r#""list""#]);
+}
+
 #[test]
 fn test_script_has_weird_chars() {
     let out = cargo_eval!("tests/data/script-has.weird§chars!.rs").unwrap();
@@ -111,3 +204,979 @@ fn test_script_cs_env() {
     )
     .unwrap()
 }
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_script_sandbox() {
+    let outside = tempdir::TempDir::new("cargo-eval-test-outside").unwrap();
+    let outside_path = outside.path().to_str().unwrap();
+
+    let out = cargo_eval!("tests/data/script-sandbox.rs", outside_path).unwrap();
+    scan!(out.stdout_output();
+        ("inside: ok", "outside: ok") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("--sandbox", "tests/data/script-sandbox.rs", outside_path).unwrap();
+    scan!(out.stdout_output();
+        ("inside: ok", "outside: denied") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_script_working_dir() {
+    let out = cargo_eval!(
+        "--working-dir",
+        "tests/data/working-dir",
+        "tests/data/script-working-dir.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello from the working directory.") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        "--working-dir",
+        "tests/data/does-not-exist",
+        "tests/data/script-working-dir.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("does not exist or is not a directory"));
+}
+
+#[test]
+fn test_script_project_config() {
+    let out = cargo_eval!("tests/data/project/nested/script-project-config.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        "-d",
+        "boolinator=2.4.0",
+        "tests/data/project/nested/script-project-config.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    )
+    .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_script_emit_runner() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-runner").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--emit-runner",
+        "--pkg-path",
+        pkg_path,
+        "--features",
+        "dont-panic",
+        "tests/data/script-features.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let runner_path = pkg_path.join("run.sh");
+    let runner = std::fs::read_to_string(&runner_path).unwrap();
+    assert!(runner.contains("cargo run --manifest-path \"$(dirname \"$0\")/Cargo.toml\""));
+    assert!(runner.contains(" --release"));
+    assert!(runner.contains(" --features dont-panic"));
+    assert!(runner.ends_with(" -- \"$@\"\n"));
+
+    let out = std::process::Command::new(&runner_path)
+        .env_remove("CARGO_TARGET_DIR")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Keep calm and borrow check."));
+}
+
+#[test]
+fn test_script_pkg_name() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--pkg-name",
+        "hello-tool",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    let package = &manifest[manifest.find("[package]").unwrap()..];
+    assert!(package.contains("name = \"hello-tool\""));
+    // The executable keeps the script's name.
+    let bin = &manifest[manifest.find("[[bin]]").unwrap()..];
+    assert!(bin.contains("name = \"script-no-deps\""));
+
+    let out = cargo_eval!("--pkg-name", "hello-tool", "tests/data/script-no-deps.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+
+    for name in &["1tool", "hello tool", "fn"] {
+        let out = cargo_eval!("--pkg-name", name, "tests/data/script-no-deps.rs").unwrap();
+        assert!(!out.success());
+        assert!(out.stderr.contains("is not a valid package name"));
+    }
+}
+
+#[test]
+fn test_script_build_script() {
+    let out = cargo_eval!("tests/data/script-build-script.rs").unwrap();
+    scan!(out.stdout_output();
+        ("false") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        "--build-script",
+        "tests/data/build-script/build.rs",
+        "tests/data/script-build-script.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_script_build_script_cached() {
+    // Both runs use the same package in the cache, so the build script from the first mustn't be picked up by the second.
+    let cache_dir = crate::util::temp_cache_dir();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--build-script",
+        "tests/data/build-script/build.rs",
+        "tests/data/script-build-script.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "tests/data/script-build-script.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("false") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_script_named_build() {
+    let out = cargo_eval!("tests/data/named-build/build.rs").unwrap();
+    scan!(out.stdout_output();
+        ("not a build script") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_script_max_compile_time() {
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+    let start = std::time::Instant::now();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--max-compile-time",
+        "2",
+        "--build-script",
+        "tests/data/build-script/slow.rs",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(60));
+    assert_eq!(out.status.code(), Some(124));
+    assert!(out
+        .stderr
+        .contains("gave up on building the script after 2s"));
+
+    // The half-built package shouldn't be left lying around.
+    let scripts = std::fs::read_dir(cache_path.join("scripts"))
+        .map(|entries| entries.count())
+        .unwrap_or(0);
+    assert_eq!(scripts, 0);
+
+    // Too long to be a `Duration`.
+    let out = cargo_eval!("--max-compile-time", "1e30", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("not a valid number of seconds"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_script_max_compile_time_interrupted() {
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    // Lists the command lines of everything running out of `cache_path`.
+    fn running_in(cache_path: &std::path::Path) -> Vec<String> {
+        let cache_path = cache_path.to_string_lossy().into_owned();
+        std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| std::fs::read(entry.ok()?.path().join("cmdline")).ok())
+            .map(|cmdline| String::from_utf8_lossy(&cmdline).replace('\0', " "))
+            .filter(|cmdline| cmdline.contains(&cache_path))
+            .collect()
+    }
+
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+    let cargo_lock = crate::util::CARGO_MUTEX.lock().unwrap();
+    let target_dir = std::env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| String::from("target"));
+    let mut child = Command::new(format!("{}/debug/cargo-eval", target_dir))
+        .args([
+            "eval",
+            "--max-compile-time",
+            "60",
+            "--build-script",
+            "tests/data/build-script/slow.rs",
+            "tests/data/script-no-deps.rs",
+        ])
+        .env_remove("CARGO_TARGET_DIR")
+        .env("CARGO_EVAL_CACHE_DIR", cache_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Wait for the build script to get going, then hit Ctrl-C.
+    let start = Instant::now();
+    while !running_in(cache_path)
+        .iter()
+        .any(|cmdline| cmdline.contains("build-script-"))
+    {
+        assert!(
+            start.elapsed() < Duration::from_secs(50),
+            "build script never started"
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let status = child.wait().unwrap();
+    drop(cargo_lock);
+
+    assert!(!status.success());
+    assert_eq!(running_in(cache_path), Vec::<String>::new());
+}
+
+#[test]
+fn test_script_rust_version() {
+    let out = cargo_eval!("tests/data/script-rust-version.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("this script needs Rust 99.0 or newer"));
+    assert!(!out.stderr.contains("Compiling"));
+
+    let out = cargo_eval!("--rust-version", "99", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("this script needs Rust 99 or newer"));
+
+    let out = cargo_eval!("--rust-version", "1.56", "tests/data/script-no-deps.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--rust-version",
+        "1.56",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("rust-version = \"1.56\""));
+}
+
+#[test]
+fn test_script_strip() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--strip",
+        "symbols",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("[profile.release]\nstrip = \"symbols\"\n"));
+
+    let out = cargo_eval!(
+        "--strip",
+        "symbols",
+        "--debug",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_script_panic_abort() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--panic",
+        "abort",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("[profile.release]\npanic = \"abort\"\n"));
+
+    let out = cargo_eval!("--panic", "abort", "--test", "tests/data/script-test.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("cannot be used with"));
+}
+
+#[test]
+fn test_script_codegen_units() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--gen-pkg-only",
+        "--pkg-path",
+        pkg_path,
+        "--codegen-units",
+        "1",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("[profile.release]\ncodegen-units = 1\n"));
+
+    let out = cargo_eval!("--codegen-units", "0", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_script_clean_env() {
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEST_INHERITED = "inherited")]
+        "--env",
+        "CARGO_EVAL_TEST_GIVEN=given",
+        "tests/data/script-env.rs"
+    )
+    .unwrap();
+    assert_eq!(
+        out.stdout_output(),
+        "\nSome(\"inherited\")\nSome(\"given\")\n"
+    );
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEST_INHERITED = "inherited")]
+        "--clean-env",
+        "--env",
+        "CARGO_EVAL_TEST_GIVEN=given",
+        "tests/data/script-env.rs"
+    )
+    .unwrap();
+    assert_eq!(out.stdout_output(), "\nNone\nSome(\"given\")\n");
+}
+
+#[test]
+fn test_script_dotenv() {
+    let out = cargo_eval!("--dotenv", "tests/data/dotenv/script-dotenv.rs").unwrap();
+    assert_eq!(out.stdout_output(), "\nhello from dotenv\n");
+    assert!(out.stderr.contains("/.env:3: ignoring malformed line"));
+
+    let out = cargo_eval!(
+        "--dotenv=tests/data/dotenv/other.env",
+        "tests/data/dotenv/script-dotenv.rs"
+    )
+    .unwrap();
+    assert_eq!(out.stdout_output(), "\nfrom another file\n");
+
+    let out = cargo_eval!(
+        "--dotenv",
+        "--env",
+        "DOTENV_GREETING=given",
+        "tests/data/dotenv/script-dotenv.rs"
+    )
+    .unwrap();
+    assert_eq!(out.stdout_output(), "\ngiven\n");
+
+    let out = cargo_eval!("tests/data/dotenv/script-dotenv.rs").unwrap();
+    assert_eq!(out.stdout_output(), "\nunset\n");
+
+    let out = cargo_eval!(
+        "--dotenv=tests/data/dotenv/missing.env",
+        "tests/data/dotenv/script-dotenv.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_script_include() {
+    let out = cargo_eval!(
+        "--include",
+        "tests/data/include/helper.rs",
+        "tests/data/script-include.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello, include!") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("tests/data/script-include.rs").unwrap();
+    assert!(!out.success());
+
+    let out = cargo_eval!(
+        "--include",
+        "tests/data/include/build.rs",
+        "tests/data/script-include.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("as a build script"));
+}
+
+#[test]
+fn test_script_include_cached() {
+    // Both runs use the same package in the cache, so the second mustn't find the file included by the first.
+    let cache_dir = crate::util::temp_cache_dir();
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "--include",
+        "tests/data/include/helper.rs",
+        "tests/data/script-include.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello, include!") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[temp_cache(cache_dir)]
+        "tests/data/script-include.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_script_no_run() {
+    let out = cargo_eval!("--no-run", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(!out.stdout.contains("--output--"));
+
+    let out = cargo_eval!("-r", "tests/data/script-no-deps.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!("--release", "--debug", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_script_stdin() {
+    let out = cargo_eval!(
+        #[stdin("hello\n")]
+        "tests/data/script-stdin.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("got \"hello\"") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        #[stdin("hello\n")]
+        "--no-stdin",
+        "tests/data/script-stdin.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("EOF") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_script_emit_binary() {
+    let out_dir = tempdir::TempDir::new("cargo-eval-test-binary").unwrap();
+    let exe_path = out_dir.path().join("script-no-deps.bin");
+    let out = cargo_eval!(
+        "--build-only",
+        "--emit-binary",
+        &exe_path,
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let bytes = std::fs::read(&exe_path).unwrap();
+    let magic: &[&[u8]] = if cfg!(windows) {
+        &[b"MZ"]
+    } else if cfg!(target_os = "macos") {
+        &[b"\xcf\xfa\xed\xfe", b"\xca\xfe\xba\xbe"]
+    } else {
+        &[b"\x7fELF"]
+    };
+    assert!(magic.iter().any(|m| bytes.starts_with(m)));
+}
+
+#[test]
+fn test_script_reproducible() {
+    let out_dir = tempdir::TempDir::new("cargo-eval-test-binary").unwrap();
+    let mut binaries = vec![];
+    for i in 0..2 {
+        // A different cache each time, so the package and target directories are somewhere else.
+        let cache_dir = crate::util::temp_cache_dir();
+        let cache_path = cache_dir.path();
+        let exe_path = out_dir.path().join(format!("script-no-deps-{}.bin", i));
+        let out = cargo_eval!(
+            #[temp_cache(cache_dir)]
+            "--build-only",
+            "--reproducible",
+            "--emit-binary",
+            &exe_path,
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        assert!(out.success());
+
+        let bytes = std::fs::read(&exe_path).unwrap();
+        let cache_str = cache_path.to_string_lossy();
+        assert!(!bytes
+            .windows(cache_str.len())
+            .any(|w| w == cache_str.as_bytes()));
+        binaries.push(bytes);
+    }
+
+    // Linkers don't promise this everywhere.
+    if cfg!(target_os = "linux") {
+        assert!(binaries[0] == binaries[1]);
+    }
+}
+
+#[test]
+fn test_script_sanitizer() {
+    // Cargo puts its own toolchain first on `PATH` when it runs the tests, so pick one by putting it in front of that.
+    let toolchain_path = |toolchain: &str| {
+        let out = std::process::Command::new("rustc")
+            .arg(format!("+{}", toolchain))
+            .args(["--print", "sysroot"])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())?;
+        let bin =
+            std::path::Path::new(std::str::from_utf8(&out.stdout).unwrap().trim()).join("bin");
+        let path = std::env::var_os("PATH").unwrap();
+        Some(
+            std::env::join_paths(std::iter::once(bin).chain(std::env::split_paths(&path))).unwrap(),
+        )
+    };
+
+    if let Some(path) = toolchain_path("stable") {
+        let out = cargo_eval!(
+            #[env(PATH=path, RUSTUP_TOOLCHAIN="stable")]
+            "--sanitizer",
+            "address",
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        assert!(!out.success());
+        assert!(out.stderr.contains("needs a nightly toolchain"));
+    }
+
+    // Only worth trying for real if there's a nightly toolchain to use.
+    if let Some(path) = toolchain_path("nightly").filter(|_| cfg!(target_os = "linux")) {
+        let out = cargo_eval!(
+            #[env(PATH=path, RUSTUP_TOOLCHAIN="nightly")]
+            "--sanitizer",
+            "address",
+            "tests/data/script-no-deps.rs"
+        )
+        .unwrap();
+        scan!(out.stdout_output();
+            ("Hello, World!") => ()
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_script_rustc_wrapper() {
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--print-rustc-wrapper",
+        "--rustc-wrapper",
+        "tests/data/rustc-wrapper.sh"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.trim().ends_with("/tests/data/rustc-wrapper.sh"));
+
+    // A fresh cache, so the script has to be compiled.
+    let cache_dir = crate::util::temp_cache_dir();
+    let cache_path = cache_dir.path();
+    let log_path = cache_path.join("wrapper.log");
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEST_WRAPPER_LOG=log_path)]
+        #[temp_cache(cache_dir)]
+        "--rustc-wrapper",
+        "tests/data/rustc-wrapper.sh",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    assert!(log.contains("script_no_deps"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_script_after_build() {
+    let out_dir = tempdir::TempDir::new("cargo-eval-test-binary").unwrap();
+    let copy_path = out_dir.path().join("copy.bin");
+    let out = cargo_eval!(
+        "--build-only",
+        "--after-build",
+        format!("cp \"$CARGO_EVAL_BIN\" '{}'", copy_path.display()),
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let out = std::process::Command::new(&copy_path).output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "--output--\nHello, World!\n"
+    );
+
+    // If the command fails, the script doesn't get to run.
+    let out = cargo_eval!("--after-build", "false", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("after-build command `false` failed"));
+    assert!(!out.stdout.contains("Hello"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_script_retry() {
+    let path = std::env::join_paths(
+        std::iter::once(
+            std::env::current_dir()
+                .unwrap()
+                .join("tests/data/flaky-cargo"),
+        )
+        .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let real_cargo = env!("CARGO");
+
+    // The first fetch fails because of the network, so it's tried again.
+    let marker_dir = tempdir::TempDir::new("cargo-eval-test-marker").unwrap();
+    let marker_path = marker_dir.path().join("network");
+    let out = cargo_eval!(
+        #[env(
+            PATH=path,
+            CARGO_EVAL_TEST_REAL_CARGO=real_cargo,
+            CARGO_EVAL_TEST_FETCH_MARKER=marker_path,
+            CARGO_EVAL_TEST_FETCH_ERROR="error: failed to download from `https://static.crates.io/`\n  [6] Couldn't resolve host name",
+        )]
+        "--retry",
+        "2",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+    assert!(out.stderr.contains("trying again"));
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+
+    // Anything else isn't.
+    let marker_path = marker_dir.path().join("other");
+    let out = cargo_eval!(
+        #[env(
+            PATH=path,
+            CARGO_EVAL_TEST_REAL_CARGO=real_cargo,
+            CARGO_EVAL_TEST_FETCH_MARKER=marker_path,
+            CARGO_EVAL_TEST_FETCH_ERROR="error: no matching package named `nope` found",
+        )]
+        "--retry",
+        "2",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(!out.stderr.contains("trying again"));
+    assert!(out.stderr.contains("no matching package named `nope`"));
+
+    // If the network never comes back, the last failure is reported.  The marker can't be made in a directory that isn't there, so every fetch fails.
+    let marker_path = marker_dir.path().join("missing/network");
+    let out = cargo_eval!(
+        #[env(
+            PATH=path,
+            CARGO_EVAL_TEST_REAL_CARGO=real_cargo,
+            CARGO_EVAL_TEST_FETCH_MARKER=marker_path,
+            CARGO_EVAL_TEST_FETCH_ERROR="error: failed to download from `https://static.crates.io/`\n  [6] Couldn't resolve host name",
+        )]
+        "--retry",
+        "1",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert_eq!(out.stderr.matches("trying again").count(), 1);
+    assert!(out.stderr.contains("Couldn't resolve host name"));
+}
+
+#[test]
+fn test_script_emit_asm() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--pkg-path",
+        pkg_path,
+        "--emit",
+        "asm",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    // It's only built, not run.
+    assert!(!out.stdout.contains("--output--"));
+    let asm_path = std::path::Path::new(out.stdout.trim());
+    assert_eq!(asm_path, pkg_path.join("script-no-deps.s"));
+    let asm = std::fs::read_to_string(asm_path).unwrap();
+    assert!(asm.contains("script_no_deps"));
+}
+
+#[test]
+fn test_script_crate_type_cdylib() {
+    let pkg_dir = tempdir::TempDir::new("cargo-eval-test-pkg").unwrap();
+    let pkg_path = pkg_dir.path();
+    let out = cargo_eval!(
+        #[no_pkg_path]
+        "--pkg-path",
+        pkg_path,
+        "--crate-type",
+        "cdylib",
+        "tests/data/script-cdylib.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let lib_path = std::path::Path::new(out.stdout.trim());
+    assert!(lib_path.starts_with(pkg_path));
+    assert!(lib_path
+        .to_string_lossy()
+        .ends_with(std::env::consts::DLL_SUFFIX));
+    assert!(lib_path.is_file());
+
+    let manifest = std::fs::read_to_string(pkg_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("crate-type = [\"cdylib\"]"));
+}
+
+#[test]
+fn test_script_print_binary_size() {
+    let out_dir = tempdir::TempDir::new("cargo-eval-test-binary").unwrap();
+    let exe_path = out_dir.path().join("script-no-deps.bin");
+    let out = cargo_eval!(
+        "--build-only",
+        "--print-binary-size",
+        "--emit-binary",
+        &exe_path,
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let size = std::fs::metadata(&exe_path).unwrap().len();
+    assert!(size > 0);
+    assert!(out
+        .stderr
+        .contains(&format!("binary size: {} bytes (", size)));
+}
+
+#[test]
+fn test_script_verbose() {
+    let out = cargo_eval!("-vv", "tests/data/script-no-deps.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+    assert!(out.stderr.contains("Running `"));
+    assert!(out.stderr.contains("rustc"));
+}
+
+#[test]
+fn test_script_raw() {
+    let template_dir = "tests/data/templates/wrap";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "tests/data/script-raw.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("use `--raw`"));
+
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_TEMPLATE_DIR=template_dir)]
+        "--raw",
+        "tests/data/script-raw.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("raw") => ()
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_script_verify_hash() {
+    let out = cargo_eval!(
+        "--verify-hash",
+        "0551CA43854D5FA9FF0014409AC303CA6DD062AA4F21D75C19F32C123E63EDDB",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    )
+    .unwrap();
+
+    let out = cargo_eval!(
+        "--verify-hash",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("refusing to run it"));
+    assert!(!out.stdout.contains("--output--"));
+}
+
+#[test]
+fn test_script_emit_tarball() {
+    let out_dir = tempdir::TempDir::new("cargo-eval-test-tarball").unwrap();
+    let tarball_path = out_dir.path().join("script.tar.gz");
+    let out = cargo_eval!(
+        "--gen-pkg-only",
+        "--emit-tarball",
+        &tarball_path,
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let file = std::fs::File::open(&tarball_path).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let paths = archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            std::path::Path::new("script-no-deps/Cargo.toml"),
+            std::path::Path::new("script-no-deps/script-no-deps.rs"),
+        ]
+    );
+}
+
+#[test]
+fn test_script_print_features() {
+    let out = cargo_eval!("--print-features", "tests/data/script-optional-dep.rs").unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    )
+    .unwrap();
+    assert!(out
+        .stderr
+        .contains("script-optional-dep 0.1.0: boolinator, default\n"));
+    assert!(out.stderr.contains("boolinator 2.4.0: (none)\n"));
+}
+
+#[test]
+fn test_script_empty() {
+    for script in &["tests/data/script-empty.rs", "tests/data/script-blank.rs"] {
+        let out = cargo_eval!(script).unwrap();
+        assert_eq!(out.status.code(), Some(65));
+        assert!(out
+            .stderr
+            .contains(&format!("error: script `{}` is empty", script)));
+        assert!(!out.stderr.contains("main"));
+    }
+}
+
+#[test]
+fn test_script_cargo_not_found() {
+    let empty_dir = tempdir::TempDir::new("cargo-eval-test-path").unwrap();
+    let empty_path = empty_dir.path();
+    let out = cargo_eval!(
+        #[env(PATH = empty_path)]
+        "tests/data/script-no-deps.rs"
+    )
+    .unwrap();
+    assert_eq!(out.status.code(), Some(127));
+    assert!(out
+        .stderr
+        .contains("error: could not find `cargo`; install Rust"));
+    assert!(out
+        .stderr
+        .contains(&format!("(searched `{}`)", empty_path.display())));
+}
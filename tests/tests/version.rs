@@ -7,3 +7,31 @@ fn test_version() {
     )
     .unwrap();
 }
+
+#[test]
+fn test_version_verbose() {
+    let cache_dir = "tests/data/cache-dir";
+    let out = cargo_eval!(
+        #[env(CARGO_EVAL_CACHE_DIR=cache_dir)]
+        #[no_pkg_path]
+        "version",
+        "--verbose"
+    )
+    .unwrap();
+    assert!(out.success());
+
+    let mut lines = out.stdout.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        format!(
+            "cargo-eval {}",
+            ::std::env::var("CARGO_PKG_VERSION").unwrap()
+        )
+    );
+    let rest = lines.collect::<Vec<_>>();
+    assert!(rest.iter().any(|l| l.starts_with("rustc: rustc ")));
+    assert!(rest.iter().any(|l| l.starts_with("target: ")));
+    assert!(rest.iter().any(|l| l.starts_with("features: ")));
+    assert!(rest.contains(&"cache-dir: tests/data/cache-dir"));
+    assert!(rest.iter().any(|l| l.starts_with("data-dir: ")));
+}
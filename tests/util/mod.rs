@@ -1,13 +1,65 @@
 use std::sync::Mutex;
 
+/**
+Runs `cargo eval` with the given arguments.
+
+The invocation can be adjusted with the following attributes, given before the arguments in any order:
+
+- `#[env(NAME=value, ...)]`: sets environment variables.
+- `#[no_pkg_path]`: doesn't pass `--pkg-path`, so the cache is used.
+- `#[stdin(bytes)]`: feeds `bytes` to standard input.
+- `#[temp_cache]`: uses a fresh cache directory for this run alone, and doesn't pass `--pkg-path`.
+- `#[temp_cache(dir)]`: uses `dir` (see `temp_cache_dir`) as the cache directory, for tests that run more than once against the same cache or look inside it, and doesn't pass `--pkg-path`.
+*/
 macro_rules! cargo_eval {
-    (
-        #[env($($env_k:ident=$env_v:expr),* $(,)*)]
-        $($args:expr),* $(,)*
-    ) => {
+    (@attr ($env:ident, $pkg_path:ident, $stdin:ident, $cache:ident); env($($env_k:ident=$env_v:expr),* $(,)*)) => {
+        $(
+            $env.push((stringify!($env_k), ::std::ffi::OsStr::new(&$env_v).to_owned()));
+        )*
+    };
+
+    (@attr ($env:ident, $pkg_path:ident, $stdin:ident, $cache:ident); no_pkg_path) => {
+        $pkg_path = false;
+    };
+
+    (@attr ($env:ident, $pkg_path:ident, $stdin:ident, $cache:ident); stdin($input:expr)) => {
+        $stdin = Some(AsRef::<[u8]>::as_ref(&$input).to_vec());
+    };
+
+    (@attr ($env:ident, $pkg_path:ident, $stdin:ident, $cache:ident); temp_cache) => {
+        $cache = Some(crate::util::temp_cache_dir());
+        cargo_eval!(@attr ($env, $pkg_path, $stdin, $cache); temp_cache($cache.as_ref().unwrap()));
+    };
+
+    (@attr ($env:ident, $pkg_path:ident, $stdin:ident, $cache:ident); temp_cache($dir:expr)) => {
+        $env.push((
+            "CARGO_EVAL_CACHE_DIR",
+            AsRef::<::std::path::Path>::as_ref(&$dir).as_os_str().to_owned(),
+        ));
+        $pkg_path = false;
+    };
+
+    (@parse [$($attrs:tt)*] #[$attr:ident $(($($attr_args:tt)*))*] $($rest:tt)*) => {
+        cargo_eval!(@parse [$($attrs)* ($attr $(($($attr_args)*))*)] $($rest)*)
+    };
+
+    (@parse [$(($attr:ident $(($($attr_args:tt)*))*))*] $($args:expr),* $(,)*) => {
         {
             extern crate tempdir;
-            use std::process::Command;
+            use std::io::Write;
+            use std::process::{Command, Stdio};
+
+            #[allow(unused_mut, unused_assignments)]
+            let mut env: Vec<(&str, ::std::ffi::OsString)> = vec![];
+            #[allow(unused_mut, unused_assignments)]
+            let mut pkg_path = true;
+            #[allow(unused_mut, unused_assignments)]
+            let mut stdin: Option<Vec<u8>> = None;
+            #[allow(unused_mut, unused_assignments)]
+            let mut cache_dir: Option<tempdir::TempDir> = None;
+            $(
+                cargo_eval!(@attr (env, pkg_path, stdin, cache_dir); $attr $(($($attr_args)*))*);
+            )*
 
             let cargo_lock = crate::util::CARGO_MUTEX.lock().expect("could not acquire Cargo mutext");
 
@@ -18,18 +70,39 @@ macro_rules! cargo_eval {
                     .unwrap_or_else(|_| String::from("target"));
                 let mut cmd = Command::new(format!("{}/debug/cargo-eval", target_dir));
                 cmd.arg("eval");
-                cmd.arg("--pkg-path").arg(temp_dir.path());
+                if pkg_path {
+                    cmd.arg("--pkg-path").arg(temp_dir.path());
+                }
                 $(
                     cmd.arg($args);
                 )*
 
                 cmd.env_remove("CARGO_TARGET_DIR");
-                $(cmd.env(stringify!($env_k), $env_v);)*
+                for (k, v) in env {
+                    cmd.env(k, v);
+                }
 
                 cmd_str = format!("{:?}", cmd);
 
-                cmd.output()
-                    .map(crate::util::Output::from)
+                match stdin {
+                    None => cmd.output(),
+                    Some(input) => {
+                        cmd.stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped());
+                        cmd.spawn().and_then(|mut child| {
+                            let mut child_stdin = child.stdin.take().unwrap();
+                            let writer = ::std::thread::spawn(move || {
+                                // The child might not read all of it; that's fine.
+                                let _ = child_stdin.write_all(&input);
+                            });
+                            let out = child.wait_with_output();
+                            writer.join().unwrap();
+                            out
+                        })
+                    }
+                }
+                .map(crate::util::Output::from)
             };
 
             if let Ok(out) = out.as_ref() {
@@ -45,14 +118,15 @@ macro_rules! cargo_eval {
             }
 
             drop(temp_dir);
+            drop(cache_dir);
             drop(cargo_lock);
 
             out
         }
     };
 
-    ($($args:expr),* $(,)*) => {
-        cargo_eval!(#[env()] $($args),*)
+    ($($tt:tt)*) => {
+        cargo_eval!(@parse [] $($tt)*)
     };
 }
 
@@ -66,6 +140,13 @@ macro_rules! with_output_marker {
     };
 }
 
+/**
+Makes an empty cache directory, removed when it's dropped.  Pass it to `cargo_eval!` with `#[temp_cache(dir)]`.
+*/
+pub fn temp_cache_dir() -> tempdir::TempDir {
+    tempdir::TempDir::new("cargo-eval-test-cache").unwrap()
+}
+
 lazy_static! {
     #[doc(hidden)]
     pub static ref CARGO_MUTEX: Mutex<()> = Mutex::new(());